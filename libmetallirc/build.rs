@@ -0,0 +1,22 @@
+//! Computes the build identity of libmetallirc, checked by the server when loading
+//! a module: modules must be built by the same compiler against the same library.
+
+use std::io::{Command, File};
+use std::os;
+
+fn main() {
+    let rustc = match Command::new("rustc").arg("--version").output() {
+        Ok(out) => String::from_utf8_lossy(out.output.as_slice()).into_string(),
+        Err(e) => panic!("Unable to run rustc: {}", e)
+    };
+    let version = os::getenv("CARGO_PKG_VERSION").unwrap_or(String::new());
+    // FNV-1a, as the result must not depend on the compiler
+    let mut hash = 0xcbf29ce484222325u64;
+    for b in rustc.as_slice().trim().bytes().chain(version.as_slice().bytes()) {
+        hash = (hash ^ b as u64) * 0x100000001b3;
+    }
+    let out = Path::new(os::getenv("OUT_DIR").expect("OUT_DIR is not set")).join("build_id.rs");
+    let mut file = File::create(&out).unwrap();
+    file.write_str(format!("/// Hash of the versions of rustc and libmetallirc.\n\
+                            pub const BUILD_ID: u64 = {};\n", hash).as_slice()).unwrap();
+}
@@ -0,0 +1,316 @@
+//! Server-wide bans.
+
+//! Three kinds of bans are handled:
+//!
+//! - K-lines, banning a `user@host` mask
+//! - D-lines, banning an IP address or range (in CIDR notation)
+//! - Q-lines, reserving nicknames matching a mask
+//!
+//! Bans can be temporary, and are stored in a file to be kept across restarts.
+
+#![experimental]
+
+use std::io::{File, BufferedReader, IoResult, Truncate, Write};
+use std::io::net::ip::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use util;
+
+/// The different kinds of bans.
+#[experimental]
+#[deriving(PartialEq, Clone, Show)]
+pub enum BanKind {
+    /// Ban of a `user@host` mask.
+    KLine,
+    /// Ban of an IP address or range.
+    DLine,
+    /// Reservation of a nickname mask.
+    QLine
+}
+
+impl BanKind {
+    /// Human-readable name of this kind of ban.
+    #[experimental]
+    pub fn name(&self) -> &'static str {
+        match *self {
+            KLine => "K-line",
+            DLine => "D-line",
+            QLine => "Q-line"
+        }
+    }
+
+    /// Letter representing this kind in the ban file.
+    fn letter(&self) -> char {
+        match *self {
+            KLine => 'K',
+            DLine => 'D',
+            QLine => 'Q'
+        }
+    }
+
+    fn from_letter(c: char) -> Option<BanKind> {
+        match c {
+            'K' => Some(KLine),
+            'D' => Some(DLine),
+            'Q' => Some(QLine),
+            _ => None
+        }
+    }
+}
+
+/// A server-wide ban.
+#[experimental]
+#[deriving(Clone)]
+pub struct Ban {
+    pub kind: BanKind,
+    pub mask: String,
+    pub reason: String,
+    /// Who set the ban.
+    pub setter: String,
+    /// Timestamp of the ban creation.
+    pub set_at: i64,
+    /// Timestamp of the ban expiration, if temporary.
+    pub expires: Option<i64>
+}
+
+#[experimental]
+impl Ban {
+
+    /// Is this ban expired at given time ?
+    #[experimental]
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires.map_or(false, |t| t <= now)
+    }
+
+    /// Does this ban match given label ? The label is the `user@host` for a K-line,
+    /// the IP for a D-line and the nickname for a Q-line.
+    #[experimental]
+    pub fn matches(&self, label: &str) -> bool {
+        match self.kind {
+            KLine | QLine => util::matches_mask(label, self.mask.as_slice()),
+            DLine => match from_str::<IpAddr>(label) {
+                Some(ip) => cidr_matches(&ip, self.mask.as_slice()),
+                None => false
+            }
+        }
+    }
+
+    /// Serializes the ban as a line of the ban file.
+    fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\t{}\t{}\t{}",
+            self.kind.letter(),
+            self.mask,
+            self.set_at,
+            self.expires.unwrap_or(0),
+            self.setter,
+            self.reason
+        )
+    }
+
+    /// Parses a line of the ban file.
+    fn from_line(line: &str) -> Option<Ban> {
+        let fields: Vec<&str> = line.splitn(5, '\t').collect();
+        if fields.len() != 6 { return None; }
+        let kind = match fields[0].chars().next().and_then(BanKind::from_letter) {
+            Some(k) => k,
+            None => return None
+        };
+        let set_at = match from_str::<i64>(fields[2]) { Some(t) => t, None => return None };
+        let expires = match from_str::<i64>(fields[3]) {
+            Some(0) => None,
+            Some(t) => Some(t),
+            None => return None
+        };
+        Some(Ban {
+            kind: kind,
+            mask: fields[1].to_string(),
+            reason: fields[5].to_string(),
+            setter: fields[4].to_string(),
+            set_at: set_at,
+            expires: expires
+        })
+    }
+}
+
+/// Container of all server-wide bans.
+#[experimental]
+pub struct BanManager {
+    bans: Vec<Ban>,
+    file: Path
+}
+
+#[experimental]
+impl BanManager {
+
+    /// Creates an empty ban manager, storing its bans in given file.
+    #[experimental]
+    pub fn new(file: Path) -> BanManager {
+        BanManager {
+            bans: Vec::new(),
+            file: file
+        }
+    }
+
+    /// Loads the bans from the ban file. A missing file is not an error.
+    /// Returns the number of loaded bans.
+    #[experimental]
+    pub fn load(&mut self) -> Result<uint, String> {
+        if !self.file.exists() { return Ok(0); }
+        let mut reader = match File::open(&self.file) {
+            Ok(f) => BufferedReader::new(f),
+            Err(e) => return Err(format!("Unable to open ban file {} : {}", self.file.display(), e))
+        };
+        for (i, line) in reader.lines().enumerate() {
+            match line {
+                Ok(l) => match Ban::from_line(l.as_slice().trim_right_chars('\n')) {
+                    Some(ban) => self.bans.push(ban),
+                    None => return Err(
+                        format!("Invalid line {} in ban file {}.", i + 1, self.file.display())
+                    )
+                },
+                Err(e) => return Err(format!("Unable to read ban file {} : {}", self.file.display(), e))
+            }
+        }
+        Ok(self.bans.len())
+    }
+
+    /// Writes all the bans to the ban file.
+    #[experimental]
+    pub fn save(&self) -> IoResult<()> {
+        let mut file = try!(File::open_mode(&self.file, Truncate, Write));
+        for ban in self.bans.iter() {
+            try!(file.write_line(ban.to_line().as_slice()));
+        }
+        file.datasync()
+    }
+
+    /// Adds a ban, replacing any existing ban of the same kind on the same mask.
+    #[experimental]
+    pub fn add(&mut self, ban: Ban) {
+        self.remove(ban.kind, ban.mask.as_slice());
+        self.bans.push(ban);
+    }
+
+    /// Removes the ban of given kind on given mask. Returns false if there was none.
+    #[experimental]
+    pub fn remove(&mut self, kind: BanKind, mask: &str) -> bool {
+        let before = self.bans.len();
+        self.bans.retain(|b| b.kind != kind || b.mask.as_slice() != mask);
+        self.bans.len() != before
+    }
+
+    /// Removes all bans expired at given time and returns them.
+    #[experimental]
+    pub fn expire(&mut self, now: i64) -> Vec<Ban> {
+        let (expired, kept) = ::std::mem::replace(&mut self.bans, Vec::new())
+                                .partition(|b| b.is_expired(now));
+        self.bans = kept;
+        expired
+    }
+
+    /// Finds a ban of given kind matching given label and still active at given time.
+    #[experimental]
+    pub fn find(&self, kind: BanKind, label: &str, now: i64) -> Option<&Ban> {
+        self.bans.iter().find(|b| b.kind == kind && !b.is_expired(now) && b.matches(label))
+    }
+
+    /// Lists all active bans of given kind.
+    #[experimental]
+    pub fn list(&self, kind: BanKind, now: i64) -> Vec<&Ban> {
+        self.bans.iter().filter(|b| b.kind == kind && !b.is_expired(now)).collect()
+    }
+
+}
+
+/// Returns the bytes of an IP address.
+fn ip_bytes(ip: &IpAddr) -> Vec<u8> {
+    match *ip {
+        Ipv4Addr(a, b, c, d) => vec!(a, b, c, d),
+        Ipv6Addr(a, b, c, d, e, f, g, h) => {
+            let mut v = Vec::with_capacity(16);
+            for w in [a, b, c, d, e, f, g, h].iter() {
+                v.push((*w >> 8) as u8);
+                v.push((*w & 0xff) as u8);
+            }
+            v
+        }
+    }
+}
+
+/// Checks if an IP is contained in given IP range in CIDR notation (an address alone
+/// is a range of one address).
+#[experimental]
+pub fn cidr_matches(ip: &IpAddr, range: &str) -> bool {
+    let mut split = range.splitn(1, '/');
+    let base = match split.next().and_then(|s| from_str::<IpAddr>(s)) {
+        Some(b) => ip_bytes(&b),
+        None => return false
+    };
+    let ip = ip_bytes(ip);
+    if ip.len() != base.len() { return false; }
+    let prefix = match split.next() {
+        Some(p) => match from_str::<uint>(p) {
+            Some(n) if n <= 8 * ip.len() => n,
+            _ => return false
+        },
+        None => 8 * ip.len()
+    };
+    for i in range(0u, prefix) {
+        let mask = 0x80u8 >> (i % 8);
+        if ip[i / 8] & mask != base[i / 8] & mask { return false; }
+    }
+    true
+}
+
+/// Checks if the string is a valid IP address or range in CIDR notation.
+#[experimental]
+pub fn check_cidr(range: &str) -> bool {
+    let mut split = range.splitn(1, '/');
+    match split.next().and_then(|s| from_str::<IpAddr>(s)) {
+        Some(ip) => cidr_matches(&ip, range),
+        None => false
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{cidr_matches, Ban, KLine};
+    use std::io::net::ip::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn test_cidr_matches() {
+        let ip = Ipv4Addr(192, 168, 12, 34);
+        assert!(cidr_matches(&ip, "192.168.12.34"));
+        assert!(cidr_matches(&ip, "192.168.0.0/16"));
+        assert!(cidr_matches(&ip, "192.168.12.32/30"));
+        assert!(cidr_matches(&ip, "0.0.0.0/0"));
+        assert!(!cidr_matches(&ip, "192.168.12.35"));
+        assert!(!cidr_matches(&ip, "192.168.12.36/30"));
+        assert!(!cidr_matches(&ip, "10.0.0.0/8"));
+        assert!(!cidr_matches(&ip, "192.168.0.0/33"));
+        assert!(!cidr_matches(&ip, "::1"));
+        let ip6 = Ipv6Addr(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        assert!(cidr_matches(&ip6, "2001:db8::/32"));
+        assert!(!cidr_matches(&ip6, "2001:db9::/32"));
+    }
+
+    #[test]
+    fn test_ban_line() {
+        let ban = Ban {
+            kind: KLine,
+            mask: "*@spam.example.com".to_string(),
+            reason: "No spam\twelcome".to_string(),
+            setter: "oper".to_string(),
+            set_at: 1000,
+            expires: Some(4600)
+        };
+        let parsed = Ban::from_line(ban.to_line().as_slice()).unwrap();
+        assert_eq!(parsed.kind, KLine);
+        assert_eq!(parsed.mask, ban.mask);
+        assert_eq!(parsed.reason, ban.reason);
+        assert_eq!(parsed.setter, ban.setter);
+        assert_eq!(parsed.set_at, 1000);
+        assert_eq!(parsed.expires, Some(4600));
+    }
+
+}
@@ -10,15 +10,29 @@ use time::now;
 
 use uuid::Uuid;
 
+use extensions::Extensions;
 use users::UserData;
-use modes::Modes;
+use modes::{Modes, membership_rank};
 
 /// The membership of a user in a channel
 #[experimental]
 pub struct Membership {
     pub user: Weak<RWLock<UserData>>,
     pub channel: Weak<RWLock<Channel>>,
-    pub modes: RWLock<Modes>
+    pub modes: RWLock<Modes>,
+    /// Data attached by modules.
+    pub extensions: Extensions
+}
+
+#[experimental]
+impl Membership {
+
+    /// The rank of the most powerful membership mode of the member, 0 if none.
+    #[experimental]
+    pub fn rank(&self) -> uint {
+        membership_rank(&*self.modes.read())
+    }
+
 }
 
 /// A channel.
@@ -28,7 +42,9 @@ pub struct Channel {
     pub topic: String,
     members: HashMap<Uuid, Weak<Membership>>,
     pub modes: Modes,
-    pub creation_time: i64
+    pub creation_time: i64,
+    /// Data attached by modules.
+    pub extensions: Extensions
 }
 
 #[experimental]
@@ -42,7 +58,8 @@ impl Channel {
             topic: String::new(),
             members: HashMap::new(),
             modes: Modes::new(),
-            creation_time: now().to_timespec().sec
+            creation_time: now().to_timespec().sec,
+            extensions: Extensions::new()
         }
     }
 
@@ -11,6 +11,7 @@ use messages::IRCMessage;
 use uuid::Uuid;
 
 use super::chan::{Channel, Membership};
+use extensions::Extensions;
 use modes::Modes;
 use util;
 use users::UserData;
@@ -35,13 +36,20 @@ impl ChannelManager {
         }
     }
 
+    /// Number of existing channels.
+    #[experimental]
+    pub fn len(&self) -> uint {
+        self.chans.len()
+    }
+
     #[experimental]
     fn do_join(user: &Arc<RWLock<UserData>>, chan: &Arc<RWLock<Channel>>, name: String) {
         if user.read().channels.read().contains_key(&name) { return; }
         let membership = Arc::new(Membership {
             user: user.downgrade(),
             channel: chan.downgrade(),
-            modes: RWLock::new(Modes::new())
+            modes: RWLock::new(Modes::new()),
+            extensions: Extensions::new()
         });
         chan.write().join(user.read().id.clone(), membership.downgrade());
         user.read().channels.write().insert(name.to_string(), membership);
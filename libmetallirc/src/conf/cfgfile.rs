@@ -2,7 +2,7 @@
 
 #![experimental]
 
-use super::ServerConf;
+use super::{AdminInfo, ConnectionClass, ServerConf};
 use logging;
 
 
@@ -18,7 +18,29 @@ use toml;
 /// port = <needed>
 /// loglevel = "Warning"
 /// logfile = "./metallircd.log"
+/// banfile = "./metallirc.bans"
 /// workers = 2
+/// whowas_per_nick = 5
+/// whowas_nicks = 1000
+///
+/// [[class]]
+/// name = <needed>
+/// port = <any>
+/// hostmask = "*"
+/// account = <any>
+/// password = <any>
+/// max_clients = 1024
+/// sendq = 1048576
+/// recvq = 8192
+/// ping_frequency = 120
+/// flood_burst = 10
+/// flood_rate = 1
+/// max_channels = 20
+///
+/// [admin]
+/// location1 = ""
+/// location2 = ""
+/// email = ""
 ///
 /// [[module]]
 /// name = "mod_name"
@@ -43,6 +65,7 @@ pub fn load_config(file: Path) -> Result<ServerConf,String> {
     };
 
     let mut config = ServerConf::default_conf();
+    config.config_file = file.clone();
 
     // [metallircd]
     match toml_table.get(&"metallircd".to_string()) {
@@ -87,16 +110,71 @@ pub fn load_config(file: Path) -> Result<ServerConf,String> {
                 },
                 _ => {}
             };
+            match ircd_table.get(&"banfile".to_string()) {
+                Some(&toml::String(ref s)) => match from_str::<Path>(s.as_slice()) {
+                    Some(p) => config.banfile = p,
+                    None => {}
+                },
+                _ => {}
+            };
             match ircd_table.get(&"workers".to_string()) {
                 Some(&toml::Integer(i)) => config.thread_handler_count = i as uint,
                 _ => {}
             };
+            match ircd_table.get(&"whowas_per_nick".to_string()) {
+                Some(&toml::Integer(i)) if i >= 0 => config.whowas_per_nick = i as uint,
+                _ => {}
+            };
+            match ircd_table.get(&"whowas_nicks".to_string()) {
+                Some(&toml::Integer(i)) if i >= 0 => config.whowas_nicks = i as uint,
+                _ => {}
+            };
         }
         _ => {
             return Err("Could not find [metallircd] section in config file.".to_string())
         }
     }
 
+    // [[class]]
+    match toml_table.get(&"class".to_string()) {
+        Some(&toml::Array(ref classes)) => {
+            for class in classes.iter() {
+                match class {
+                    &toml::Table(ref class_table) => {
+                        config.classes.push(try!(parse_class(class_table, &file)));
+                    },
+                    _ => return Err("Class sections should be in the form [[class]] .".to_string())
+                }
+            }
+        },
+        Some(_) => {
+            return Err("Class sections should be in the form [[class]] .".to_string())
+        },
+        None => {}
+    }
+    if config.classes.is_empty() {
+        config.classes.push(ConnectionClass::default_class());
+    }
+
+    // [admin]
+    match toml_table.get(&"admin".to_string()) {
+        Some(&toml::Table(ref admin_table)) => {
+            let field = |name: &str| match admin_table.get(&name.to_string()) {
+                Some(&toml::String(ref s)) => s.clone(),
+                _ => String::new()
+            };
+            config.admin = Some(AdminInfo {
+                location1: field("location1"),
+                location2: field("location2"),
+                email: field("email")
+            });
+        },
+        Some(_) => {
+            return Err("Administrative information should be in an [admin] section.".to_string())
+        },
+        None => {}
+    }
+
     // [modules]
     match toml_table.get(&"module".to_string()) {
         Some(&toml::Table(ref modules_table)) => {
@@ -118,4 +196,60 @@ pub fn load_config(file: Path) -> Result<ServerConf,String> {
     }
 
     Ok(config)
-}
\ No newline at end of file
+}
+
+/// Parses a [[class]] section of the config file.
+fn parse_class(table: &toml::TomlTable, file: &Path) -> Result<ConnectionClass, String> {
+    let mut class = ConnectionClass::default_class();
+    class.name = match table.get(&"name".to_string()) {
+        Some(&toml::String(ref s)) => s.clone(),
+        _ => return Err(
+            format!("Error parsing config file {} : missing or invalid class.name", file.display())
+        )
+    };
+    match table.get(&"port".to_string()) {
+        Some(&toml::Integer(i)) => class.port = Some(i as u16),
+        _ => {}
+    };
+    match table.get(&"hostmask".to_string()) {
+        Some(&toml::String(ref s)) => class.hostmask = s.clone(),
+        _ => {}
+    };
+    match table.get(&"account".to_string()) {
+        Some(&toml::String(ref s)) => class.account = Some(s.clone()),
+        _ => {}
+    };
+    match table.get(&"password".to_string()) {
+        Some(&toml::String(ref s)) => class.password = Some(s.clone()),
+        _ => {}
+    };
+    match table.get(&"max_clients".to_string()) {
+        Some(&toml::Integer(i)) => class.max_clients = i as uint,
+        _ => {}
+    };
+    match table.get(&"sendq".to_string()) {
+        Some(&toml::Integer(i)) => class.sendq = i as uint,
+        _ => {}
+    };
+    match table.get(&"recvq".to_string()) {
+        Some(&toml::Integer(i)) => class.recvq = i as uint,
+        _ => {}
+    };
+    match table.get(&"ping_frequency".to_string()) {
+        Some(&toml::Integer(i)) => class.ping_frequency = i,
+        _ => {}
+    };
+    match table.get(&"flood_burst".to_string()) {
+        Some(&toml::Integer(i)) => class.flood_burst = i as uint,
+        _ => {}
+    };
+    match table.get(&"flood_rate".to_string()) {
+        Some(&toml::Integer(i)) => class.flood_rate = i as uint,
+        _ => {}
+    };
+    match table.get(&"max_channels".to_string()) {
+        Some(&toml::Integer(i)) => class.max_channels = i as uint,
+        _ => {}
+    };
+    Ok(class)
+}
@@ -0,0 +1,72 @@
+//! Connection classes (Y-lines).
+
+#![experimental]
+
+use util;
+
+/// A connection class, defining the limits applied to the connections it matches.
+#[experimental]
+#[deriving(Clone)]
+pub struct ConnectionClass {
+    pub name: String,
+
+    // matching
+    /// Only match connections made on this port.
+    pub port: Option<u16>,
+    /// Mask matched against `user@host` of the connection.
+    pub hostmask: String,
+    /// Only match connections authenticated to this account.
+    pub account: Option<String>,
+    /// Only match connections which gave this password with PASS.
+    pub password: Option<String>,
+
+    // limits
+    /// Maximum number of clients in this class.
+    pub max_clients: uint,
+    /// Maximum size (in bytes) of data waiting to be sent to a client.
+    pub sendq: uint,
+    /// Maximum size (in bytes) of data received from a client and not yet processed.
+    pub recvq: uint,
+    /// Delay of inactivity (in seconds) before sending a PING to the client.
+    pub ping_frequency: i64,
+    /// Number of commands a client can send at once.
+    pub flood_burst: uint,
+    /// Number of commands per second a client can sustain.
+    pub flood_rate: uint,
+    /// Maximum number of channels a client can be in.
+    pub max_channels: uint
+}
+
+#[experimental]
+impl ConnectionClass {
+
+    /// The class used when none is defined in the config file.
+    #[experimental]
+    pub fn default_class() -> ConnectionClass {
+        ConnectionClass {
+            name: "default".to_string(),
+            port: None,
+            hostmask: "*".to_string(),
+            account: None,
+            password: None,
+            max_clients: 1024,
+            sendq: 1024 * 1024,
+            recvq: 8192,
+            ping_frequency: 120,
+            flood_burst: 10,
+            flood_rate: 1,
+            max_channels: 20
+        }
+    }
+
+    /// Does this class match given connection parameters ?
+    #[experimental]
+    pub fn matches(&self, port: u16, userhost: &str,
+                   account: Option<&String>, password: Option<&String>) -> bool {
+        self.port.map_or(true, |p| p == port)
+        && util::matches_mask(userhost, self.hostmask.as_slice())
+        && (self.account.is_none() || self.account.as_ref() == account)
+        && (self.password.is_none() || self.password.as_ref() == password)
+    }
+
+}
@@ -5,15 +5,29 @@
 use logging::{LogLevel, Warning};
 
 pub use self::cfgfile::load_config;
+pub use self::classes::ConnectionClass;
 
 use std::collections::TreeMap;
 use std::io::net::ip::{IpAddr, Ipv4Addr};
 use toml::TomlTable;
 
 mod cfgfile;
+mod classes;
+
+/// Administrative information given by the ADMIN command.
+#[experimental]
+#[deriving(Clone)]
+pub struct AdminInfo {
+    pub location1: String,
+    pub location2: String,
+    pub email: String
+}
 
 #[experimental]
 pub struct ServerConf {
+    /// The file this config was loaded from.
+    pub config_file: Path,
+
     // generic
     pub name: String,
     pub address: IpAddr,
@@ -23,9 +37,23 @@ pub struct ServerConf {
     pub loglevel: LogLevel,
     pub logfile: Path,
 
+    /// File storing the server bans.
+    pub banfile: Path,
+
     // threads
     pub thread_handler_count: uint,
 
+    /// Number of WHOWAS records kept for each nickname.
+    pub whowas_per_nick: uint,
+    /// Number of different nicknames kept in the WHOWAS history.
+    pub whowas_nicks: uint,
+
+    /// Connection classes, in matching order.
+    pub classes: Vec<ConnectionClass>,
+
+    /// Administrative information, if any.
+    pub admin: Option<AdminInfo>,
+
     /// Contains the toml table of the config file, to be used by each module.
     pub modules: TreeMap<String, TomlTable>
 }
@@ -36,6 +64,8 @@ impl ServerConf {
     #[experimental]
     pub fn default_conf() -> ServerConf {
         ServerConf {
+            config_file: from_str("./metallirc.toml").unwrap(),
+
             name: String::new(), // no default
             address: Ipv4Addr(0,0,0,0), // no default
             port: 0u16, // no default
@@ -44,12 +74,39 @@ impl ServerConf {
             loglevel: Warning,
             logfile: from_str("./metallirc.log").unwrap(),
 
+            banfile: from_str("./metallirc.bans").unwrap(),
+
             // threads
             thread_handler_count: 2u,
 
+            whowas_per_nick: 5u,
+            whowas_nicks: 1000u,
+
+            // classes
+            classes: Vec::new(),
+
+            admin: None,
+
             // rest of the config file
             modules: TreeMap::new()
         }
     }
 
+    /// Finds the first connection class matching given connection parameters.
+    #[experimental]
+    pub fn find_class(&self, port: u16, userhost: &str,
+                      account: Option<&String>, password: Option<&String>) -> Option<&ConnectionClass> {
+        self.classes.iter().find(|c| c.matches(port, userhost, account, password))
+    }
+
+    /// Retrieves a connection class by its name.
+    /// Falls back to the last class if it does not exist anymore.
+    #[experimental]
+    pub fn get_class(&self, name: &str) -> &ConnectionClass {
+        match self.classes.iter().find(|c| c.name.as_slice() == name) {
+            Some(c) => c,
+            None => self.classes.last().expect("No connection class defined.")
+        }
+    }
+
 }
\ No newline at end of file
@@ -0,0 +1,139 @@
+//! Storage for module data attached to users, channels and memberships.
+
+//! Each `UserData`, `Channel` and `Membership` has an `Extensions` map, in which
+//! modules can store at most one value of each type. The values are dropped with
+//! their owner.
+//!
+//! The types used by a module must be declared to the `ModuleRegistrar`, so that
+//! their values are removed before the module is unloaded, or saved and restored
+//! across a reload if they are `SerializableExtension`.
+
+#![experimental]
+
+use std::any::{Any, AnyRefExt, AnyMutRefExt};
+use std::collections::HashMap;
+use std::intrinsics::TypeId;
+use std::sync::RWLock;
+
+use uuid::Uuid;
+
+use ServerData;
+
+/// A typed map of values attached to an object by modules.
+#[experimental]
+pub struct Extensions {
+    data: RWLock<HashMap<TypeId, Box<Any + Send + Sync>>>
+}
+
+#[experimental]
+impl Extensions {
+
+    /// Creates an empty map.
+    #[experimental]
+    pub fn new() -> Extensions {
+        Extensions {
+            data: RWLock::new(HashMap::new())
+        }
+    }
+
+    /// Attaches a value, replacing any previous value of same type.
+    #[experimental]
+    pub fn insert<T: Send + Sync + 'static>(&self, value: T) {
+        self.data.write().insert(TypeId::of::<T>(), box value as Box<Any + Send + Sync>);
+    }
+
+    /// Removes the value of type `T`. Returns false if there was none.
+    #[experimental]
+    pub fn remove<T: Send + Sync + 'static>(&self) -> bool {
+        self.remove_type(&TypeId::of::<T>())
+    }
+
+    /// Removes the value of given type. Returns false if there was none.
+    #[experimental]
+    pub fn remove_type(&self, id: &TypeId) -> bool {
+        self.data.write().remove(id).is_some()
+    }
+
+    /// Returns true if a value of type `T` is attached.
+    #[experimental]
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.data.read().contains_key(&TypeId::of::<T>())
+    }
+
+    /// Applies given closure to the value of type `T`, if any.
+    #[experimental]
+    pub fn with<T: Send + Sync + 'static, R>(&self, f: |&T| -> R) -> Option<R> {
+        let data = self.data.read();
+        match data.get(&TypeId::of::<T>()) {
+            Some(value) => {
+                let any: &Any = &**value;
+                any.downcast_ref::<T>().map(f)
+            },
+            None => None
+        }
+    }
+
+    /// Applies given closure to the value of type `T`, if any, allowing to modify it.
+    #[experimental]
+    pub fn with_mut<T: Send + Sync + 'static, R>(&self, f: |&mut T| -> R) -> Option<R> {
+        let mut data = self.data.write();
+        match data.get_mut(&TypeId::of::<T>()) {
+            Some(value) => {
+                let any: &mut Any = &mut **value;
+                any.downcast_mut::<T>().map(f)
+            },
+            None => None
+        }
+    }
+
+}
+
+/// An extension which can be saved as text, to survive the reload of its module.
+#[experimental]
+pub trait SerializableExtension : Send + Sync + 'static {
+    /// Saves the value.
+    #[experimental]
+    fn serialize(&self) -> String;
+    /// Restores a value saved by `serialize()`, returns `None` if the data is invalid.
+    #[experimental]
+    fn deserialize(data: &str) -> Option<Self>;
+}
+
+/// The object an `Extensions` map belongs to.
+#[experimental]
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
+pub enum ExtensionOwner {
+    /// A user, by UUID.
+    UserOwner(Uuid),
+    /// A channel, by name.
+    ChannelOwner(String),
+    /// The membership of a user in a channel.
+    MembershipOwner(Uuid, String)
+}
+
+/// Applies given closure to the extensions of all users, channels and memberships of the server.
+#[experimental]
+pub fn apply_to_all_extensions(srv: &ServerData, f: |ExtensionOwner, &Extensions|) {
+    srv.users.read().apply_to_all(|u| {
+        f(UserOwner(u.id.clone()), &u.extensions);
+        for (chan, m) in u.channels.read().iter() {
+            f(MembershipOwner(u.id.clone(), chan.clone()), &m.extensions);
+        }
+    });
+    srv.channels.read().apply_to_chans(|c| f(ChannelOwner(c.name.clone()), &c.extensions));
+}
+
+/// Serializes the value of type `T` of given extensions, if any.
+#[experimental]
+pub fn save_extension<T: SerializableExtension>(ext: &Extensions) -> Option<String> {
+    ext.with(|value: &T| value.serialize())
+}
+
+/// Restores a value of type `T` in given extensions, ignoring invalid data.
+#[experimental]
+pub fn restore_extension<T: SerializableExtension>(ext: &Extensions, data: &str) {
+    let value: Option<T> = SerializableExtension::deserialize(data);
+    if let Some(v) = value {
+        ext.insert(v);
+    }
+}
@@ -1,6 +1,6 @@
 //! metallirc main library
 
-#![feature(macro_rules,if_let,while_let)]
+#![feature(macro_rules,if_let,while_let,unsafe_destructor)]
 
 #![experimental]
 
@@ -9,15 +9,30 @@ extern crate toml;
 extern crate uuid;
 
 use std::sync::RWLock;
+use std::sync::atomic::AtomicUint;
 
+use time::Timespec;
+
+pub mod bans;
 pub mod channels;
 pub mod conf;
+pub mod extensions;
 pub mod logging;
 pub mod messages;
 pub mod modes;
 pub mod modules;
+pub mod timers;
 pub mod users;
 pub mod util;
+pub mod welcome;
+
+/// Version of the server, as advertised to the clients.
+#[experimental]
+pub const VERSION: &'static str = "metallirc-0.0.1";
+
+/// Name of the network, as advertised to the clients.
+#[experimental]
+pub const NETWORK: &'static str = "metallirc";
 
 /// Contains all data of the server in a way that is safe to be shared between the server threads.
 #[experimental]
@@ -25,11 +40,19 @@ pub struct ServerData {
     pub settings: RWLock<conf::ServerConf>,
     pub users: RWLock<users::UserManager>,
     pub channels: RWLock<channels::ChannelManager>,
+    pub bans: RWLock<bans::BanManager>,
+    /// History of the departed nicknames.
+    pub whowas: RWLock<users::WhowasHistory>,
 
     pub logger: logging::Logger,
+    /// When the server was started.
+    pub start_time: Timespec,
     pub signal_shutdown: RWLock<bool>,
+    /// Number of connections still negociating their registration.
+    pub unregistered: AtomicUint,
+    pub timers: timers::TimerService,
 
-    pub modules_handler: RWLock<modules::ModulesHandler>
+    pub modules_handler: modules::ModulesLock
 }
 
 #[experimental]
@@ -38,13 +61,67 @@ impl ServerData {
     /// Creates the server data structure from a config.
     pub fn new(settings: conf::ServerConf)-> ServerData {
         let logger = logging::Logger::new(settings.loglevel);
+        let mut bans = bans::BanManager::new(settings.banfile.clone());
+        match bans.load() {
+            Ok(n) => logger.log(logging::Info, format!("Loaded {} bans.", n)),
+            Err(e) => logger.log(logging::Error, e)
+        }
         ServerData {
             settings: RWLock::new(settings),
             users: RWLock::new(users::UserManager::new()),
             channels: RWLock::new(channels::ChannelManager::new()),
+            bans: RWLock::new(bans),
+            whowas: RWLock::new(users::WhowasHistory::new()),
             logger: logger,
+            start_time: time::get_time(),
             signal_shutdown: RWLock::new(false),
-            modules_handler: RWLock::new(modules::ModulesHandler::init())
+            unregistered: AtomicUint::new(0),
+            timers: timers::TimerService::new(),
+            modules_handler: modules::ModulesLock::new(modules::ModulesHandler::init())
+        }
+    }
+
+    /// Reloads the config file, and applies the new settings. Settings which cannot be
+    /// changed at runtime are kept.
+    ///
+    /// If the new config file is invalid, or if a module rejects its new configuration,
+    /// nothing is changed and the error is returned. Otherwise, returns the list of the
+    /// problems encountered, which did not prevent the reload.
+    #[experimental]
+    pub fn rehash(&self) -> Result<Vec<String>, String> {
+        let file = self.settings.read().config_file.clone();
+        let mut new_settings = try!(conf::load_config(file));
+        let mut warnings = Vec::new();
+        {
+            let old = self.settings.read();
+            if new_settings.name != old.name {
+                warnings.push("The server name cannot be changed without restart.".to_string());
+                new_settings.name = old.name.clone();
+            }
+            if new_settings.address != old.address || new_settings.port != old.port {
+                warnings.push("The listening address cannot be changed without restart.".to_string());
+                new_settings.address = old.address;
+                new_settings.port = old.port;
+            }
+            if new_settings.logfile != old.logfile {
+                warnings.push("The log file cannot be changed without restart.".to_string());
+                new_settings.logfile = old.logfile.clone();
+            }
+            if new_settings.banfile != old.banfile {
+                warnings.push("The ban file cannot be changed without restart.".to_string());
+                new_settings.banfile = old.banfile.clone();
+            }
+            if new_settings.thread_handler_count != old.thread_handler_count {
+                warnings.push("The number of workers cannot be changed without restart.".to_string());
+                new_settings.thread_handler_count = old.thread_handler_count;
+            }
+        }
+        match self.modules_handler.read().reload_config(&new_settings.modules, &self.logger) {
+            Ok(module_warnings) => warnings.extend(module_warnings.into_iter()),
+            Err(errors) => return Err(errors.connect(" "))
         }
+        self.logger.set_level(new_settings.loglevel);
+        *self.settings.write() = new_settings;
+        Ok(warnings)
     }
 }
\ No newline at end of file
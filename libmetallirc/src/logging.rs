@@ -2,6 +2,7 @@
 
 #![experimental]
 
+use std::sync::atomic::{AtomicUint, SeqCst};
 use std::sync::mpsc_queue::Queue as MPSCQueue;
 
 use time::now;
@@ -20,7 +21,7 @@ pub enum LogLevel {
 #[experimental]
 pub struct Logger {
     queue: MPSCQueue<String>,
-    pub level: LogLevel
+    level: AtomicUint
 }
 
 #[experimental]
@@ -31,7 +32,7 @@ impl Logger {
     pub fn new(level: LogLevel) -> Logger {
         let logger = Logger {
             queue: MPSCQueue::new(),
-            level: level
+            level: AtomicUint::new(level as uint)
         };
         logger.queue.push(String::new()); // newline at reboot
         logger.log(Info, format!("Initialised logging with level {}", level));
@@ -41,12 +42,19 @@ impl Logger {
     /// Adds a new entry to the logs.
     #[experimental]
     pub fn log(&self, level: LogLevel, text: String) {
-        if level as u8 <= self.level as u8 {
+        if level as uint <= self.level.load(SeqCst) {
             let t = now();
             self.queue.push(format!("[{}] {}: {}", t.strftime("%d/%b/%Y:%H:%M:%S %z").unwrap(), level, text));
         }
     }
 
+    /// Changes the loglevel.
+    #[experimental]
+    pub fn set_level(&self, level: LogLevel) {
+        self.level.store(level as uint, SeqCst);
+        self.log(Info, format!("Loglevel set to {}", level));
+    }
+
     /// Pops the next message, to be used only by the thread handling log writing.
     #[experimental]
     pub fn pop(&self) -> Option<String> {
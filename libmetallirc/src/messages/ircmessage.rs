@@ -8,6 +8,9 @@ use std::from_str::FromStr;
 /// Represents an IRC protocol message.
 #[deriving(Show, PartialEq, Clone)]
 pub struct IRCMessage {
+    /// The IRCv3 message tags, as `(key, value)`. An empty value stands for a tag
+    /// without value.
+    pub tags: Vec<(String, String)>,
     pub prefix: Option<String>,
     pub command: String,
     pub args: Vec<String>,
@@ -20,6 +23,11 @@ impl IRCMessage {
     #[experimental]
     pub fn to_protocol(&self) -> String {
         let mut output = String::new();
+        if self.tags.len() > 0 {
+            output.push('@');
+            output.push_str(self.tags_to_protocol().as_slice());
+            output.push(' ');
+        }
         if let Some(ref text) = self.prefix {
             output.push(':');
             output.push_str(text.as_slice());
@@ -42,11 +50,30 @@ impl IRCMessage {
     #[experimental]
     pub fn protocol_len(&self) -> uint {
         self.command.len()
+        + if self.tags.len() > 0 { self.tags_to_protocol().len() + 2 } else { 0 }
         + if let Some(ref s) = self.prefix { s.len() } else { 0 }
         + self.args.iter().fold(0, |n, s| n + 1 + s.len())
         + if let Some(ref s) = self.suffix { s.len() } else { 0 }
     }
 
+    /// The tags in protocol form, without the leading `@`.
+    fn tags_to_protocol(&self) -> String {
+        format_tags(self.tags.as_slice())
+    }
+
+    /// The value of given tag, if the message has it.
+    #[experimental]
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.iter().find(|&&(ref k, _)| k.as_slice() == key).map(|&(_, ref v)| v.as_slice())
+    }
+
+    /// Sets the value of given tag, replacing any previous value.
+    #[experimental]
+    pub fn set_tag(&mut self, key: &str, value: &str) {
+        self.tags.retain(|&(ref k, _)| k.as_slice() != key);
+        self.tags.push((key.to_string(), value.to_string()));
+    }
+
     /// Attemps to parse parameters of the Message a `needed` necessary
     /// parameters and `optionnal` optionnal parameters. Returning `None`
     /// if there were not enough parmeters to fullfill `needed`.
@@ -107,6 +134,16 @@ impl FromStr for IRCMessage {
                return None;
         }
         let mut rest = s;
+        // are there tags to parse ?
+        let mut tags = Vec::new();
+        if rest.char_at(0) == '@' {
+            let mut split = rest.splitn(1, ' ');
+            tags = parse_tags(split.next().unwrap().slice_from(1));
+            match split.next() { // the rest
+                Some(txt) if txt.len() > 0 => { rest = txt; },
+                _ => { return None; } // no command ??
+            }
+        }
         // is there a prefix to parse ?
         let mut prefix = None;
         if rest.char_at(0) == ':' {
@@ -157,6 +194,7 @@ impl FromStr for IRCMessage {
             }
         }
         Some(IRCMessage{
+            tags: tags,
             prefix: prefix,
             command: command,
             args: args,
@@ -165,13 +203,81 @@ impl FromStr for IRCMessage {
     }
 }
 
+/// Formats tags as in the protocol, without the leading `@`.
+#[experimental]
+pub fn format_tags(tags: &[(String, String)]) -> String {
+    let mut output = String::new();
+    for &(ref key, ref value) in tags.iter() {
+        if output.len() > 0 { output.push(';'); }
+        output.push_str(key.as_slice());
+        if value.len() > 0 {
+            output.push('=');
+            output.push_str(escape_tag_value(value.as_slice()).as_slice());
+        }
+    }
+    output
+}
+
+/// Parses tags in protocol form, without the leading `@`.
+#[experimental]
+pub fn parse_tags(text: &str) -> Vec<(String, String)> {
+    let mut tags = Vec::new();
+    for tag in text.split(';') {
+        if tag.len() == 0 { continue; }
+        let mut kv = tag.splitn(1, '=');
+        let key = kv.next().unwrap();
+        let value = kv.next().map_or(String::new(), |v| unescape_tag_value(v));
+        tags.push((key.to_string(), value));
+    }
+    tags
+}
+
+/// Escapes a tag value for the protocol.
+fn escape_tag_value(value: &str) -> String {
+    let mut output = String::new();
+    for c in value.chars() {
+        match c {
+            ';' => output.push_str("\\:"),
+            ' ' => output.push_str("\\s"),
+            '\\' => output.push_str("\\\\"),
+            '\r' => output.push_str("\\r"),
+            '\n' => output.push_str("\\n"),
+            c => output.push(c)
+        }
+    }
+    output
+}
+
+/// Unescapes a tag value from the protocol.
+fn unescape_tag_value(value: &str) -> String {
+    let mut output = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => output.push(';'),
+            Some('s') => output.push(' '),
+            Some('r') => output.push('\r'),
+            Some('n') => output.push('\n'),
+            // an invalid escape is the escaped char itself, a trailing backslash is dropped
+            Some(other) => output.push(other),
+            None => {}
+        }
+    }
+    output
+}
+
 #[cfg(test)]
 mod tests {
-    use super::IRCMessage;
+    use super::{IRCMessage, format_tags, parse_tags};
 
     #[test]
     fn ircmessage_to_protocol() {
         let message = IRCMessage {
+            tags: Vec::new(),
             prefix: Some("kitty".to_string()),
             command: "FOO".to_string(),
             args: vec!("bar".to_string(), "baz".to_string()),
@@ -184,6 +290,7 @@ mod tests {
     fn ircmessage_from_string() {
         let message = from_str::<IRCMessage>(":bl:ih BLAH blo_uh bl:uh bleh :I love cakes !! ::").unwrap();
         let expected = IRCMessage {
+            tags: Vec::new(),
             prefix: Some("bl:ih".to_string()),
             command: "BLAH".to_string(),
             args: vec!("blo_uh".to_string(), "bl:uh".to_string(), "bleh".to_string()),
@@ -192,4 +299,24 @@ mod tests {
         assert_eq!(message, expected);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn ircmessage_tags() {
+        let message = from_str::<IRCMessage>("@label=a\\sb\\:c;+typing :kitty TAGMSG #cakes").unwrap();
+        assert_eq!(message.tag("label"), Some("a b;c"));
+        assert_eq!(message.tag("+typing"), Some(""));
+        assert_eq!(message.tag("time"), None);
+        assert_eq!(message.prefix, Some("kitty".to_string()));
+        assert_eq!(message.to_protocol().as_slice(), "@label=a\\sb\\:c;+typing :kitty TAGMSG #cakes");
+        assert!(from_str::<IRCMessage>("@label=a").is_none());
+    }
+
+    #[test]
+    fn ircmessage_tags_roundtrip() {
+        let tags = parse_tags("+draft/react=\\\\o/;msgid=42;+typing");
+        assert_eq!(tags[0], ("+draft/react".to_string(), "\\o/".to_string()));
+        assert_eq!(tags[2], ("+typing".to_string(), "".to_string()));
+        assert_eq!(format_tags(tags.as_slice()).as_slice(), "+draft/react=\\\\o/;msgid=42;+typing");
+        assert!(parse_tags("").is_empty());
+    }
+
+}
@@ -7,8 +7,9 @@
 
 #![experimental]
 
-pub use self::ircmessage::IRCMessage;
+pub use self::ircmessage::{IRCMessage, format_tags, parse_tags};
 pub use self::textmessage::{Actor, User, Server, Channel, Everybody, TextMessage};
+pub use self::textmessage::{TextKind, Privmsg, Notice, Tagmsg};
 pub use self::numericreply::NumericReply;
 
 mod ircmessage;
@@ -16,6 +16,7 @@ pub enum NumericReply<'a> {
     RPL_CREATED(&'a str),
     RPL_MYINFO(&'a str, &'a str, &'a str, &'a str,),
     RPL_BOUNCE(&'a str, u16),
+    RPL_ISUPPORT(Vec<&'a str>),
     //
     // Responses
     //
@@ -31,10 +32,10 @@ pub enum NumericReply<'a> {
     RPL_WHOISOPERATOR(&'a str),
     RPL_WHOISIDLE(&'a str, u32),
     RPL_ENDOFWHOIS(&'a str),
-    RPL_WHOISCHANNELS(&'a str, Vec<(Option<char>, &'a str)>),
+    RPL_WHOISCHANNELS(&'a str, Vec<(String, &'a str)>),
     // Whowas related
     RPL_WHOWASUSER(&'a str, &'a str, &'a str, &'a str),
-    RPL_ENDOFWHOWAS,
+    RPL_ENDOFWHOWAS(&'a str),
     // List related
     RPL_LIST(&'a str, u32, &'a str),
     RPL_LISTEND,
@@ -61,9 +62,9 @@ pub enum NumericReply<'a> {
     RPL_CREATIONTIME(&'a str, i64),
     // Server related
     RPL_VERSION(&'a str, &'a str, &'a str, &'a str),
-    RPL_WHOREPLY(&'a str, &'a str, &'a str, &'a str, &'a str, char, bool, Option<char>, u32, &'a str),
+    RPL_WHOREPLY(&'a str, &'a str, &'a str, &'a str, &'a str, char, bool, &'a str, u32, &'a str),
     RPL_ENDOFWHO(&'a str),
-    RPL_NAMEREPLY(char, &'a str, Vec<(Option<char>, String)>),
+    RPL_NAMEREPLY(char, &'a str, Vec<&'a str>),
     RPL_ENDOFNAMES(&'a str),
     RPL_LINKS(&'a str, &'a str, u32, &'a str),
     RPL_ENDOFLINKS(&'a str),
@@ -106,10 +107,20 @@ pub enum NumericReply<'a> {
     RPL_ENDOFSTATS(&'a str),
     RPL_STATSUPTIME(u32, u8, u8, u8),
     RPL_STATSOLINE(&'a str, &'a str),
+    RPL_STATSYLINE(&'a str, u32, u32, u32, u32),
+    RPL_STATSKLINE(&'a str, &'a str),
+    RPL_STATSQLINE(&'a str, &'a str),
+    RPL_STATSDLINE(&'a str, &'a str),
     // Client mode
     RPL_UMODEIS(&'a str),
     // Misc
     RPL_TRYAGAIN(&'a str),
+    // Monitor related
+    RPL_MONONLINE(Vec<&'a str>),
+    RPL_MONOFFLINE(Vec<&'a str>),
+    RPL_MONLIST(Vec<&'a str>),
+    RPL_ENDOFMONLIST,
+    ERR_MONLISTFULL(u32, &'a str),
     //
     // Errors
     //
@@ -122,6 +133,7 @@ pub enum NumericReply<'a> {
     ERR_TOOMANYTARGETS(&'a str, &'a str, &'a str),
     ERR_NOSUCHSERVICE(&'a str),
     ERR_NOORIGIN,
+    ERR_INVALIDCAPCMD(&'a str),
     ERR_UNKNOWNCOMMAND(&'a str),
     ERR_NOMOTD,
     ERR_NOADMININFO(&'a str),
@@ -185,24 +197,28 @@ impl<'a> NumericReply<'a> {
     pub fn into_ircmessage(self, usrnick: &str) -> IRCMessage {
         match self {
             RPL_WELCOME(msg, fullname) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "001".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(format!("Welcome to {} {}", msg, fullname))
             },
             RPL_YOURHOST(srvname, version) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "002".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(format!("Your host is {}, running version {}", srvname, version))
             },
             RPL_CREATED(date) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "003".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(format!("This server was created {}", date))
             },
             RPL_MYINFO(srvname, version, user_modes, chan_modes) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "004".into_string(),
                 args: vec!(
@@ -215,15 +231,28 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_BOUNCE(server, port) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "005".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(format!("Try server {} port {}", server, port))
             },
+            RPL_ISUPPORT(tokens) => IRCMessage {
+                tags: Vec::new(),
+                prefix: None,
+                command: "005".into_string(),
+                args: {
+                    let mut args = vec!(usrnick.into_string());
+                    args.extend(tokens.into_iter().map(|t| t.into_string()));
+                    args
+                },
+                suffix: Some("are supported by this server".into_string())
+            },
             //
             // Responses
             //
             RPL_USERHOST(v) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "302".into_string(),
                 args: vec!(usrnick.into_string()),
@@ -232,6 +261,7 @@ impl<'a> NumericReply<'a> {
                         if s.len() > 0 { s.push(' '); }
                         s.push_str(nick);
                         if op { s.push('*'); }
+                        s.push('=');
                         if aw { s.push('-'); } else { s.push('+'); }
                         s.push_str(host);
                         s
@@ -239,8 +269,9 @@ impl<'a> NumericReply<'a> {
                 )
             },
             RPL_ISON(v) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
-                command: "302".into_string(),
+                command: "303".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(
                     v.into_iter().fold(String::new(), |mut s, nick|{
@@ -252,18 +283,21 @@ impl<'a> NumericReply<'a> {
             },
             // Away related
             RPL_AWAY(nick, msg) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "301".into_string(),
                 args: vec!(usrnick.into_string(), nick.into_string()),
                 suffix: Some(msg.into_string())
             },
             RPL_UNAWAY => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "305".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("You are no longer marked as away".into_string())
             },
             RPL_NOWAWAY => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "306".into_string(),
                 args: vec!(usrnick.into_string()),
@@ -271,6 +305,7 @@ impl<'a> NumericReply<'a> {
             },
             // Whois related
             RPL_WHOISUSER(nick, user, host, real_name) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "311".into_string(),
                 args: vec!(
@@ -283,6 +318,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some(real_name.into_string())
             },
             RPL_WHOISSERVER(nick, server, server_info) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "312".into_string(),
                 args: vec!(
@@ -293,6 +329,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some(server_info.into_string())
             },
             RPL_WHOISOPERATOR(nick) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "313".into_string(),
                 args: vec!(
@@ -302,6 +339,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("is an IRC operator".into_string())
             },
             RPL_WHOISIDLE(nick, time) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "317".into_string(),
                 args: vec!(
@@ -312,6 +350,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("seconds idle".into_string())
             },
             RPL_ENDOFWHOIS(masks) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "318".into_string(),
                 args: vec!(
@@ -322,6 +361,7 @@ impl<'a> NumericReply<'a> {
             },
             // (&'a str, Vec<(char, &'a str)>)
             RPL_WHOISCHANNELS(nick, v) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "319".into_string(),
                 args: vec!(
@@ -331,7 +371,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some(
                     v.into_iter().fold(String::new(), |mut s, (m, chan)|{
                         if s.len() > 0 { s.push(' '); }
-                        if let Some(c) = m { s.push(c) };
+                        s.push_str(m.as_slice());
                         s.push_str(chan);
                         s
                     })
@@ -339,6 +379,7 @@ impl<'a> NumericReply<'a> {
             },
             // Whowas related
             RPL_WHOWASUSER(nick, user, host, real_name) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "314".into_string(),
                 args: vec!(
@@ -350,14 +391,16 @@ impl<'a> NumericReply<'a> {
                 ),
                 suffix: Some(real_name.into_string())
             },
-            RPL_ENDOFWHOWAS => IRCMessage {
+            RPL_ENDOFWHOWAS(nick) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "369".into_string(),
-                args: vec!(usrnick.into_string()),
+                args: vec!(usrnick.into_string(), nick.into_string()),
                 suffix: Some("End of WHOWAS list".into_string())
             },
             // List related
             RPL_LIST(chan, users, topic) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "322".into_string(),
                 args: vec!(
@@ -368,12 +411,14 @@ impl<'a> NumericReply<'a> {
                 suffix: Some(topic.into_string())
             },
             RPL_LISTEND => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "323".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("End of LIST".into_string())
             },
             RPL_SERVLIST(name, server, mask, t_ype, hopcount, info) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "234".into_string(),
                 args: vec!(
@@ -388,6 +433,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_SERVLISTEND(mask, t_ype) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "235".into_string(),
                 args: vec!(
@@ -397,34 +443,39 @@ impl<'a> NumericReply<'a> {
                 ),
                 suffix: Some("End of service listing".into_string())
             },
-            RPL_LUSERCLIENT(users, services, servers) => IRCMessage {
+            RPL_LUSERCLIENT(users, invisible, servers) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "251".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(
-                    format!("There are {} users and {} services on {} servers",
-                        users, services, servers)
+                    format!("There are {} users and {} invisible on {} servers",
+                        users, invisible, servers)
                 )
             },
             RPL_LUSEROP(count) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "252".into_string(),
                 args: vec!(usrnick.into_string(), count.to_string()),
                 suffix: Some("operator(s) online".into_string())
             },
             RPL_LUSERUNKNOWN(count) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "253".into_string(),
                 args: vec!(usrnick.into_string(), count.to_string()),
                 suffix: Some("unknown connection(s)".into_string())
             },
             RPL_LUSERCHANNELS(count) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "254".into_string(),
                 args: vec!(usrnick.into_string(), count.to_string()),
                 suffix: Some("channels formed".into_string())
             },
             RPL_LUSERME(users, servers) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "255".into_string(),
                 args: vec!(usrnick.into_string()),
@@ -435,6 +486,7 @@ impl<'a> NumericReply<'a> {
             },
             // Chan related
             RPL_UNIQOPIS(chan, nick) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "325".into_string(),
                 args: vec!(
@@ -445,6 +497,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_CHANNELMODEIS(chan, modes) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "324".into_string(),
                 args: vec!(
@@ -455,6 +508,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_NOTOPIC(chan) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "331".into_string(),
                 args: vec!(
@@ -464,6 +518,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("No topic is set".into_string())
             },
             RPL_TOPIC(chan, topic) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "331".into_string(),
                 args: vec!(
@@ -473,6 +528,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some(topic.into_string())
             },
             RPL_INVITING(nick, chan) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "341".into_string(),
                 args: vec!(
@@ -483,6 +539,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_SUMMONING(nick) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "342".into_string(),
                 args: vec!(
@@ -492,6 +549,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Summoning user to IRC.".into_string())
             },
             RPL_INVITELIST(chan, mask) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "346".into_string(),
                 args: vec!(
@@ -502,6 +560,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_ENDOFINVITELIST(chan) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "347".into_string(),
                 args: vec!(
@@ -511,6 +570,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("End of channel invite list".into_string())
             },
             RPL_EXCEPTLIST(chan, mask) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "348".into_string(),
                 args: vec!(
@@ -521,6 +581,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_ENDOFEXCEPTLIST(chan) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "349".into_string(),
                 args: vec!(
@@ -530,6 +591,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("End of channel exception list".into_string())
             },
             RPL_BANLIST(chan, mask) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "367".into_string(),
                 args: vec!(
@@ -540,15 +602,17 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_ENDOFBANLIST(chan) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "368".into_string(),
                 args: vec!(
                     usrnick.into_string(),
                     chan.into_string()
                 ),
-                suffix: Some("End of channel exception list".into_string())
+                suffix: Some("End of channel ban list".into_string())
             },
             RPL_CREATIONTIME(chan, time) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "329".into_string(),
                 args: vec!(
@@ -559,6 +623,7 @@ impl<'a> NumericReply<'a> {
             },
             // Server related
             RPL_VERSION(version, debuglevel, server, comments) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "351".into_string(),
                 args: vec!(
@@ -570,6 +635,7 @@ impl<'a> NumericReply<'a> {
             },
             RPL_WHOREPLY(channel, user, host, server,
                     nick, hg, star, membership, hopcount, real_name) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "352".into_string(),
                 args: vec!(
@@ -582,13 +648,14 @@ impl<'a> NumericReply<'a> {
                     {
                         let mut s = hg.to_string();
                         if star { s.push('*'); }
-                        if let Some(c) = membership { s.push(c); }
+                        s.push_str(membership);
                         s
                     }
                 ),
                 suffix: Some(hopcount.to_string() + " " + real_name)
             },
             RPL_ENDOFWHO(name) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "315".into_string(),
                 args: vec!(
@@ -598,6 +665,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("End of WHO list".into_string())
             },
             RPL_NAMEREPLY(cprefix, chan, v) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "353".into_string(),
                 args: vec!(
@@ -605,16 +673,10 @@ impl<'a> NumericReply<'a> {
                     cprefix.to_string(),
                     chan.into_string()
                 ),
-                suffix: Some(
-                    v.into_iter().fold(String::new(), |mut s, (prefix, nick)|{
-                        if s.len() > 0 { s.push(' '); }
-                        if let Some(c) = prefix { s.push(c) };
-                        s.push_str(nick.as_slice());
-                        s
-                    })
-                )
+                suffix: Some(v.connect(" "))
             },
             RPL_ENDOFNAMES(chan) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "366".into_string(),
                 args: vec!(
@@ -624,6 +686,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("End of NAMES list".into_string())
             },
             RPL_LINKS(mask, server, hopcount, server_info) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "364".into_string(),
                 args: vec!(
@@ -636,6 +699,7 @@ impl<'a> NumericReply<'a> {
                 )
             },
             RPL_ENDOFLINKS(mask) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "366".into_string(),
                 args: vec!(
@@ -645,18 +709,21 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("End of LINKS list".into_string())
             },
             RPL_INFO(info) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "371".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(info.into_string())
             },
             RPL_ENDOFINFO => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "374".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("End of INFO list".into_string())
             },
             RPL_TIME(server, time) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "391".into_string(),
                 args: vec!(
@@ -667,31 +734,36 @@ impl<'a> NumericReply<'a> {
             },
             // MOTD
             RPL_MOTDSTART(server) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "375".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(format!("- {} Message of the day - ", server))
             },
             RPL_MOTD(text) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "372".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(format!("- {}", text))
             },
             RPL_ENDOFMOTD => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "376".into_string(),
                 args: vec!(usrnick.into_string()),
-                suffix: Some("End of MOTH command".into_string())
+                suffix: Some("End of MOTD command".into_string())
             },
             // Administration
             RPL_YOUREOPER => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "381".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("You are now an IRC operator".into_string())
             },
             RPL_REHASHING(file) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "382".into_string(),
                 args: vec!(
@@ -701,6 +773,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Rehashing".into_string())
             },
             RPL_YOURESERVICE(name) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "383".into_string(),
                 args: vec!(
@@ -714,6 +787,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_ADMINME(server) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "256".into_string(),
                 args: vec!(
@@ -723,18 +797,21 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Administrative info".into_string())
             },
             RPL_ADMINLOC1(info) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "257".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(info.into_string())
             },
             RPL_ADMINLOC2(info) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "258".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(info.into_string())
             },
             RPL_ADMINEMAIL(info) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "259".into_string(),
                 args: vec!(usrnick.into_string()),
@@ -742,24 +819,28 @@ impl<'a> NumericReply<'a> {
             },
             // Users related
             RPL_USERSSTART => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "392".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("UserID   Terminal  Host".into_string())
             },
             RPL_USERS(username, tty, host) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "393".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(format!("{} {} {}", username, tty, host))
             },
             RPL_ENDOFUSERS => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "394".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("End of users".into_string())
             },
             RPL_NOUSERS => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "395".into_string(),
                 args: vec!(usrnick.into_string()),
@@ -768,6 +849,7 @@ impl<'a> NumericReply<'a> {
             // Trace related
             RPL_TRACELINK(fullversion, dest, next_srv, proto_vers,
                           uptime, back_sendq, up_sendq) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "200".into_string(),
                 args: vec!(
@@ -784,6 +866,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_TRACECONNECTING(class, server) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "201".into_string(),
                 args: vec!(
@@ -795,6 +878,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_TRACEHANDSHAKE(class, server) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "202".into_string(),
                 args: vec!(
@@ -806,6 +890,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_TRACEUNKNOWN(class, ip) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "203".into_string(),
                 args: vec!(
@@ -817,6 +902,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_TRACEOPERATOR(class, nick) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "204".into_string(),
                 args: vec!(
@@ -828,6 +914,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_TRACEUSER(class, nick) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "205".into_string(),
                 args: vec!(
@@ -839,6 +926,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_TRACESERVER(class, s, c, server, nick_user, host_server, version) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "206".into_string(),
                 args: vec!(
@@ -854,6 +942,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_TRACESERVICE(class, name, t_ype, active_type) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "207".into_string(),
                 args: vec!(
@@ -867,6 +956,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_TRACENEWTYPE(newtype, client_name) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "208".into_string(),
                 args: vec!(
@@ -878,6 +968,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_TRACECLASS(class, count) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "209".into_string(),
                 args: vec!(
@@ -889,6 +980,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_TRACELOG(logfile, debuglevel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "261".into_string(),
                 args: vec!(
@@ -900,6 +992,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_TRACEEND(server, version, debuglevel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "262".into_string(),
                 args: vec!(
@@ -911,6 +1004,7 @@ impl<'a> NumericReply<'a> {
             },
             // Stats related
             RPL_STATSLINKINFO(linkname, sendq, sentm, sentkb, recvm, recvkb, time) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "211".into_string(),
                 args: vec!(
@@ -926,6 +1020,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_STATSCOMMANDS(command, count, bcount, rcount) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "212".into_string(),
                 args: vec!(
@@ -938,6 +1033,7 @@ impl<'a> NumericReply<'a> {
                 suffix: None
             },
             RPL_ENDOFSTATS(letter) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "219".into_string(),
                 args: vec!(
@@ -947,12 +1043,14 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("End of STATS report".into_string())
             },
             RPL_STATSUPTIME(d, h, m, s) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "242".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(format!("Server Up {} days {}:{:02u}:{:02u}", d, h, m, s))
             },
             RPL_STATSOLINE(hostmask, name) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "243".into_string(),
                 args: vec!(
@@ -964,8 +1062,58 @@ impl<'a> NumericReply<'a> {
                 ),
                 suffix: None
             },
+            RPL_STATSYLINE(class, ping_freq, max_clients, sendq, recvq) => IRCMessage {
+                tags: Vec::new(),
+                prefix: None,
+                command: "218".into_string(),
+                args: vec!(
+                    usrnick.into_string(),
+                    "Y".into_string(),
+                    class.into_string(),
+                    ping_freq.to_string(),
+                    "0".into_string(),
+                    max_clients.to_string(),
+                    sendq.to_string(),
+                    recvq.to_string()
+                ),
+                suffix: None
+            },
+            RPL_STATSKLINE(mask, reason) => IRCMessage {
+                tags: Vec::new(),
+                prefix: None,
+                command: "216".into_string(),
+                args: vec!(
+                    usrnick.into_string(),
+                    "K".into_string(),
+                    mask.into_string()
+                ),
+                suffix: Some(reason.into_string())
+            },
+            RPL_STATSQLINE(mask, reason) => IRCMessage {
+                tags: Vec::new(),
+                prefix: None,
+                command: "217".into_string(),
+                args: vec!(
+                    usrnick.into_string(),
+                    "Q".into_string(),
+                    mask.into_string()
+                ),
+                suffix: Some(reason.into_string())
+            },
+            RPL_STATSDLINE(mask, reason) => IRCMessage {
+                tags: Vec::new(),
+                prefix: None,
+                command: "225".into_string(),
+                args: vec!(
+                    usrnick.into_string(),
+                    "D".into_string(),
+                    mask.into_string()
+                ),
+                suffix: Some(reason.into_string())
+            },
             // Client mode
             RPL_UMODEIS(modes) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "221".into_string(),
                 args: vec!(usrnick.into_string()),
@@ -973,6 +1121,7 @@ impl<'a> NumericReply<'a> {
             },
             // Misc
             RPL_TRYAGAIN(command) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "263".into_string(),
                 args: vec!(
@@ -981,10 +1130,51 @@ impl<'a> NumericReply<'a> {
                 ),
                 suffix: Some("Please wait a while and try again.".into_string())
             },
+            // Monitor related
+            RPL_MONONLINE(targets) => IRCMessage {
+                tags: Vec::new(),
+                prefix: None,
+                command: "730".into_string(),
+                args: vec!(usrnick.into_string()),
+                suffix: Some(targets.connect(","))
+            },
+            RPL_MONOFFLINE(targets) => IRCMessage {
+                tags: Vec::new(),
+                prefix: None,
+                command: "731".into_string(),
+                args: vec!(usrnick.into_string()),
+                suffix: Some(targets.connect(","))
+            },
+            RPL_MONLIST(targets) => IRCMessage {
+                tags: Vec::new(),
+                prefix: None,
+                command: "732".into_string(),
+                args: vec!(usrnick.into_string()),
+                suffix: Some(targets.connect(","))
+            },
+            RPL_ENDOFMONLIST => IRCMessage {
+                tags: Vec::new(),
+                prefix: None,
+                command: "733".into_string(),
+                args: vec!(usrnick.into_string()),
+                suffix: Some("End of MONITOR list".into_string())
+            },
+            ERR_MONLISTFULL(limit, targets) => IRCMessage {
+                tags: Vec::new(),
+                prefix: None,
+                command: "734".into_string(),
+                args: vec!(
+                    usrnick.into_string(),
+                    limit.to_string(),
+                    targets.into_string()
+                ),
+                suffix: Some("Monitor list is full.".into_string())
+            },
             //
             // Errors
             //
             ERR_NOSUCHNICK(nick) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "401".into_string(),
                 args: vec!(
@@ -994,6 +1184,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("No such nick/channel".into_string())
             },
             ERR_NOSUCHSERVER(srv) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "402".into_string(),
                 args: vec!(
@@ -1003,6 +1194,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("No such server".into_string())
             },
             ERR_NOSUCHCHANNEL(chan) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "403".into_string(),
                 args: vec!(
@@ -1012,6 +1204,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("No such channel".into_string())
             },
             ERR_CANNOTSENDTOCHAN(chan) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "404".into_string(),
                 args: vec!(
@@ -1021,6 +1214,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Cannot send to channel".into_string())
             },
             ERR_TOOMANYCHANNELS(chan) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "405".into_string(),
                 args: vec!(
@@ -1030,6 +1224,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("You have joined too many channels".into_string())
             },
             ERR_WASNOSUCHNICK(nick) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "406".into_string(),
                 args: vec!(
@@ -1039,6 +1234,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("There was no such nickname".into_string())
             },
             ERR_TOOMANYTARGETS(target, err_code, msg) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "407".into_string(),
                 args: vec!(
@@ -1048,6 +1244,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some(format!("{} recipients. {}", err_code, msg))
             },
             ERR_NOSUCHSERVICE(service) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "408".into_string(),
                 args: vec!(
@@ -1057,12 +1254,24 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("No such service".into_string())
             },
             ERR_NOORIGIN => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "409".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("No origin specified".into_string())
             },
+            ERR_INVALIDCAPCMD(command) => IRCMessage {
+                tags: Vec::new(),
+                prefix: None,
+                command: "410".into_string(),
+                args: vec!(
+                    usrnick.into_string(),
+                    command.into_string()
+                ),
+                suffix: Some("Invalid CAP command".into_string())
+            },
             ERR_UNKNOWNCOMMAND(command) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "421".into_string(),
                 args: vec!(
@@ -1072,12 +1281,14 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Unknown command".into_string())
             },
             ERR_NOMOTD => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "422".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("MOTD file is missing".into_string())
             },
             ERR_NOADMININFO(server) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "423".into_string(),
                 args: vec!(
@@ -1087,12 +1298,14 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("No administrative info available".into_string())
             },
             ERR_FILEERROR(op, file) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "424".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(format!("File error doing {} on {}", op, file))
             },
             ERR_UNAVAILRESOURCE(name) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "437".into_string(),
                 args: vec!(
@@ -1103,18 +1316,21 @@ impl<'a> NumericReply<'a> {
             },
             // PRIVMSG_ERR
             ERR_NORECIPIENT(command) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "411".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some(format!("No recipient given ({})", command))
             },
             ERR_NOTEXTTOSEND => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "412".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("No text to send".into_string())
             },
             ERR_NOTOPLEVEL(mask) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "413".into_string(),
                 args: vec!(
@@ -1124,6 +1340,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("No toplevel specified".into_string())
             },
             ERR_WILDTOLEVEL(mask) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "414".into_string(),
                 args: vec!(
@@ -1133,6 +1350,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Wildcard in toplevel domain".into_string())
             },
             ERR_BADMASK(mask) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "415".into_string(),
                 args: vec!(
@@ -1143,12 +1361,14 @@ impl<'a> NumericReply<'a> {
             },
             // Nick Related
             ERR_NONICKNAMEGIVEN => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "432".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("No nickname given".into_string())
             },
             ERR_ERRONEUSNICKNAME(nick) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "432".into_string(),
                 args: vec!(
@@ -1158,6 +1378,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Erroneous nickname".into_string())
             },
             ERR_NICKNAMEINUSE(nick) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "433".into_string(),
                 args: vec!(
@@ -1167,6 +1388,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Nickname is already in use".into_string())
             },
             ERR_NICKCOLLISION(nick, user, host) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "436".into_string(),
                 args: vec!(
@@ -1177,6 +1399,7 @@ impl<'a> NumericReply<'a> {
             },
             // Chan related
             ERR_USERNOTINCHANNEL(nick, channel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "441".into_string(),
                 args: vec!(
@@ -1187,6 +1410,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("They aren't on that channel".into_string())
             },
             ERR_NOTONCHANNEL(channel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "442".into_string(),
                 args: vec!(
@@ -1196,6 +1420,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("You're not on that channel".into_string())
             },
             ERR_USERONCHANNEL(nick, channel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "443".into_string(),
                 args: vec!(
@@ -1206,6 +1431,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("is already on channel".into_string())
             },
             ERR_NOLOGIN(user) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "444".into_string(),
                 args: vec!(
@@ -1215,6 +1441,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("User not logged in".into_string())
             },
             ERR_KEYSET(channel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "467".into_string(),
                 args: vec!(
@@ -1224,6 +1451,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Key already set".into_string())
             },
             ERR_CHANNELISFULL(channel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "471".into_string(),
                 args: vec!(
@@ -1233,6 +1461,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Cannot join channel (+l)".into_string())
             },
             ERR_UNKNOWNMODE(mode, channel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "471".into_string(),
                 args: vec!(
@@ -1242,6 +1471,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some(format!("is unknown mode char to me for {}", channel))
             },
             ERR_INVITEONLYCHAN(channel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "473".into_string(),
                 args: vec!(
@@ -1251,6 +1481,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Cannot join channel (+i)".into_string())
             },
             ERR_BANNEDFROMCHAN(channel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "474".into_string(),
                 args: vec!(
@@ -1260,6 +1491,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Cannot join channel (+b)".into_string())
             },
             ERR_BADCHANNELKEY(channel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "475".into_string(),
                 args: vec!(
@@ -1269,6 +1501,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Cannot join channel (+b)".into_string())
             },
             ERR_BADCHANMASK(channel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "476".into_string(),
                 args: vec!(
@@ -1278,6 +1511,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Bad channel mask".into_string())
             },
             ERR_NOCHANMODES(channel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "477".into_string(),
                 args: vec!(
@@ -1287,6 +1521,7 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Channel doesn't support modes".into_string())
             },
             ERR_BANLISTFULL(channel, mode) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "478".into_string(),
                 args: vec!(
@@ -1298,24 +1533,28 @@ impl<'a> NumericReply<'a> {
             },
             // NotAlloed related
             ERR_SUMMONDISABLED => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "445".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("SUMMON has been disabled".into_string())
             },
             ERR_USERSDISABLED => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "446".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("USERS has been disabled".into_string())
             },
             ERR_NOTREGISTERED => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "451".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("You have not registered".into_string())
             },
             ERR_NEEDMOREPARAMS(command) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "461".into_string(),
                 args: vec!(
@@ -1325,42 +1564,49 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("Not enough parameters".into_string())
             },
             ERR_ALREADYREGISTERED => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "462".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("Unauthorized command (already registered)".into_string())
             },
             ERR_NOPERMFORHOST => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "463".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("Your host isn't among the privileged".into_string())
             },
             ERR_PASSWDMISMATCH => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "464".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("Password incorrect".into_string())
             },
             ERR_YOURBANNEDCREEP => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "465".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("You are banned from this server".into_string())
             },
             ERR_YOUWILLBEBANNED => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "466".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: None
             },
             ERR_NOPRIVILIGES => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "481".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("Permission Denied- You're not an IRC operator".into_string())
             },
             ERR_CHANOPRIVSNEEDED(channel) => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "482".into_string(),
                 args: vec!(
@@ -1370,36 +1616,42 @@ impl<'a> NumericReply<'a> {
                 suffix: Some("You're not channel operator".into_string())
             },
             ERR_CANTKILLSERVER => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "483".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("You can't kill a server!".into_string())
             },
             ERR_RESTRICTED => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "484".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("Your connection is restricted!".into_string())
             },
             ERR_UNIQOPPRIVSNEEDED => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "485".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("You're not the original channel operator".into_string())
             },
             ERR_NOOPERHOST => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "491".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("No O-lines for your host".into_string())
             },
             ERR_UMODEUNKNOWNFLAG => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "501".into_string(),
                 args: vec!(usrnick.into_string()),
                 suffix: Some("Unknown MODE flag".into_string())
             },
             ERR_USERSDONTMATCH => IRCMessage {
+                tags: Vec::new(),
                 prefix: None,
                 command: "502".into_string(),
                 args: vec!(usrnick.into_string()),
@@ -5,6 +5,7 @@
 use uuid::Uuid;
 
 use super::IRCMessage;
+use users::UserData;
 
 /// Represents an entity which can send or receive messages.
 #[deriving(Clone)]
@@ -28,25 +29,65 @@ impl Actor {
     }
 }
 
-/// Represents a text message, either a PRIVMSG or a NOTICE.
+/// The commands carrying a message from a user.
+#[experimental]
+#[deriving(PartialEq, Clone, Show)]
+pub enum TextKind {
+    Privmsg,
+    Notice,
+    /// A message made of tags only.
+    Tagmsg
+}
+
+impl TextKind {
+    /// The IRC command of this kind of message.
+    pub fn command(&self) -> &'static str {
+        match *self {
+            Privmsg => "PRIVMSG",
+            Notice => "NOTICE",
+            Tagmsg => "TAGMSG"
+        }
+    }
+}
+
+/// Represents a text message, either a PRIVMSG, a NOTICE or a TAGMSG.
 #[experimental]
 pub struct TextMessage {
-    pub notice: bool,
+    pub kind: TextKind,
     pub source: Actor,
     pub target: Actor,
-    pub text: String
+    /// The text of the message, empty for a TAGMSG.
+    pub text: String,
+    /// The tags relayed with the message: the client-only tags (prefixed with `+`)
+    /// and the tags added by the server, like `msgid`.
+    pub tags: Vec<(String, String)>
 }
 
 impl TextMessage {
     /// Generates the appropriate protocol text.
-    /// Returns None if either source or target is a non-existing user.
     pub fn into_ircmessage(self) -> IRCMessage {
         IRCMessage {
+            tags: self.tags,
             prefix: Some(self.source.into_text()),
-            command: if self.notice { "NOTICE".to_string() } else { "PRIVMSG".to_string() },
+            command: self.kind.command().to_string(),
             args: vec!(self.target.into_text()),
-            suffix: Some(self.text)
+            suffix: if self.kind == Tagmsg { None } else { Some(self.text) }
         }
     }
 
-}
\ No newline at end of file
+    /// The message as sent to given recipient, with given prefix and target. The tags are
+    /// only sent to the recipients which enabled `message-tags`, and a TAGMSG is not sent
+    /// at all to the others.
+    pub fn message_for(&self, prefix: String, target: String, recipient: &UserData) -> Option<IRCMessage> {
+        let with_tags = recipient.has_capability("message-tags");
+        if self.kind == Tagmsg && !with_tags { return None; }
+        Some(IRCMessage {
+            tags: if with_tags { self.tags.clone() } else { Vec::new() },
+            prefix: Some(prefix),
+            command: self.kind.command().to_string(),
+            args: vec!(target),
+            suffix: if self.kind == Tagmsg { None } else { Some(self.text.clone()) }
+        })
+    }
+
+}
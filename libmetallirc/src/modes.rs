@@ -88,12 +88,122 @@ impl Clone for Modes {
     }
 }
 
+/// Rank of the voiced members.
+#[experimental]
+pub const VOICE_RANK: uint = 10;
+/// Rank of the half-operators, the lowest rank allowed to manage a channel.
+#[experimental]
+pub const HALFOP_RANK: uint = 20;
+/// Rank of the channel operators.
+#[experimental]
+pub const OP_RANK: uint = 30;
+/// Rank of the channel administrators.
+#[experimental]
+pub const ADMIN_RANK: uint = 40;
+/// Rank of the channel owners.
+#[experimental]
+pub const OWNER_RANK: uint = 50;
+
+/// The membership modes shown as a prefix of the nicknames, with their prefix and rank,
+/// the most powerful first.
+#[experimental]
+pub const MEMBERSHIP_PREFIXES: &'static [(char, char, uint)] = &[
+    ('q', '~', OWNER_RANK),
+    ('a', '&', ADMIN_RANK),
+    ('o', '@', OP_RANK),
+    ('h', '%', HALFOP_RANK),
+    ('v', '+', VOICE_RANK)
+];
+
+/// The rank of the most powerful membership mode, 0 if none.
+#[experimental]
+pub fn membership_rank(m: &Modes) -> uint {
+    MEMBERSHIP_PREFIXES.iter().find(|&&(mode, _, _)| m.get(mode.to_ascii())).map_or(0, |&(_, _, rank)| rank)
+}
+
+/// Whether a member of rank `asker` can give (`set`) or take the membership mode of rank
+/// `mode` to a member of rank `target`, which is itself if `own`. Anyone can give up its
+/// own status, otherwise halfops can only voice, and the members of a higher rank cannot
+/// be changed.
+#[experimental]
+pub fn can_change_membership(asker: uint, mode: uint, set: bool, target: uint, own: bool) -> bool {
+    (!set && own) || (asker >= mode && (asker >= OP_RANK || mode < HALFOP_RANK) && target <= asker)
+}
+
+/// Whether a member of rank `asker` can kick a member of rank `target`: halfops can,
+/// unless the target has a higher rank.
+#[experimental]
+pub fn can_kick(asker: uint, target: uint) -> bool {
+    asker >= HALFOP_RANK && target <= asker
+}
+
+/// The prefix of the most powerful membership mode, if any.
+#[experimental]
 pub fn letter_for_membership(m: &Modes) -> Option<char> {
-    if m.get('o'.to_ascii()) {
-        Some('@')
-    } else if m.get('v'.to_ascii()) {
-        Some('+')
+    MEMBERSHIP_PREFIXES.iter().find(|&&(mode, _, _)| m.get(mode.to_ascii())).map(|&(_, prefix, _)| prefix)
+}
+
+/// The prefixes of all the membership modes, the most powerful first.
+#[experimental]
+pub fn prefixes_for_membership(m: &Modes) -> String {
+    MEMBERSHIP_PREFIXES.iter().filter(|&&(mode, _, _)| m.get(mode.to_ascii()))
+                       .map(|&(_, prefix, _)| prefix).collect()
+}
+
+/// The prefix shown for a membership: all the prefixes if `multi`
+/// (for clients having enabled `multi-prefix`), the most powerful one otherwise.
+#[experimental]
+pub fn membership_prefix(m: &Modes, multi: bool) -> String {
+    if multi {
+        prefixes_for_membership(m)
     } else {
-        None
+        letter_for_membership(m).map_or(String::new(), |c| c.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{can_change_membership, can_kick, VOICE_RANK, HALFOP_RANK, OP_RANK, ADMIN_RANK, OWNER_RANK};
+
+    #[test]
+    fn test_give_up_own_status() {
+        assert!(can_change_membership(OP_RANK, OP_RANK, false, OP_RANK, true));
+        assert!(can_change_membership(VOICE_RANK, VOICE_RANK, false, VOICE_RANK, true));
+        // but not take more
+        assert!(!can_change_membership(VOICE_RANK, OP_RANK, true, VOICE_RANK, true));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_halfop_only_voices() {
+        assert!(can_change_membership(HALFOP_RANK, VOICE_RANK, true, 0, false));
+        assert!(can_change_membership(HALFOP_RANK, VOICE_RANK, false, VOICE_RANK, false));
+        assert!(!can_change_membership(HALFOP_RANK, HALFOP_RANK, true, 0, false));
+        assert!(!can_change_membership(HALFOP_RANK, OP_RANK, true, 0, false));
+        assert!(!can_change_membership(VOICE_RANK, VOICE_RANK, true, 0, false));
+    }
+
+    #[test]
+    fn test_higher_rank_unchanged() {
+        assert!(!can_change_membership(OP_RANK, VOICE_RANK, true, ADMIN_RANK, false));
+        assert!(!can_change_membership(ADMIN_RANK, OP_RANK, false, OWNER_RANK, false));
+        assert!(can_change_membership(OP_RANK, OP_RANK, false, OP_RANK, false));
+    }
+
+    #[test]
+    fn test_op_cannot_give_admin() {
+        assert!(can_change_membership(OP_RANK, OP_RANK, true, 0, false));
+        assert!(!can_change_membership(OP_RANK, ADMIN_RANK, true, 0, false));
+        assert!(can_change_membership(OWNER_RANK, ADMIN_RANK, true, OP_RANK, false));
+    }
+
+    #[test]
+    fn test_kick() {
+        assert!(can_kick(HALFOP_RANK, 0));
+        assert!(can_kick(OP_RANK, OP_RANK));
+        assert!(!can_kick(VOICE_RANK, 0));
+        assert!(!can_kick(0, 0));
+        assert!(!can_kick(HALFOP_RANK, OP_RANK));
+        assert!(!can_kick(OP_RANK, OWNER_RANK));
+    }
+}
@@ -2,107 +2,144 @@
 
 //! Here is the machinery handling the module system.
 //!
-//! A module can implement two traits (even both):
+//! A module is a dynamic library exporting its ABI identity and an init function,
+//! both generated by the `declare_module!(..)` macro:
 //!
-//! - `CommandHandler` if it handles one or more commands
+//! ```
+//! declare_module!("mymodule", "0.1.0", ["core"], init)
+//!
+//! fn init(reg: &mut ModuleRegistrar, conf: &toml::TomlTable, logger: &Logger) {
+//!     reg.add_command_handler("CmdFoo", &["FOO"], PRIORITY_NORMAL, CmdFoo);
+//! }
+//! ```
+//!
+//! A module is only loaded once all the modules it depends on are.
+//!
+//! The init function registers the handlers of the module to the `ModuleRegistrar`,
+//! each one under the name of the traitment unit providing it, and with a priority
+//! giving its place in the call order:
+//!
+//! - `CommandHandler` if it handles one or more commands, registered by name
+//! - `PreCommandHook` and `PostCommandHook` if it needs to see all the commands.
 //! - `MessageSendingHandler` if it affects the transmission of a message.
+//! - `UserModeHandler` and `ChannelModeHandler` if it handles some modes.
+//! - `EventHandler` if it reacts to the life of users and channels.
+//! - `ConfigReloadHandler` if its configuration can be reloaded by a rehash.
+//! - `UnloadHandler` if it has some state to clean or hand off when unloaded.
 //!
-//! Such traits must be declared using the `module!(..)` macro like this:
+//! A unit implementing several traits can be shared between its handlers by wrapping
+//! it in an `Arc`.
 //!
-//! ```
-//! module!(MyModule is CommandHandler, MessageSendingHandler)
-//! ```
+//! The types a module stores in the `Extensions` of users, channels and memberships
+//! must be declared to the registrar as well.
+//!
+//! The modes handled by the mode handlers are advertised to the clients on connection,
+//! along with the ISUPPORT tokens registered by the modules.
 //!
-//! These are not *real* modules in the sense of they are not dynamically loaded,
-//! but they provide a common generic interface for handling commands and messages
-//! in an elegant way.
+//! Modules can run code later through `srv.timers`, using their name as owner: their
+//! timers are cancelled when they are unloaded. Whoever unloads a module must suspend
+//! its timers first, see `TimerService::suspend_owner`.
 
 #![experimental]
 
-use logging::{Logger, Debug, Error, Info};
+use extensions::{Extensions, ExtensionOwner, SerializableExtension};
+use extensions::{apply_to_all_extensions, save_extension, restore_extension};
+use logging::{Logger, Debug, Error, Info, Warning};
 use messages::{IRCMessage, TextMessage, numericreply};
 use ServerData;
-use users::UserData;
+use users::{UserData, SERVER_CAPABILITIES};
 use channels::Membership;
 
 use uuid::Uuid;
 use toml;
 
-use std::ascii::Ascii;
+use std::ascii::{Ascii, AsciiExt};
+use std::collections::{HashMap, TreeMap};
+use std::collections::hash_map::{Occupied, Vacant};
+use std::cmp::Equal;
 use std::dynamic_lib::DynamicLibrary;
+use std::intrinsics::TypeId;
+use std::mem::transmute;
 use std::slice::Items;
+use std::cell::UnsafeCell;
+use std::kinds::marker::NoSend;
+use std::sync::{Arc, Mutex};
 
-//
-//
-// Dangerous Zone
-//
-// Here lies some pretty nasty magic needed for downcasting into their traits.
-// This should probably not change util DST a completely finished in Rust language.
-//
+/// Version of the interface between the server and its modules.
+///
+/// It must be incremented at each change of the module API (handler traits, registrar,
+/// and all the types they use).
+#[experimental]
+pub const ABI_VERSION: u32 = 16;
 
-use std::intrinsics::TypeId;
-use std::mem::{size_of, transmute};
-use std::raw::TraitObject;
+include!(concat!(env!("OUT_DIR"), "/build_id.rs"))
 
-#[doc(hidden)]
-pub trait Module: 'static {
-    // HACK(eddyb) Missing upcast to Any to make this clean.
-    fn get_type_id(&self) -> TypeId { TypeId::of::<&'static Self>() }
-    fn get_vtable_for_trait(&self, _trait_id: TypeId) -> Option<&'static ()> { None }
-}
+/// Identity of the interface between the server and its modules, exported by each module.
+/// Modules with another identity are refused at load time: as the interface is made of
+/// Rust types and functions, it changes with the API version, but also with the versions
+/// of rustc and libmetallirc.
+#[experimental]
+pub const ABI_ID: u64 = BUILD_ID ^ ABI_VERSION as u64;
 
+/// Declares the module of the crate, with its name, version, the names of the
+/// modules it depends on, and the function registering its handlers.
 #[macro_export]
-macro_rules! module {
-    ($ty:ty is $($Trait:ty),+) => (
-        impl ::metallirc::modules::Module for $ty {
-            fn get_vtable_for_trait(&self, trait_id: ::std::intrinsics::TypeId) -> Option<&'static ()> {
-                $(if trait_id == ::std::intrinsics::TypeId::of::<&'static $Trait>() {
-                    Some(unsafe {&*::std::mem::transmute::<&$Trait, ::std::raw::TraitObject>(self).vtable})
-                })else+ else {
-                    None
-                }
+macro_rules! declare_module {
+    ($name:expr, $version:expr, [$($dep:expr),*], $init:ident) => (
+        #[no_mangle]
+        pub static METALLIRC_ABI_ID: u64 = ::metallirc::modules::ABI_ID;
+
+        #[no_mangle]
+        pub fn metallirc_module_descriptor() -> ::metallirc::modules::ModuleDescriptor {
+            ::metallirc::modules::ModuleDescriptor {
+                name: $name.to_string(),
+                version: $version.to_string(),
+                dependencies: vec!($($dep.to_string()),*),
+                handlers: Vec::new()
             }
         }
+
+        #[no_mangle]
+        pub fn metallirc_module_init(conf: &::toml::TomlTable, logger: &::metallirc::logging::Logger)
+            -> ::metallirc::modules::ModuleRegistrar {
+            let mut registrar = ::metallirc::modules::ModuleRegistrar::new(metallirc_module_descriptor());
+            $init(&mut registrar, conf, logger);
+            registrar
+        }
     )
 }
 
-#[doc(hidden)]
-pub trait ModuleRef<'a> {
-    fn as_ref<Sized? T>(self) -> Option<&'a T>;
-}
+/// Priority of a handler: handlers with lower priorities are called first. Between
+/// handlers of same priority, the ones of the most recently loaded module come first.
+#[experimental]
+pub type Priority = i32;
 
-impl<'a> ModuleRef<'a> for &'a Module+'static {
-    fn as_ref<Sized? T:'static>(self) -> Option<&'a T> {
-        let type_id = TypeId::of::<&'static T>();
-        unsafe {
-            let obj = transmute::<_, TraitObject>(self);
-            if size_of::<*const T>() == size_of::<uint>() {
-                if self.get_type_id() == type_id {
-                    Some(*transmute::<_, &&T>(&obj.data))
-                } else {
-                    None
-                }
-            } else {
-                self.get_vtable_for_trait(type_id).map(|vtable| {
-                    *transmute::<_, &&T>(&TraitObject {
-                        data: obj.data,
-                        vtable: vtable as *const _ as *mut _
-                    })
-                })
-            }
-        }
-    }
-}
+/// For handlers which must see everything before the others (filters, ...).
+#[experimental]
+pub const PRIORITY_FIRST: Priority = -100;
+/// The usual priority.
+#[experimental]
+pub const PRIORITY_NORMAL: Priority = 0;
+/// For handlers consuming what they handle (delivering messages, ...).
+#[experimental]
+pub const PRIORITY_LAST: Priority = 100;
 
-#[macro_export]
-macro_rules! init_modules {
-    ($($m:expr),+) => {
-        vec!($(box $m as Box<Module + Send + Sync>),+)
-    }
+/// The kinds of channel modes, as advertised in the CHANMODES and PREFIX ISUPPORT tokens.
+#[experimental]
+#[deriving(PartialEq, Clone)]
+pub enum ChanModeKind {
+    /// A mode managing a list of masks, like bans (type A).
+    ListMode,
+    /// A setting always taking a parameter (type B).
+    ParamAlways,
+    /// A setting taking a parameter only when set (type C).
+    ParamWhenSet,
+    /// A setting without parameter (type D).
+    NoParam,
+    /// A status given to a member, with its prefix symbol and its rank, the
+    /// higher ranks being the more powerful.
+    PrefixMode(char, uint)
 }
-//
-// End of Dangerous Zone
-//
 
 /// Special actions to be performed by the recycler thread (requiring `&mut` access to the UserManager).
 #[experimental]
@@ -113,19 +150,47 @@ pub enum RecyclingAction {
     /// a nick change is requested
     ChangeNick(String),
     /// the user should be zombified
-    Zombify
+    Zombify,
+    /// the given users should be disconnected for given reason
+    Kill(Vec<Uuid>, String),
+    /// the given module should be loaded
+    LoadModule(String),
+    /// the given module should be unloaded
+    UnloadModule(String),
+    /// the given module should be reloaded
+    ReloadModule(String),
+    /// the username and hostname of given user should be changed
+    ChangeHost(Uuid, String, String)
 }
 
 /// A trait for modules handling commands.
 #[experimental]
 pub trait CommandHandler : Send + Sync {
-    /// Tries to handle the command. Returns true if the command was matched and handled,
-    /// and false if this handler don't handle this command.
+    /// Tries to handle the command. Returns true if the command was handled, and false
+    /// to let the next handler registered for this command try it.
     #[experimental]
     fn handle_command(&self, user: &UserData, user_uuid: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction);
 }
 
+/// A trait for modules needing to see the commands before they are handled.
+#[experimental]
+pub trait PreCommandHook : Send + Sync {
+    /// Called before any command is handled, even unknown ones. Returning false stops
+    /// the processing of the command, the hook is then responsible for any reply.
+    #[experimental]
+    fn before_command(&self, user: &UserData, user_uuid: &Uuid, cmd: &IRCMessage, srv: &ServerData) -> bool;
+}
+
+/// A trait for modules needing to see the commands after they are handled.
+#[experimental]
+pub trait PostCommandHook : Send + Sync {
+    /// Called after a command was handled, with the action its handler requested.
+    #[experimental]
+    fn after_command(&self, user: &UserData, user_uuid: &Uuid, cmd: &IRCMessage,
+                     action: &RecyclingAction, srv: &ServerData);
+}
+
 /// A trait for modules altering the sending of a text message from a user.
 #[experimental]
 pub trait MessageSendingHandler : Send + Sync {
@@ -135,6 +200,80 @@ pub trait MessageSendingHandler : Send + Sync {
     fn handle_message_sending(&self, msg: TextMessage, srv: &ServerData) -> Option<TextMessage>;
 }
 
+/// A trait for modules whose configuration can be reloaded.
+///
+/// A reload is done in two steps: all the modules check their new configuration, and
+/// it is only applied if none of them rejected it.
+#[experimental]
+pub trait ConfigReloadHandler : Send + Sync {
+    /// Checks the new configuration of the module without applying it. Returns an
+    /// error message if it is invalid.
+    #[experimental]
+    fn check_config(&self, conf: &toml::TomlTable) -> Result<(), String>;
+    /// Applies the new configuration of the module, once all the modules accepted theirs.
+    /// If it turns out to be invalid anyway, nothing should be changed and an error
+    /// message returned.
+    #[experimental]
+    fn reload_config(&self, conf: &toml::TomlTable, logger: &Logger) -> Result<(), String>;
+}
+
+/// A trait for modules needing to be warned before being unloaded.
+#[experimental]
+pub trait UnloadHandler : Send + Sync {
+    /// Called before the module is unloaded. When `reloading`, the returned state is
+    /// given back to the same unit of the new instance of the module.
+    #[experimental]
+    fn pre_unload(&self, reloading: bool, srv: &ServerData) -> Option<String>;
+    /// Restores the state saved by the previous instance of this unit.
+    #[experimental]
+    fn restore_state(&self, _state: String, _srv: &ServerData) {}
+}
+
+/// A trait for modules reacting to events of the server. All methods do nothing by
+/// default, so that handlers only implement the events they need.
+///
+/// The `before_*` events allow the handler to forbid the action by returning false,
+/// it is then responsible for telling the user why.
+#[experimental]
+pub trait EventHandler : Send + Sync {
+    /// A user finished its registration.
+    #[experimental]
+    fn on_register(&self, _user: &UserData, _user_uuid: &Uuid, _srv: &ServerData) {}
+    /// A user changed its nick.
+    #[experimental]
+    fn on_nick_change(&self, _user: &UserData, _old_nick: &str, _srv: &ServerData) {}
+    /// A user is about to join a chan.
+    #[experimental]
+    fn before_join(&self, _user: &UserData, _chan: &str, _srv: &ServerData) -> bool { true }
+    /// A user joined a chan.
+    #[experimental]
+    fn on_join(&self, _user: &UserData, _chan: &str, _srv: &ServerData) {}
+    /// A user left a chan.
+    #[experimental]
+    fn on_part(&self, _user: &UserData, _chan: &str, _reason: &str, _srv: &ServerData) {}
+    /// A user is about to kick another one from a chan.
+    #[experimental]
+    fn before_kick(&self, _kicker: &UserData, _victim: &UserData, _chan: &str, _srv: &ServerData) -> bool {
+        true
+    }
+    /// A user was kicked from a chan.
+    #[experimental]
+    fn on_kick(&self, _kicker: &UserData, _victim: &UserData, _chan: &str, _reason: &str, _srv: &ServerData) {}
+    /// A user mode was changed.
+    #[experimental]
+    fn on_usermode_change(&self, _asker: &UserData, _target: &UserData, _flag: Ascii, _set: bool,
+                          _srv: &ServerData) {}
+    /// A channel mode was changed.
+    #[experimental]
+    fn on_chanmode_change(&self, _asker: &Membership, _flag: Ascii, _set: bool, _srv: &ServerData) {}
+    /// A user is about to be destroyed, after its disconnection.
+    #[experimental]
+    fn on_destroy(&self, _user: &UserData, _user_uuid: &Uuid, _srv: &ServerData) {}
+    /// A chan was destroyed, its last member having left.
+    #[experimental]
+    fn on_chan_destroy(&self, _chan: &str, _srv: &ServerData) {}
+}
+
 /// A trait for modules handling user modes.
 #[experimental]
 pub trait UserModeHandler : Send + Sync {
@@ -144,6 +283,8 @@ pub trait UserModeHandler : Send + Sync {
     fn handle_usermode_request(&self, asker: &UserData, target: &UserData,
                                flag: Ascii, set: bool,
                                srv: &ServerData) -> Option<bool>;
+    /// The user modes handled, advertised in RPL_MYINFO.
+    fn usermodes(&self) -> Vec<char> { Vec::new() }
 }
 
 /// A trait for modules handling channel modes.
@@ -156,19 +297,361 @@ pub trait ChannelModeHandler : Send + Sync {
                                flag: Ascii, set: bool,
                                args: &mut Items<String>,
                                srv: &ServerData) -> Option<bool>;
+    /// The channel modes handled with their kinds, advertised in RPL_MYINFO and ISUPPORT.
+    fn chanmodes(&self) -> Vec<(char, ChanModeKind)> { Vec::new() }
+}
+
+// Units shared between several handlers
+
+impl<T: CommandHandler> CommandHandler for Arc<T> {
+    fn handle_command(&self, user: &UserData, user_uuid: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        (**self).handle_command(user, user_uuid, cmd, srv)
+    }
+}
+
+impl<T: PreCommandHook> PreCommandHook for Arc<T> {
+    fn before_command(&self, user: &UserData, user_uuid: &Uuid, cmd: &IRCMessage, srv: &ServerData) -> bool {
+        (**self).before_command(user, user_uuid, cmd, srv)
+    }
+}
+
+impl<T: PostCommandHook> PostCommandHook for Arc<T> {
+    fn after_command(&self, user: &UserData, user_uuid: &Uuid, cmd: &IRCMessage,
+                     action: &RecyclingAction, srv: &ServerData) {
+        (**self).after_command(user, user_uuid, cmd, action, srv)
+    }
+}
+
+impl<T: MessageSendingHandler> MessageSendingHandler for Arc<T> {
+    fn handle_message_sending(&self, msg: TextMessage, srv: &ServerData) -> Option<TextMessage> {
+        (**self).handle_message_sending(msg, srv)
+    }
+}
+
+impl<T: ConfigReloadHandler> ConfigReloadHandler for Arc<T> {
+    fn check_config(&self, conf: &toml::TomlTable) -> Result<(), String> {
+        (**self).check_config(conf)
+    }
+    fn reload_config(&self, conf: &toml::TomlTable, logger: &Logger) -> Result<(), String> {
+        (**self).reload_config(conf, logger)
+    }
+}
+
+impl<T: UnloadHandler> UnloadHandler for Arc<T> {
+    fn pre_unload(&self, reloading: bool, srv: &ServerData) -> Option<String> {
+        (**self).pre_unload(reloading, srv)
+    }
+    fn restore_state(&self, state: String, srv: &ServerData) {
+        (**self).restore_state(state, srv)
+    }
+}
+
+impl<T: EventHandler> EventHandler for Arc<T> {
+    fn on_register(&self, user: &UserData, user_uuid: &Uuid, srv: &ServerData) {
+        (**self).on_register(user, user_uuid, srv)
+    }
+    fn on_nick_change(&self, user: &UserData, old_nick: &str, srv: &ServerData) {
+        (**self).on_nick_change(user, old_nick, srv)
+    }
+    fn before_join(&self, user: &UserData, chan: &str, srv: &ServerData) -> bool {
+        (**self).before_join(user, chan, srv)
+    }
+    fn on_join(&self, user: &UserData, chan: &str, srv: &ServerData) {
+        (**self).on_join(user, chan, srv)
+    }
+    fn on_part(&self, user: &UserData, chan: &str, reason: &str, srv: &ServerData) {
+        (**self).on_part(user, chan, reason, srv)
+    }
+    fn before_kick(&self, kicker: &UserData, victim: &UserData, chan: &str, srv: &ServerData) -> bool {
+        (**self).before_kick(kicker, victim, chan, srv)
+    }
+    fn on_kick(&self, kicker: &UserData, victim: &UserData, chan: &str, reason: &str, srv: &ServerData) {
+        (**self).on_kick(kicker, victim, chan, reason, srv)
+    }
+    fn on_usermode_change(&self, asker: &UserData, target: &UserData, flag: Ascii, set: bool,
+                          srv: &ServerData) {
+        (**self).on_usermode_change(asker, target, flag, set, srv)
+    }
+    fn on_chanmode_change(&self, asker: &Membership, flag: Ascii, set: bool, srv: &ServerData) {
+        (**self).on_chanmode_change(asker, flag, set, srv)
+    }
+    fn on_destroy(&self, user: &UserData, user_uuid: &Uuid, srv: &ServerData) {
+        (**self).on_destroy(user, user_uuid, srv)
+    }
+    fn on_chan_destroy(&self, chan: &str, srv: &ServerData) {
+        (**self).on_chan_destroy(chan, srv)
+    }
+}
+
+impl<T: UserModeHandler> UserModeHandler for Arc<T> {
+    fn handle_usermode_request(&self, asker: &UserData, target: &UserData,
+                               flag: Ascii, set: bool,
+                               srv: &ServerData) -> Option<bool> {
+        (**self).handle_usermode_request(asker, target, flag, set, srv)
+    }
+    fn usermodes(&self) -> Vec<char> {
+        (**self).usermodes()
+    }
+}
+
+impl<T: ChannelModeHandler> ChannelModeHandler for Arc<T> {
+    fn handle_chanmode_request(&self, asker: &Membership,
+                               flag: Ascii, set: bool,
+                               args: &mut Items<String>,
+                               srv: &ServerData) -> Option<bool> {
+        (**self).handle_chanmode_request(asker, flag, set, args, srv)
+    }
+    fn chanmodes(&self) -> Vec<(char, ChanModeKind)> {
+        (**self).chanmodes()
+    }
+}
+
+/// Description of a module.
+#[experimental]
+#[deriving(Clone)]
+pub struct ModuleDescriptor {
+    pub name: String,
+    pub version: String,
+    /// Names of the modules this one needs.
+    pub dependencies: Vec<String>,
+    /// The registered handlers, as `(unit, kind)`.
+    pub handlers: Vec<(String, &'static str)>
+}
+
+/// Collects the handlers a module registers in its init function.
+#[experimental]
+pub struct ModuleRegistrar {
+    descriptor: ModuleDescriptor,
+    commands: Vec<(String, String, Priority, Box<CommandHandler + 'static + Send + Sync>)>,
+    pre_hooks: Vec<(String, Priority, Box<PreCommandHook + 'static + Send + Sync>)>,
+    post_hooks: Vec<(String, Priority, Box<PostCommandHook + 'static + Send + Sync>)>,
+    messages: Vec<(String, Priority, Box<MessageSendingHandler + 'static + Send + Sync>)>,
+    events: Vec<(String, Priority, Box<EventHandler + 'static + Send + Sync>)>,
+    usermodes: Vec<(String, Priority, Box<UserModeHandler + 'static + Send + Sync>)>,
+    chanmodes: Vec<(String, Priority, Box<ChannelModeHandler + 'static + Send + Sync>)>,
+    reloads: Vec<(String, Priority, Box<ConfigReloadHandler + 'static + Send + Sync>)>,
+    unloads: Vec<(String, Priority, Box<UnloadHandler + 'static + Send + Sync>)>,
+    extensions: Vec<ExtensionType>,
+    isupport: Vec<String>,
+    capabilities: Vec<String>
+}
+
+/// A type of extension declared by a module.
+struct ExtensionType {
+    name: String,
+    type_id: TypeId,
+    save: Option<fn(&Extensions) -> Option<String>>,
+    restore: Option<fn(&Extensions, &str)>
+}
+
+impl ModuleRegistrar {
+    /// Creates the registrar of a module, this is done by `declare_module!(..)`.
+    #[experimental]
+    pub fn new(descriptor: ModuleDescriptor) -> ModuleRegistrar {
+        ModuleRegistrar {
+            descriptor: descriptor,
+            commands: Vec::new(),
+            pre_hooks: Vec::new(),
+            post_hooks: Vec::new(),
+            messages: Vec::new(),
+            events: Vec::new(),
+            usermodes: Vec::new(),
+            chanmodes: Vec::new(),
+            reloads: Vec::new(),
+            unloads: Vec::new(),
+            extensions: Vec::new(),
+            isupport: Vec::new(),
+            capabilities: Vec::new()
+        }
+    }
+
+    /// Registers a handler of given unit for the given commands. It will only be called
+    /// for these commands.
+    #[experimental]
+    pub fn add_command_handler<T: CommandHandler + 'static>(&mut self, unit: &str, commands: &[&str],
+                                                            priority: Priority, handler: T) {
+        self.descriptor.handlers.push((unit.to_string(), "command"));
+        let shared = Arc::new(handler);
+        for command in commands.iter() {
+            self.commands.push((command.to_ascii_upper(), unit.to_string(), priority,
+                                box shared.clone() as Box<CommandHandler + 'static + Send + Sync>));
+        }
+    }
+
+    /// Registers a hook of given unit called before each command.
+    #[experimental]
+    pub fn add_pre_command_hook<T: PreCommandHook + 'static>(&mut self, unit: &str, priority: Priority,
+                                                             hook: T) {
+        self.descriptor.handlers.push((unit.to_string(), "pre-command"));
+        self.pre_hooks.push((unit.to_string(), priority,
+                             box hook as Box<PreCommandHook + 'static + Send + Sync>));
+    }
+
+    /// Registers a hook of given unit called after each handled command.
+    #[experimental]
+    pub fn add_post_command_hook<T: PostCommandHook + 'static>(&mut self, unit: &str, priority: Priority,
+                                                               hook: T) {
+        self.descriptor.handlers.push((unit.to_string(), "post-command"));
+        self.post_hooks.push((unit.to_string(), priority,
+                              box hook as Box<PostCommandHook + 'static + Send + Sync>));
+    }
+
+    /// Registers a message sending handler of given unit.
+    #[experimental]
+    pub fn add_message_handler<T: MessageSendingHandler + 'static>(&mut self, unit: &str, priority: Priority,
+                                                                   handler: T) {
+        self.descriptor.handlers.push((unit.to_string(), "message"));
+        self.messages.push((unit.to_string(), priority,
+                            box handler as Box<MessageSendingHandler + 'static + Send + Sync>));
+    }
+
+    /// Registers an event handler of given unit.
+    #[experimental]
+    pub fn add_event_handler<T: EventHandler + 'static>(&mut self, unit: &str, priority: Priority,
+                                                        handler: T) {
+        self.descriptor.handlers.push((unit.to_string(), "event"));
+        self.events.push((unit.to_string(), priority,
+                          box handler as Box<EventHandler + 'static + Send + Sync>));
+    }
+
+    /// Registers a user mode handler of given unit.
+    #[experimental]
+    pub fn add_usermode_handler<T: UserModeHandler + 'static>(&mut self, unit: &str, priority: Priority,
+                                                              handler: T) {
+        self.descriptor.handlers.push((unit.to_string(), "usermode"));
+        self.usermodes.push((unit.to_string(), priority,
+                             box handler as Box<UserModeHandler + 'static + Send + Sync>));
+    }
+
+    /// Registers a channel mode handler of given unit.
+    #[experimental]
+    pub fn add_chanmode_handler<T: ChannelModeHandler + 'static>(&mut self, unit: &str, priority: Priority,
+                                                                 handler: T) {
+        self.descriptor.handlers.push((unit.to_string(), "chanmode"));
+        self.chanmodes.push((unit.to_string(), priority,
+                             box handler as Box<ChannelModeHandler + 'static + Send + Sync>));
+    }
+
+    /// Registers a config reload handler of given unit.
+    #[experimental]
+    pub fn add_config_reload_handler<T: ConfigReloadHandler + 'static>(&mut self, unit: &str, handler: T) {
+        self.descriptor.handlers.push((unit.to_string(), "config"));
+        self.reloads.push((unit.to_string(), PRIORITY_NORMAL,
+                           box handler as Box<ConfigReloadHandler + 'static + Send + Sync>));
+    }
+
+    /// Registers an unload handler of given unit. When the module is reloaded, its saved
+    /// state is given to the handler of the same unit.
+    #[experimental]
+    pub fn add_unload_handler<T: UnloadHandler + 'static>(&mut self, unit: &str, handler: T) {
+        self.descriptor.handlers.push((unit.to_string(), "unload"));
+        self.unloads.push((unit.to_string(), PRIORITY_NORMAL,
+                           box handler as Box<UnloadHandler + 'static + Send + Sync>));
+    }
+
+    /// Adds a token to the ISUPPORT (005) lines sent to the clients on connection.
+    #[experimental]
+    pub fn add_isupport_token(&mut self, token: &str, value: Option<&str>) {
+        self.isupport.push(match value {
+            Some(v) => format!("{}={}", token, v),
+            None => token.to_string()
+        });
+    }
+
+    /// Declares a client capability the module implements, offered to the clients by CAP LS.
+    #[experimental]
+    pub fn add_capability(&mut self, name: &str) {
+        self.capabilities.push(name.to_string());
+    }
+
+    /// Declares a type of extension the module stores in users, channels or memberships.
+    /// All the values of this type are removed before the module is unloaded.
+    #[experimental]
+    pub fn add_extension<T: Send + Sync + 'static>(&mut self, name: &str) {
+        self.descriptor.handlers.push((name.to_string(), "extension"));
+        self.extensions.push(ExtensionType {
+            name: name.to_string(),
+            type_id: TypeId::of::<T>(),
+            save: None,
+            restore: None
+        });
+    }
+
+    /// Declares a type of extension like `add_extension()`, but its values are kept
+    /// when the module is reloaded.
+    #[experimental]
+    pub fn add_serializable_extension<T: SerializableExtension>(&mut self, name: &str) {
+        self.descriptor.handlers.push((name.to_string(), "extension"));
+        self.extensions.push(ExtensionType {
+            name: name.to_string(),
+            type_id: TypeId::of::<T>(),
+            save: Some(save_extension::<T>),
+            restore: Some(restore_extension::<T>)
+        });
+    }
+}
+
+/// A handler, with the module it belongs to.
+struct Registered<Sized? T> {
+    module: String,
+    unit: String,
+    priority: Priority,
+    /// Load rank of the module
+    rank: uint,
+    handler: Box<T>
+}
+
+/// Adds the handlers of a module to a list, keeping it sorted in call order.
+fn insert_handlers<Sized? T>(list: &mut Vec<Registered<T>>, module: &str, rank: uint,
+                             handlers: Vec<(String, Priority, Box<T>)>) {
+    for (unit, priority, handler) in handlers.into_iter() {
+        list.push(Registered {
+            module: module.to_string(),
+            unit: unit,
+            priority: priority,
+            rank: rank,
+            handler: handler
+        });
+    }
+    // the sort is stable, so the registration order is kept between handlers of a module
+    list.sort_by(|a, b| match a.priority.cmp(&b.priority) {
+        Equal => b.rank.cmp(&a.rank),
+        order => order
+    });
 }
 
 /// The modules handler.
 ///
 /// It owns all modules instances and dispatches commands and messages to them.
 pub struct ModulesHandler {
-    libs: Vec<ModuleLib>
+    libs: Vec<ModuleLib>,
+    next_rank: uint,
+    /// The command handlers, by command name
+    commands: HashMap<String, Vec<Registered<CommandHandler + 'static + Send + Sync>>>,
+    pre_hooks: Vec<Registered<PreCommandHook + 'static + Send + Sync>>,
+    post_hooks: Vec<Registered<PostCommandHook + 'static + Send + Sync>>,
+    messages: Vec<Registered<MessageSendingHandler + 'static + Send + Sync>>,
+    events: Vec<Registered<EventHandler + 'static + Send + Sync>>,
+    usermodes: Vec<Registered<UserModeHandler + 'static + Send + Sync>>,
+    chanmodes: Vec<Registered<ChannelModeHandler + 'static + Send + Sync>>,
+    reloads: Vec<Registered<ConfigReloadHandler + 'static + Send + Sync>>,
+    unloads: Vec<Registered<UnloadHandler + 'static + Send + Sync>>,
+    /// The extension types, with the module declaring them
+    extensions: Vec<(String, ExtensionType)>,
+    /// The ISUPPORT tokens, with the module registering them
+    isupport: Vec<(String, String)>,
+    /// The client capabilities, with the module implementing them
+    capabilities: Vec<(String, String)>,
+    /// Number of uses and total size in bytes of the handled commands
+    command_stats: Mutex<HashMap<String, (uint, uint)>>
 }
 
-#[allow(dead_code)] // We reserve these attributes for future use
+#[allow(dead_code)] // The library is only kept to be closed after the handlers are dropped
 struct ModuleLib {
     name: String,
-    modules: Vec<Box<Module + 'static + Send + Sync>>,
+    descriptor: ModuleDescriptor,
+    rank: uint,
     lib: DynamicLibrary
 }
 
@@ -177,63 +660,339 @@ impl ModulesHandler {
     #[experimental]
     pub fn init() -> ModulesHandler {
         // Put the modules here for them to be loaded
-        ModulesHandler { libs: Vec::new() }
+        ModulesHandler {
+            libs: Vec::new(),
+            next_rank: 0,
+            commands: HashMap::new(),
+            pre_hooks: Vec::new(),
+            post_hooks: Vec::new(),
+            messages: Vec::new(),
+            events: Vec::new(),
+            usermodes: Vec::new(),
+            chanmodes: Vec::new(),
+            reloads: Vec::new(),
+            unloads: Vec::new(),
+            extensions: Vec::new(),
+            isupport: Vec::new(),
+            capabilities: Vec::new(),
+            command_stats: Mutex::new(HashMap::new())
+        }
     }
 
-    pub fn open_module(&mut self, name: &str, cfg: &toml::TomlTable, logger: &Logger) {
-        let path = match cfg["path".to_string()] { 
-            toml::String(ref path_str) => match from_str::<Path>(path_str.as_slice()) {
-                Some(p) => p,
-                None => {
-                    logger.log(Error, format!("Invalid path for module {}.", name));
-                    return;
-                }
-            },
-            _ => {
-                logger.log(Error, format!("Invalid path for module {}.", name));
-                return;
+    /// Opens all given modules, each one after the ones it depends on.
+    /// Modules which cannot be loaded are reported in the logs.
+    #[experimental]
+    pub fn open_modules(&mut self, configs: &TreeMap<String, toml::TomlTable>, logger: &Logger) {
+        let mut pending = TreeMap::new();
+        for (name, cfg) in configs.iter() {
+            if self.is_loaded(name.as_slice()) { continue; }
+            match open_lib(name.as_slice(), cfg, logger) {
+                Ok(opened) => { pending.insert(name.clone(), opened); },
+                Err(e) => logger.log(Error, e)
+            }
+        }
+        // load the modules whose dependencies are satisfied until there is none left
+        loop {
+            let ready = pending.iter()
+                               .find(|&(_, &(_, ref desc))| self.dependencies_loaded(desc))
+                               .map(|(name, _)| name.clone());
+            match ready {
+                Some(name) => {
+                    let (lib, descriptor) = pending.remove(&name).unwrap();
+                    let rank = self.next_rank;
+                    self.next_rank += 1;
+                    self.init_lib(name.as_slice(), lib, descriptor, &configs[name], rank, logger);
+                },
+                None => break
             }
+        }
+        for (name, &(_, ref descriptor)) in pending.iter() {
+            logger.log(Error, format!("Module {} was not loaded: {}", name,
+                self.explain_dependencies(descriptor, &pending)));
+        }
+    }
+
+    /// Opens the library of module `name` and initialises its traitment units.
+    /// All its dependencies must already be loaded. On failure, the error is logged and returned.
+    #[experimental]
+    pub fn open_module(&mut self, name: &str, cfg: &toml::TomlTable, logger: &Logger) -> Result<(), String> {
+        if self.is_loaded(name) {
+            return Err(format!("Module {} is already loaded.", name));
+        }
+        let rank = self.next_rank;
+        match self.load_lib(name, cfg, rank, logger) {
+            Ok(()) => { self.next_rank += 1; Ok(()) },
+            Err(e) => { logger.log(Error, e.clone()); Err(e) }
+        }
+    }
+
+    /// Unloads module `name`, calling its pre-unload hooks first.
+    /// A module cannot be unloaded while other modules depend on it.
+    #[experimental]
+    pub fn unload_module(&mut self, name: &str, srv: &ServerData) -> Result<(), String> {
+        let idx = try!(self.find_lib(name));
+        let dependents: Vec<String> = self.libs.iter()
+                                          .filter(|l| l.descriptor.dependencies.iter().any(|d| d.as_slice() == name))
+                                          .map(|l| l.name.clone())
+                                          .collect();
+        if dependents.len() > 0 {
+            return Err(format!("Module {} is needed by: {}.", name, dependents.connect(", ")));
+        }
+        self.close_lib(idx, false, srv);
+        Ok(())
+    }
+
+    /// Reloads module `name` in place, with given config. The state saved by the units
+    /// of the old instance is given back to the units of the same name in the new one,
+    /// and its serializable extensions are restored by type name.
+    ///
+    /// The old library must be closed before opening the new one, so if loading fails,
+    /// the module stays unloaded.
+    #[experimental]
+    pub fn reload_module(&mut self, name: &str, cfg: &toml::TomlTable, srv: &ServerData) -> Result<(), String> {
+        let idx = try!(self.find_lib(name));
+        // keep its place in the dispatch order
+        let rank = self.libs[idx].rank;
+        let (states, extensions) = self.close_lib(idx, true, srv);
+        if let Err(e) = self.load_lib(name, cfg, rank, &srv.logger) {
+            srv.logger.log(Error, e.clone());
+            return Err(format!("{} Module {} is now unloaded.", e, name));
+        }
+        self.restore_extensions(name, extensions, srv);
+        for (unit, state) in states.into_iter() {
+            match self.unloads.iter().find(|r| r.module.as_slice() == name && r.unit == unit) {
+                Some(r) => r.handler.restore_state(state, srv),
+                None => srv.logger.log(Warning,
+                    format!("Unit {} disappeared from module {}, its state is lost.", unit, name))
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists the descriptors of the loaded modules.
+    #[experimental]
+    pub fn list_modules(&self) -> Vec<ModuleDescriptor> {
+        self.libs.iter().map(|l| l.descriptor.clone()).collect()
+    }
+
+    fn is_loaded(&self, name: &str) -> bool {
+        self.libs.iter().any(|l| l.name.as_slice() == name)
+    }
+
+    fn find_lib(&self, name: &str) -> Result<uint, String> {
+        match self.libs.iter().position(|l| l.name.as_slice() == name) {
+            Some(idx) => Ok(idx),
+            None => Err(format!("Module {} is not loaded.", name))
+        }
+    }
+
+    fn dependencies_loaded(&self, descriptor: &ModuleDescriptor) -> bool {
+        descriptor.dependencies.iter().all(|d| self.is_loaded(d.as_slice()))
+    }
+
+    /// Explains why a module waiting for its dependencies cannot be loaded: follows
+    /// its first missing dependency until reaching an unavailable module or a cycle.
+    fn explain_dependencies<'a>(&self, descriptor: &'a ModuleDescriptor,
+                                pending: &'a TreeMap<String, (DynamicLibrary, ModuleDescriptor)>) -> String {
+        let mut path = vec!(descriptor.name.clone());
+        let mut current = descriptor;
+        loop {
+            let dep = match current.dependencies.iter().find(|d| !self.is_loaded(d.as_slice())) {
+                Some(d) => d,
+                None => return "its dependencies could not be loaded.".to_string()
+            };
+            if path.contains(dep) {
+                path.push(dep.clone());
+                return format!("cyclic dependency {}.", path.connect(" -> "));
+            }
+            match pending.get(dep) {
+                Some(&(_, ref desc)) => {
+                    path.push(dep.clone());
+                    current = desc;
+                },
+                None if path.len() == 1 => return format!("missing dependency {}.", dep),
+                None => return format!("dependency {} cannot be loaded, as it needs missing module {}.",
+                                       path[1], dep)
+            }
+        }
+    }
+
+    /// Opens the library of a module, checks its dependencies are loaded and initialises it.
+    fn load_lib(&mut self, name: &str, cfg: &toml::TomlTable, rank: uint, logger: &Logger) -> Result<(), String> {
+        let (lib, descriptor) = try!(open_lib(name, cfg, logger));
+        if let Some(dep) = descriptor.dependencies.iter().find(|d| !self.is_loaded(d.as_slice())) {
+            return Err(format!("Module {} depends on module {}, which is not loaded.", name, dep));
+        }
+        self.init_lib(name, lib, descriptor, cfg, rank, logger);
+        Ok(())
+    }
+
+    /// Initialises an opened library and registers its handlers.
+    fn init_lib(&mut self, name: &str, lib: DynamicLibrary, descriptor: ModuleDescriptor,
+                cfg: &toml::TomlTable, rank: uint, logger: &Logger) {
+        let init = unsafe {
+            // the symbol was checked by open_lib(), and its Rust ABI is the one of
+            // this server as the library has the same ABI identity
+            transmute::<*mut u8, fn(&toml::TomlTable, &Logger) -> ModuleRegistrar>(
+                lib.symbol("metallirc_module_init").unwrap()
+            )
         };
-        logger.log(Info, format!("Opening library {} for module {}.", path.display(), name));
-        match DynamicLibrary::open(Some(path)) {
-            Err(e) => logger.log(Error, e),
-            Ok(lib) => {
-                let handle = unsafe {
-                    match lib.symbol("init") {
-                            Err(e) => { logger.log(Error, e); return },
-                            Ok(f) => {
-                                ::std::mem::transmute::<*mut u8,
-                                    fn(&toml::TomlTable, &Logger) ->
-                                        Vec<Box<Module + 'static + Send + Sync>>
-                                    >(f)
-                            },
-                    }
-                };
-                let new_modules = handle(cfg, logger);
-                logger.log(Info, format!("Loaded {} new traitment units by module {}.",new_modules.len(), name));
-                self.libs.push(
-                    ModuleLib {
-                        name: name.to_string(),
-                        modules: new_modules,
-                        lib: lib
+        let registrar = init(cfg, logger);
+        logger.log(Info, format!("Loaded module {} version {}, with {} handlers.",
+            name, descriptor.version, registrar.descriptor.handlers.len()));
+        let ModuleRegistrar { descriptor: full_descriptor, commands, pre_hooks, post_hooks,
+                              messages, events, usermodes, chanmodes, reloads, unloads,
+                              extensions, isupport, capabilities } = registrar;
+        for (command, unit, priority, handler) in commands.into_iter() {
+            let list = match self.commands.entry(command) {
+                Vacant(entry) => entry.set(Vec::new()),
+                Occupied(entry) => entry.into_mut()
+            };
+            insert_handlers(list, name, rank, vec!((unit, priority, handler)));
+        }
+        insert_handlers(&mut self.pre_hooks, name, rank, pre_hooks);
+        insert_handlers(&mut self.post_hooks, name, rank, post_hooks);
+        insert_handlers(&mut self.messages, name, rank, messages);
+        insert_handlers(&mut self.events, name, rank, events);
+        insert_handlers(&mut self.usermodes, name, rank, usermodes);
+        insert_handlers(&mut self.chanmodes, name, rank, chanmodes);
+        insert_handlers(&mut self.reloads, name, rank, reloads);
+        insert_handlers(&mut self.unloads, name, rank, unloads);
+        for t in extensions.into_iter() {
+            self.extensions.push((name.to_string(), t));
+        }
+        for token in isupport.into_iter() {
+            self.isupport.push((name.to_string(), token));
+        }
+        for cap in capabilities.into_iter() {
+            self.capabilities.push((name.to_string(), cap));
+        }
+        let lib = ModuleLib {
+            name: name.to_string(),
+            descriptor: full_descriptor,
+            rank: rank,
+            lib: lib
+        };
+        // libs are kept in load order
+        let pos = self.libs.iter().position(|l| l.rank > rank).unwrap_or(self.libs.len());
+        self.libs.insert(pos, lib);
+    }
+
+    /// Calls the pre-unload hooks of the units of a library and closes it.
+    /// Returns the states saved by the units, with their names, and when reloading,
+    /// the serializable extensions, as `(type name, data)` by owner.
+    fn close_lib(&mut self, idx: uint, reloading: bool, srv: &ServerData)
+        -> (Vec<(String, String)>, HashMap<ExtensionOwner, Vec<(String, String)>>) {
+        let lib = self.libs.remove(idx).unwrap();
+        let name = lib.name.as_slice();
+        let mut states = Vec::new();
+        for r in self.unloads.iter().filter(|r| r.module.as_slice() == name) {
+            if let Some(state) = r.handler.pre_unload(reloading, srv) {
+                states.push((r.unit.clone(), state));
+            }
+        }
+        let cancelled = srv.timers.cancel_owner(name);
+        if cancelled > 0 {
+            srv.logger.log(Debug, format!("Cancelled {} timers of module {}.", cancelled, name));
+        }
+        // the values of its extensions cannot outlive the library defining their types
+        let mut saved = HashMap::new();
+        {
+            let types: Vec<&ExtensionType> = self.extensions.iter()
+                                                 .filter(|&&(ref m, _)| m.as_slice() == name)
+                                                 .map(|&(_, ref t)| t)
+                                                 .collect();
+            if types.len() > 0 {
+                apply_to_all_extensions(srv, |owner, ext| {
+                    for t in types.iter() {
+                        if reloading {
+                            if let Some(data) = t.save.and_then(|save| save(ext)) {
+                                match saved.entry(owner.clone()) {
+                                    Vacant(entry) => { entry.set(vec!((t.name.clone(), data))); },
+                                    Occupied(mut entry) => entry.get_mut().push((t.name.clone(), data))
+                                }
+                            }
+                        }
+                        ext.remove_type(&t.type_id);
                     }
-                );
+                });
             }
         }
+        self.extensions.retain(|&(ref m, _)| m.as_slice() != name);
+        self.isupport.retain(|&(ref m, _)| m.as_slice() != name);
+        if !reloading {
+            // nobody implements these capabilities anymore
+            for &(_, ref cap) in self.capabilities.iter().filter(|&&(ref m, _)| m.as_slice() == name) {
+                srv.users.read().apply_to_all(|u| { u.capabilities.write().remove(cap); });
+            }
+        }
+        self.capabilities.retain(|&(ref m, _)| m.as_slice() != name);
+        // handlers must be dropped before the library is closed
+        for list in self.commands.values_mut() {
+            list.retain(|r| r.module.as_slice() != name);
+        }
+        let unhandled: Vec<String> = self.commands.iter()
+                                                  .filter(|&(_, list)| list.is_empty())
+                                                  .map(|(command, _)| command.clone())
+                                                  .collect();
+        for command in unhandled.iter() {
+            self.commands.remove(command);
+        }
+        self.pre_hooks.retain(|r| r.module.as_slice() != name);
+        self.post_hooks.retain(|r| r.module.as_slice() != name);
+        self.messages.retain(|r| r.module.as_slice() != name);
+        self.events.retain(|r| r.module.as_slice() != name);
+        self.usermodes.retain(|r| r.module.as_slice() != name);
+        self.chanmodes.retain(|r| r.module.as_slice() != name);
+        self.reloads.retain(|r| r.module.as_slice() != name);
+        self.unloads.retain(|r| r.module.as_slice() != name);
+        srv.logger.log(Info, format!("Unloaded module {}.", name));
+        (states, saved)
+    }
+
+    /// Gives the extensions saved before a reload to the new instance of the module.
+    fn restore_extensions(&self, name: &str, mut saved: HashMap<ExtensionOwner, Vec<(String, String)>>,
+                          srv: &ServerData) {
+        if saved.is_empty() { return; }
+        let types: Vec<&ExtensionType> = self.extensions.iter()
+                                             .filter(|&&(ref m, _)| m.as_slice() == name)
+                                             .map(|&(_, ref t)| t)
+                                             .collect();
+        apply_to_all_extensions(srv, |owner, ext| {
+            if let Some(values) = saved.remove(&owner) {
+                for (type_name, data) in values.into_iter() {
+                    match types.iter().find(|t| t.name == type_name).and_then(|t| t.restore) {
+                        Some(restore) => restore(ext, data.as_slice()),
+                        None => srv.logger.log(Warning, format!(
+                            "Extension {} disappeared from module {}, its data is lost.", type_name, name))
+                    }
+                }
+            }
+        });
     }
 
-    /// Tries all the command handlers in order, stopping as soon as one successfully handles the command.
-    /// Returns false if no appropriate handler was found.
+    /// Passes the command to the pre-command hooks, then tries the handlers registered for
+    /// it in order, stopping as soon as one successfully handles the command, and finally
+    /// to the post-command hooks.
     #[experimental]
-    pub fn handle_command(&self, user: &UserData, user_uuid: &Uuid, cmd: IRCMessage, srv: &ServerData)
+    pub fn handle_command(&self, user: &UserData, user_uuid: &Uuid, mut cmd: IRCMessage, srv: &ServerData)
         -> RecyclingAction {
-        for l in self.libs.iter().rev() {
-            for m in l.modules.iter() {
-                if let Some(handler) = m.as_ref::<CommandHandler>() {
-                    let (done, action) = handler.handle_command(user, user_uuid, &cmd, srv);
-                    if done {
-                        return action;
+        // commands are case-insensitive
+        cmd.command = cmd.command.as_slice().to_ascii_upper();
+        for r in self.pre_hooks.iter() {
+            if !r.handler.before_command(user, user_uuid, &cmd, srv) {
+                return Nothing;
+            }
+        }
+        if let Some(list) = self.commands.get(&cmd.command) {
+            for r in list.iter() {
+                let (done, action) = r.handler.handle_command(user, user_uuid, &cmd, srv);
+                if done {
+                    self.count_command(&cmd);
+                    for hook in self.post_hooks.iter() {
+                        hook.handler.after_command(user, user_uuid, &cmd, &action, srv);
                     }
+                    return action;
                 }
             }
         }
@@ -248,35 +1007,134 @@ impl ModulesHandler {
     /// Sends a message by processing it through all the handlers in order until onrof them consumes it.
     #[experimental]
     pub fn send_message(&self, mut msg: TextMessage, srv: &ServerData) {
-        for l in self.libs.iter().rev() {
-            for m in l.modules.iter() {
-                if let Some(handler) = m.as_ref::<MessageSendingHandler>() {
-                    if let Some(m) = handler.handle_message_sending(msg, srv) {
-                        msg = m;
-                    } else {
-                        return;
-                    }
-                }
+        for r in self.messages.iter() {
+            if let Some(m) = r.handler.handle_message_sending(msg, srv) {
+                msg = m;
+            } else {
+                return;
             }
         }
         // if we reach this point, no handler consumed the message, we drop it.
     }
 
+    /// Tells the event handlers a user finished its registration.
+    #[experimental]
+    pub fn notify_register(&self, user: &UserData, user_uuid: &Uuid, srv: &ServerData) {
+        for r in self.events.iter() {
+            r.handler.on_register(user, user_uuid, srv);
+        }
+    }
+
+    /// Tells the event handlers a user changed its nick.
+    #[experimental]
+    pub fn notify_nick_change(&self, user: &UserData, old_nick: &str, srv: &ServerData) {
+        for r in self.events.iter() {
+            r.handler.on_nick_change(user, old_nick, srv);
+        }
+    }
+
+    /// Asks the event handlers whether a user can join given chan.
+    #[experimental]
+    pub fn allow_join(&self, user: &UserData, chan: &str, srv: &ServerData) -> bool {
+        self.events.iter().all(|r| r.handler.before_join(user, chan, srv))
+    }
+
+    /// Tells the event handlers a user joined a chan.
+    #[experimental]
+    pub fn notify_join(&self, user: &UserData, chan: &str, srv: &ServerData) {
+        for r in self.events.iter() {
+            r.handler.on_join(user, chan, srv);
+        }
+    }
+
+    /// Tells the event handlers a user left a chan.
+    #[experimental]
+    pub fn notify_part(&self, user: &UserData, chan: &str, reason: &str, srv: &ServerData) {
+        for r in self.events.iter() {
+            r.handler.on_part(user, chan, reason, srv);
+        }
+    }
+
+    /// Asks the event handlers whether a user can be kicked from given chan.
+    #[experimental]
+    pub fn allow_kick(&self, kicker: &UserData, victim: &UserData, chan: &str, srv: &ServerData) -> bool {
+        self.events.iter().all(|r| r.handler.before_kick(kicker, victim, chan, srv))
+    }
+
+    /// Tells the event handlers a user was kicked from a chan.
+    #[experimental]
+    pub fn notify_kick(&self, kicker: &UserData, victim: &UserData, chan: &str, reason: &str,
+                       srv: &ServerData) {
+        for r in self.events.iter() {
+            r.handler.on_kick(kicker, victim, chan, reason, srv);
+        }
+    }
+
+    /// Tells the event handlers a user is about to be destroyed.
+    #[experimental]
+    pub fn notify_destroy(&self, user: &UserData, user_uuid: &Uuid, srv: &ServerData) {
+        for r in self.events.iter() {
+            r.handler.on_destroy(user, user_uuid, srv);
+        }
+    }
+
+    /// Tells the event handlers a chan was destroyed.
+    #[experimental]
+    pub fn notify_chan_destroy(&self, chan: &str, srv: &ServerData) {
+        for r in self.events.iter() {
+            r.handler.on_chan_destroy(chan, srv);
+        }
+    }
+
+    /// Checks the new configuration of each module, and gives it to the modules if all of
+    /// them accepted it. Returns the warnings of the reload, or the errors of the modules
+    /// rejecting their configuration, in which case nothing was applied.
+    #[experimental]
+    pub fn reload_config(&self, configs: &TreeMap<String, toml::TomlTable>, logger: &Logger)
+        -> Result<Vec<String>, Vec<String>> {
+        let mut warnings = Vec::new();
+        let mut errors = Vec::new();
+        let mut accepted = Vec::new();
+        for l in self.libs.iter() {
+            let cfg = match configs.get(&l.name) {
+                Some(c) => c,
+                None => {
+                    warnings.push(format!("Module {} is loaded but has no config section, not reloaded.", l.name));
+                    continue;
+                }
+            };
+            for r in self.reloads.iter().filter(|r| r.module == l.name) {
+                match r.handler.check_config(cfg) {
+                    Ok(()) => accepted.push((r, cfg)),
+                    Err(e) => errors.push(format!("Module {} : {}", l.name, e))
+                }
+            }
+        }
+        if errors.len() > 0 { return Err(errors); }
+        for &(r, cfg) in accepted.iter() {
+            if let Err(e) = r.handler.reload_config(cfg, logger) {
+                warnings.push(format!("Module {} : {}", r.module, e));
+            }
+        }
+        Ok(warnings)
+    }
+
     /// Suggests the mode to all available handlers. Returns `Some(true)` if it was
     /// handled, `Some(false)` if it was refused, and `None` if it was unknown.
     #[experimental]
     pub fn handle_usermode(&self, asker: &UserData, target: &UserData,
                            flag: Ascii, set: bool,
                            srv: &ServerData) -> Option<bool> {
-        for l in self.libs.iter().rev() {
-            for m in l.modules.iter() {
-                if let Some(handler) = m.as_ref::<UserModeHandler>() {
-                    let ret = handler.handle_usermode_request(asker, target, flag, set, srv);
-                    if ret.is_some() {
-                        return ret;
-                    }
+        for r in self.usermodes.iter() {
+            let ret = r.handler.handle_usermode_request(asker, target, flag, set, srv);
+            if ret == Some(true) {
+                for e in self.events.iter() {
+                    e.handler.on_usermode_change(asker, target, flag, set, srv);
                 }
             }
+            if ret.is_some() {
+                return ret;
+            }
         }
         // if we reach this point, the mode was not handled
         None
@@ -289,19 +1147,228 @@ impl ModulesHandler {
                               flag: Ascii, set: bool,
                               args: &mut Items<String>,
                               srv: &ServerData) -> Option<bool> {
-        for l in self.libs.iter().rev() {
-            for m in l.modules.iter() {
-                if let Some(handler) = m.as_ref::<ChannelModeHandler>() {
-                    let ret = handler.handle_chanmode_request(membership, flag, set, args, srv);
-                    if ret.is_some() {
-                        return ret;
-                    }
+        for r in self.chanmodes.iter() {
+            let ret = r.handler.handle_chanmode_request(membership, flag, set, args, srv);
+            if ret == Some(true) {
+                for e in self.events.iter() {
+                    e.handler.on_chanmode_change(membership, flag, set, srv);
                 }
             }
+            if ret.is_some() {
+                return ret;
+            }
         }
         // if we reach this point, the mode was not handled
         None
     }
+
+    fn count_command(&self, cmd: &IRCMessage) {
+        let mut stats = self.command_stats.lock();
+        let size = cmd.protocol_len() + 2;
+        match stats.entry(cmd.command.clone()) {
+            Vacant(entry) => { entry.set((1, size)); },
+            Occupied(mut entry) => {
+                let (count, bytes) = *entry.get();
+                entry.set((count + 1, bytes + size));
+            }
+        }
+    }
+
+    /// The usage of the handled commands, as `(command, count, bytes)`, sorted by command.
+    #[experimental]
+    pub fn command_stats(&self) -> Vec<(String, uint, uint)> {
+        let mut list: Vec<(String, uint, uint)> = self.command_stats.lock().iter()
+                                                      .map(|(c, &(n, b))| (c.clone(), n, b))
+                                                      .collect();
+        list.sort();
+        list
+    }
+
+    /// The user modes handled by the modules, sorted.
+    #[experimental]
+    pub fn supported_usermodes(&self) -> String {
+        let mut modes: Vec<char> = self.usermodes.iter().flat_map(|r| r.handler.usermodes().into_iter())
+                                                 .collect();
+        modes.sort();
+        modes.dedup();
+        modes.into_iter().collect()
+    }
+
+    /// The channel modes handled by the modules, sorted.
+    #[experimental]
+    pub fn supported_chanmodes(&self) -> String {
+        let mut modes: Vec<char> = self.all_chanmodes().into_iter().map(|(c, _)| c).collect();
+        modes.sort();
+        modes.dedup();
+        modes.into_iter().collect()
+    }
+
+    /// The ISUPPORT tokens describing the modules: CHANMODES and PREFIX computed from
+    /// the mode handlers, then the tokens registered by the modules.
+    #[experimental]
+    pub fn isupport_tokens(&self) -> Vec<String> {
+        let modes = self.all_chanmodes();
+        let of_kind = |kind: ChanModeKind| -> String {
+            let mut list: Vec<char> = modes.iter().filter(|&&(_, ref k)| *k == kind).map(|&(c, _)| c).collect();
+            list.sort();
+            list.dedup();
+            list.into_iter().collect()
+        };
+        let chanmodes = format!("CHANMODES={},{},{},{}", of_kind(ListMode), of_kind(ParamAlways),
+                                of_kind(ParamWhenSet), of_kind(NoParam));
+        let mut prefixes: Vec<(uint, char, char)> = modes.iter().filter_map(|&(c, ref k)| match *k {
+            PrefixMode(symbol, rank) => Some((rank, c, symbol)),
+            _ => None
+        }).collect();
+        // most powerful first
+        prefixes.sort_by(|a, b| b.cmp(a));
+        prefixes.dedup();
+        let prefix = format!("PREFIX=({}){}", prefixes.iter().map(|&(_, c, _)| c).collect::<String>(),
+                             prefixes.iter().map(|&(_, _, s)| s).collect::<String>());
+        let mut tokens = vec!(chanmodes, prefix);
+        tokens.extend(self.isupport.iter().map(|&(_, ref t)| t.clone()));
+        tokens
+    }
+
+    /// The client capabilities implemented by the server and the modules, sorted.
+    #[experimental]
+    pub fn supported_capabilities(&self) -> Vec<String> {
+        let mut caps: Vec<String> = self.capabilities.iter().map(|&(_, ref c)| c.clone()).collect();
+        caps.extend(SERVER_CAPABILITIES.iter().map(|c| c.to_string()));
+        caps.sort();
+        caps.dedup();
+        caps
+    }
+
+    fn all_chanmodes(&self) -> Vec<(char, ChanModeKind)> {
+        self.chanmodes.iter().flat_map(|r| r.handler.chanmodes().into_iter()).collect()
+    }
+}
+
+// The number of reads of the modules handler the current thread is doing.
+local_data_key!(MODULES_READING: uint)
+
+/// The lock protecting the modules handler.
+///
+/// The handlers use the modules handler while being called by it, so a thread already
+/// reading it reads it again without locking: otherwise a module operation waiting for
+/// the write lock between the two reads would block both threads forever. The lock is
+/// released when the last read of the thread ends, whatever the order the guards are
+/// dropped in.
+#[experimental]
+pub struct ModulesLock {
+    handler: UnsafeCell<ModulesHandler>,
+    state: Mutex<LockState>
+}
+
+struct LockState {
+    /// Number of threads reading the handler.
+    readers: uint,
+    writing: bool,
+    /// Threads waiting to write, which go before the threads starting to read.
+    waiting_writers: uint
+}
+
+/// A read access to the modules handler.
+#[experimental]
+pub struct ModulesReadGuard<'a> {
+    lock: &'a ModulesLock,
+    // the reads are counted by thread
+    no_send: NoSend
+}
+
+/// A write access to the modules handler.
+#[experimental]
+pub struct ModulesWriteGuard<'a> {
+    lock: &'a ModulesLock
+}
+
+#[experimental]
+impl ModulesLock {
+
+    /// Wraps given modules handler.
+    #[experimental]
+    pub fn new(handler: ModulesHandler) -> ModulesLock {
+        ModulesLock {
+            handler: UnsafeCell::new(handler),
+            state: Mutex::new(LockState { readers: 0, writing: false, waiting_writers: 0 })
+        }
+    }
+
+    /// Gives read access to the modules handler, locking it unless the current thread
+    /// already reads it.
+    #[experimental]
+    pub fn read<'a>(&'a self) -> ModulesReadGuard<'a> {
+        let depth = MODULES_READING.get().map_or(0, |d| *d);
+        if depth == 0 {
+            let mut state = self.state.lock();
+            while state.writing || state.waiting_writers > 0 {
+                state.cond.wait();
+            }
+            state.readers += 1;
+        }
+        MODULES_READING.replace(Some(depth + 1));
+        ModulesReadGuard { lock: self, no_send: NoSend }
+    }
+
+    /// Gives write access to the modules handler. The current thread must not be reading it.
+    #[experimental]
+    pub fn write<'a>(&'a self) -> ModulesWriteGuard<'a> {
+        if MODULES_READING.get().map_or(false, |d| *d > 0) {
+            panic!("The modules handler cannot be written by a thread reading it.");
+        }
+        let mut state = self.state.lock();
+        state.waiting_writers += 1;
+        while state.writing || state.readers > 0 {
+            state.cond.wait();
+        }
+        state.waiting_writers -= 1;
+        state.writing = true;
+        ModulesWriteGuard { lock: self }
+    }
+}
+
+impl<'a> Deref<ModulesHandler> for ModulesReadGuard<'a> {
+    fn deref<'b>(&'b self) -> &'b ModulesHandler {
+        // the thread holds the lock while it has a guard
+        unsafe { &*self.lock.handler.get() }
+    }
+}
+
+#[unsafe_destructor]
+impl<'a> Drop for ModulesReadGuard<'a> {
+    fn drop(&mut self) {
+        let depth = MODULES_READING.get().map_or(1, |d| *d);
+        if depth > 1 {
+            MODULES_READING.replace(Some(depth - 1));
+        } else {
+            MODULES_READING.replace(None);
+            let mut state = self.lock.state.lock();
+            state.readers -= 1;
+            state.cond.broadcast();
+        }
+    }
+}
+
+impl<'a> Deref<ModulesHandler> for ModulesWriteGuard<'a> {
+    fn deref<'b>(&'b self) -> &'b ModulesHandler {
+        unsafe { &*self.lock.handler.get() }
+    }
+}
+
+impl<'a> DerefMut<ModulesHandler> for ModulesWriteGuard<'a> {
+    fn deref_mut<'b>(&'b mut self) -> &'b mut ModulesHandler {
+        unsafe { &mut *self.lock.handler.get() }
+    }
+}
+
+#[unsafe_destructor]
+impl<'a> Drop for ModulesWriteGuard<'a> {
+    fn drop(&mut self) {
+        let mut state = self.lock.state.lock();
+        state.writing = false;
+        state.cond.broadcast();
+    }
 }
 
 /// Shortcut command for modules: sends to the user a "Not enought parameters" message
@@ -313,3 +1380,93 @@ pub fn send_needmoreparams(u: &UserData, cmd: &str, srv: &ServerData) {
                 .into_prefixed_message(u.nickname.as_slice(), srv.settings.read().name.as_slice())
     );
 }
+
+/// Opens the library of a module, checks its ABI identity and reads its descriptor.
+fn open_lib(name: &str, cfg: &toml::TomlTable, logger: &Logger)
+    -> Result<(DynamicLibrary, ModuleDescriptor), String> {
+    let path = match cfg.get(&"path".to_string()) {
+        Some(&toml::String(ref path_str)) => match from_str::<Path>(path_str.as_slice()) {
+            Some(p) => p,
+            None => return Err(format!("Invalid path for module {}.", name))
+        },
+        _ => return Err(format!("Invalid path for module {}.", name))
+    };
+    logger.log(Info, format!("Opening library {} for module {}.", path.display(), name));
+    let lib = try!(DynamicLibrary::open(Some(path)));
+    // nothing else can be safely read from the library before this check
+    let abi = unsafe {
+        match lib.symbol::<u64>("METALLIRC_ABI_ID") {
+            Ok(id) => *id,
+            Err(_) => return Err(format!("Module {} does not declare its ABI identity.", name))
+        }
+    };
+    if abi != ABI_ID {
+        return Err(format!("Module {} was built for another module ABI, or with other versions of rustc \
+                            or libmetallirc than this server.", name));
+    }
+    let descriptor = unsafe {
+        if lib.symbol::<u8>("metallirc_module_init").is_err() {
+            return Err(format!("Module {} has no init function.", name));
+        }
+        match lib.symbol("metallirc_module_descriptor") {
+            Err(e) => return Err(e),
+            Ok(f) => transmute::<*mut u8, fn() -> ModuleDescriptor>(f)()
+        }
+    };
+    if descriptor.name.as_slice() != name {
+        return Err(format!("Library of module {} declares a module named {}.", name, descriptor.name));
+    }
+    Ok((lib, descriptor))
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::timer::sleep;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{ModulesHandler, ModulesLock};
+
+    #[test]
+    fn test_modules_lock_reentrant() {
+        let lock = Arc::new(ModulesLock::new(ModulesHandler::init()));
+        let outer = lock.read();
+        let (tx, rx) = channel();
+        let writer = lock.clone();
+        spawn(proc() {
+            writer.write();
+            tx.send(());
+        });
+        // let the writer wait for the lock
+        sleep(Duration::milliseconds(50));
+        {
+            let inner = lock.read();
+            assert!(inner.list_modules().is_empty());
+        }
+        assert!(rx.try_recv().is_err());
+        drop(outer);
+        rx.recv();
+    }
+
+    #[test]
+    fn test_modules_lock_out_of_order() {
+        let lock = Arc::new(ModulesLock::new(ModulesHandler::init()));
+        let outer = lock.read();
+        let inner = lock.read();
+        let (tx, rx) = channel();
+        let writer = lock.clone();
+        spawn(proc() {
+            writer.write();
+            tx.send(());
+        });
+        sleep(Duration::milliseconds(50));
+        // the thread still reads the handler through the inner guard
+        drop(outer);
+        sleep(Duration::milliseconds(50));
+        assert!(rx.try_recv().is_err());
+        assert!(inner.list_modules().is_empty());
+        drop(inner);
+        rx.recv();
+    }
+}
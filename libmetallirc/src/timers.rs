@@ -0,0 +1,316 @@
+//! Timers and scheduled tasks.
+
+//! The `TimerService` of the server runs tasks after a delay, once or repeatedly, on
+//! a dedicated scheduler thread. Tasks are given access to the `ServerData`.
+//!
+//! Each timer has an owner: modules must use their own name, so that their timers are
+//! cancelled when they are unloaded. The tasks of a module are suspended before it is
+//! unloaded, which waits for the one running, if any, to return.
+
+#![experimental]
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, SeqCst};
+use std::time::duration::Duration;
+
+use time::precise_time_ns;
+
+use ServerData;
+
+/// A task to be run by the scheduler.
+#[experimental]
+pub trait TimedTask : Send + Sync {
+    /// Runs the task.
+    #[experimental]
+    fn run(&self, srv: &ServerData);
+}
+
+impl<T: TimedTask> TimedTask for Arc<T> {
+    fn run(&self, srv: &ServerData) {
+        (**self).run(srv)
+    }
+}
+
+/// Handle to a scheduled task, allowing to cancel it.
+#[experimental]
+#[deriving(Clone)]
+pub struct TimerHandle {
+    cancelled: Arc<AtomicBool>
+}
+
+#[experimental]
+impl TimerHandle {
+
+    /// Cancels the task. A repeating task will not run anymore, a one-shot task will
+    /// not run if it did not already.
+    #[experimental]
+    pub fn cancel(&self) {
+        self.cancelled.store(true, SeqCst);
+    }
+
+    /// Was the task cancelled ?
+    #[experimental]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(SeqCst)
+    }
+}
+
+struct Timer {
+    owner: String,
+    /// Time at which the task should run, in milliseconds.
+    due: u64,
+    /// Interval of a repeating task, in milliseconds.
+    interval: Option<u64>,
+    task: Box<TimedTask + 'static + Send + Sync>,
+    handle: TimerHandle
+}
+
+/// Which tasks can be run.
+struct RunState {
+    /// Owners whose tasks must not be started.
+    suspended: Vec<String>,
+    /// Owner of the task being run.
+    running: Option<String>
+}
+
+/// The scheduled tasks of the server.
+#[experimental]
+pub struct TimerService {
+    timers: Mutex<Vec<Timer>>,
+    // always locked after `timers` when both are
+    state: Mutex<RunState>
+}
+
+/// Current time in milliseconds, on a monotonic clock.
+fn now_ms() -> u64 {
+    precise_time_ns() / 1000000
+}
+
+/// Milliseconds in a duration, negative durations being considered as null.
+fn duration_ms(d: Duration) -> u64 {
+    let ms = d.num_milliseconds();
+    if ms < 0 { 0 } else { ms as u64 }
+}
+
+#[experimental]
+impl TimerService {
+
+    /// Creates a service without any task.
+    #[experimental]
+    pub fn new() -> TimerService {
+        TimerService {
+            timers: Mutex::new(Vec::new()),
+            state: Mutex::new(RunState { suspended: Vec::new(), running: None })
+        }
+    }
+
+    /// Runs given task once, after given delay.
+    #[experimental]
+    pub fn schedule_once<T: TimedTask + 'static>(&self, owner: &str, delay: Duration, task: T) -> TimerHandle {
+        self.schedule(owner, duration_ms(delay), None, box task as Box<TimedTask + 'static + Send + Sync>)
+    }
+
+    /// Runs given task at given interval, starting after one interval.
+    #[experimental]
+    pub fn schedule_repeating<T: TimedTask + 'static>(&self, owner: &str, interval: Duration, task: T)
+        -> TimerHandle {
+        // a null interval would run the task in loop
+        let ms = ::std::cmp::max(duration_ms(interval), 1);
+        self.schedule(owner, ms, Some(ms), box task as Box<TimedTask + 'static + Send + Sync>)
+    }
+
+    fn schedule(&self, owner: &str, delay: u64, interval: Option<u64>,
+                task: Box<TimedTask + 'static + Send + Sync>) -> TimerHandle {
+        let handle = TimerHandle { cancelled: Arc::new(AtomicBool::new(false)) };
+        self.timers.lock().push(Timer {
+            owner: owner.to_string(),
+            due: now_ms() + delay,
+            interval: interval,
+            task: task,
+            handle: handle.clone()
+        });
+        handle
+    }
+
+    /// Cancels and drops all the tasks of given owner. Returns the number of tasks removed.
+    #[experimental]
+    pub fn cancel_owner(&self, owner: &str) -> uint {
+        let mut timers = self.timers.lock();
+        let before = timers.len();
+        for t in timers.iter().filter(|t| t.owner.as_slice() == owner) {
+            t.handle.cancel();
+        }
+        timers.retain(|t| t.owner.as_slice() != owner);
+        before - timers.len()
+    }
+
+    /// Prevents the tasks of given owner from being started, until `resume_owner` is
+    /// called, and waits for the one running, if any, to return.
+    ///
+    /// Must be called before unloading a module, and not while holding the modules
+    /// handler, which the running task may need.
+    #[experimental]
+    pub fn suspend_owner(&self, owner: &str) {
+        let mut state = self.state.lock();
+        state.suspended.push(owner.to_string());
+        while state.running.as_ref().map_or(false, |r| r.as_slice() == owner) {
+            state.cond.wait();
+        }
+    }
+
+    /// Allows again the tasks of given owner to be run.
+    #[experimental]
+    pub fn resume_owner(&self, owner: &str) {
+        let mut state = self.state.lock();
+        if let Some(i) = state.suspended.iter().position(|o| o.as_slice() == owner) {
+            state.suspended.swap_remove(i);
+        }
+    }
+
+    /// Number of tasks waiting to be run.
+    #[experimental]
+    pub fn len(&self) -> uint {
+        self.timers.lock().len()
+    }
+
+    /// Drops all the tasks, used at shutdown.
+    #[experimental]
+    pub fn clear(&self) {
+        let mut timers = self.timers.lock();
+        for t in timers.iter() {
+            t.handle.cancel();
+        }
+        timers.clear();
+    }
+
+    /// Runs all the tasks which are due, the oldest first, and schedules again the
+    /// repeating ones. Called by the scheduler thread.
+    #[experimental]
+    pub fn run_due(&self, srv: &ServerData) {
+        self.run_tasks(|t| t.task.run(srv));
+    }
+
+    fn run_tasks(&self, run: |&Timer|) {
+        let now = now_ms();
+        loop {
+            // the locks are released while the task runs, so it can schedule others,
+            // and only the running task is out of the list, for cancel_owner() to see
+            // all the others
+            let mut t = {
+                let mut timers = self.timers.lock();
+                timers.retain(|t| !t.handle.is_cancelled());
+                let mut state = self.state.lock();
+                let next = timers.iter().enumerate()
+                                 .filter(|&(_, t)| t.due <= now && !state.suspended.contains(&t.owner))
+                                 .min_by(|&(_, t)| t.due)
+                                 .map(|(i, _)| i);
+                match next {
+                    Some(i) => {
+                        state.running = Some(timers[i].owner.clone());
+                        timers.swap_remove(i).unwrap()
+                    },
+                    None => return
+                }
+            };
+            run(&t);
+            let mut timers = self.timers.lock();
+            if let Some(interval) = t.interval {
+                if !t.handle.is_cancelled() {
+                    // a late task runs again one interval from now, not in this run
+                    t.due = if t.due + interval > now { t.due + interval } else { now + interval };
+                    timers.push(t);
+                }
+            }
+            let mut state = self.state.lock();
+            state.running = None;
+            state.cond.broadcast();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::time::duration::Duration;
+
+    use super::{TimedTask, TimerService, now_ms};
+    use ServerData;
+
+    struct Noop;
+
+    impl TimedTask for Noop {
+        fn run(&self, _: &ServerData) {}
+    }
+
+    /// Runs the due tasks, returning their owners in run order.
+    fn run(timers: &TimerService) -> Vec<String> {
+        let mut ran = Vec::new();
+        timers.run_tasks(|t| ran.push(t.owner.clone()));
+        ran
+    }
+
+    #[test]
+    fn test_schedule_once() {
+        let timers = TimerService::new();
+        timers.schedule_once("a", Duration::zero(), Noop);
+        timers.schedule_once("b", Duration::hours(1), Noop);
+        assert_eq!(run(&timers), vec!("a".to_string()));
+        assert_eq!(timers.len(), 1);
+        assert!(run(&timers).is_empty());
+    }
+
+    #[test]
+    fn test_schedule_repeating() {
+        let timers = TimerService::new();
+        timers.schedule_repeating("a", Duration::hours(1), Noop);
+        timers.timers.lock()[0].due = 0;
+        let start = now_ms();
+        assert_eq!(run(&timers), vec!("a".to_string()));
+        assert_eq!(timers.len(), 1);
+        // rescheduled one interval after the late run
+        assert!(timers.timers.lock()[0].due >= start + 3600 * 1000);
+        assert!(run(&timers).is_empty());
+        timers.timers.lock()[0].due = 0;
+        assert_eq!(run(&timers), vec!("a".to_string()));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let timers = TimerService::new();
+        let handle = timers.schedule_once("a", Duration::zero(), Noop);
+        timers.schedule_once("b", Duration::zero(), Noop);
+        timers.schedule_repeating("b", Duration::milliseconds(10), Noop);
+        timers.schedule_once("c", Duration::zero(), Noop);
+        handle.cancel();
+        assert!(handle.is_cancelled());
+        assert_eq!(timers.cancel_owner("b"), 2);
+        assert_eq!(run(&timers), vec!("c".to_string()));
+        assert_eq!(timers.len(), 0);
+    }
+
+    #[test]
+    fn test_due_order() {
+        let timers = TimerService::new();
+        for owner in ["a", "b", "c", "d"].iter() {
+            timers.schedule_once(*owner, Duration::zero(), Noop);
+        }
+        {
+            let mut list = timers.timers.lock();
+            for t in list.iter_mut() {
+                t.due = match t.owner.as_slice() { "a" => 3, "b" => 1, "c" => 2, _ => 0 };
+            }
+        }
+        assert_eq!(run(&timers), vec!("d".to_string(), "b".to_string(), "c".to_string(), "a".to_string()));
+    }
+
+    #[test]
+    fn test_suspend_owner() {
+        let timers = TimerService::new();
+        timers.schedule_once("a", Duration::zero(), Noop);
+        timers.schedule_once("b", Duration::zero(), Noop);
+        timers.suspend_owner("a");
+        assert_eq!(run(&timers), vec!("b".to_string()));
+        timers.resume_owner("a");
+        assert_eq!(run(&timers), vec!("a".to_string()));
+    }
+}
@@ -0,0 +1,220 @@
+//! Batches of messages, grouping related lines for the clients supporting them.
+
+#![experimental]
+
+use messages::IRCMessage;
+
+/// A batch of messages being sent to a user, see `UserData::start_batch`.
+#[experimental]
+pub struct Batch {
+    /// Reference of the batch, None if the client does not support batches.
+    reference: Option<String>,
+    /// Name of the server, prefix of the BATCH lines.
+    server: String
+}
+
+#[experimental]
+impl Batch {
+
+    /// Creates a batch, without reference if the client does not support batches.
+    #[experimental]
+    pub fn new(reference: Option<String>, server: &str) -> Batch {
+        Batch {
+            reference: reference,
+            server: server.to_string()
+        }
+    }
+
+    /// Opens a batch of given type sent by given server, within `parent` if it is nested,
+    /// returning it with its opening line. The reference is only asked for if the batch
+    /// can be sent: the client enabled `batch` and the parent, if any, was sent.
+    #[experimental]
+    pub fn open(batch_cap: bool, reference: || -> String, server: &str, kind: &str, params: Vec<String>,
+                parent: Option<&Batch>, tags: Vec<(String, String)>) -> (Batch, Option<IRCMessage>) {
+        // a nested batch cannot be sent if its parent is not
+        if !batch_cap || parent.map_or(false, |p| p.reference().is_none()) {
+            return (Batch::new(None, server), None);
+        }
+        let reference = reference();
+        let mut args = vec!(format!("+{}", reference), kind.to_string());
+        args.extend(params.into_iter());
+        let mut msg = IRCMessage {
+            tags: tags,
+            prefix: Some(server.to_string()),
+            command: "BATCH".to_string(),
+            args: args,
+            suffix: None
+        };
+        if let Some(p) = parent.and_then(|p| p.reference()) {
+            msg.set_tag("batch", p);
+        }
+        (Batch::new(Some(reference), server), Some(msg))
+    }
+
+    /// Reference of the batch, None if the client does not support batches.
+    #[experimental]
+    pub fn reference(&self) -> Option<&str> {
+        self.reference.as_ref().map(|r| r.as_slice())
+    }
+
+    /// Name of the server sending the batch.
+    #[experimental]
+    pub fn server(&self) -> &str {
+        self.server.as_slice()
+    }
+
+    /// Marks given message as part of the batch, if it is sent.
+    #[experimental]
+    pub fn tag_message(&self, msg: &mut IRCMessage) {
+        if let Some(reference) = self.reference() {
+            msg.set_tag("batch", reference);
+        }
+    }
+
+    /// Closes the batch, returning its closing line if it is sent.
+    #[experimental]
+    pub fn close(self) -> Option<IRCMessage> {
+        let Batch { reference, server } = self;
+        match reference {
+            Some(reference) => Some(IRCMessage {
+                tags: Vec::new(),
+                prefix: Some(server),
+                command: "BATCH".to_string(),
+                args: vec!(format!("-{}", reference)),
+                suffix: None
+            }),
+            None => None
+        }
+    }
+}
+
+/// The lines of the response to a labeled command, made of given replies: an ACK if
+/// there are none, the reply itself if there is one, and a `labeled-response` batch
+/// of them otherwise.
+#[experimental]
+pub fn labeled_response(label: &str, mut replies: Vec<IRCMessage>, server: &str, batch_cap: bool,
+                        reference: || -> String) -> Vec<IRCMessage> {
+    match replies.len() {
+        // acknowledge the command
+        0 => vec!(IRCMessage {
+            tags: vec!(("label".to_string(), label.to_string())),
+            prefix: Some(server.to_string()),
+            command: "ACK".to_string(),
+            args: vec!(),
+            suffix: None
+        }),
+        1 => {
+            let mut reply = replies.pop().unwrap();
+            reply.set_tag("label", label);
+            vec!(reply)
+        },
+        _ => {
+            let (batch, start) = Batch::open(batch_cap, reference, server, "labeled-response", vec!(), None,
+                                             vec!(("label".to_string(), label.to_string())));
+            let mut lines: Vec<IRCMessage> = start.into_iter().collect();
+            for mut reply in replies.into_iter() {
+                // the batches opened by the command are nested in this one
+                let nested = reply.tag("batch").is_some()
+                             || (reply.command.as_slice() == "BATCH"
+                                 && reply.args.get(0).map_or(false, |a| a.as_slice().starts_with("-")));
+                if !nested { batch.tag_message(&mut reply); }
+                lines.push(reply);
+            }
+            lines.extend(batch.close().into_iter());
+            lines
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use messages::IRCMessage;
+
+    use super::{Batch, labeled_response};
+
+    fn line(msg: &IRCMessage) -> String {
+        msg.to_protocol()
+    }
+
+    #[test]
+    fn test_batch_without_cap() {
+        let (batch, start) = Batch::open(false, || panic!("no reference needed"), "irc.example.com",
+                                         "netsplit", vec!(), None, vec!());
+        assert!(start.is_none());
+        assert!(batch.reference().is_none());
+        let mut msg: IRCMessage = from_str(":foo!bar@baz QUIT :split").unwrap();
+        batch.tag_message(&mut msg);
+        assert!(msg.tag("batch").is_none());
+        assert!(batch.close().is_none());
+    }
+
+    #[test]
+    fn test_batch() {
+        let (batch, start) = Batch::open(true, || "b0".to_string(), "irc.example.com", "netsplit",
+                                         vec!("a".to_string()), None, vec!());
+        assert_eq!(line(&start.unwrap()).as_slice(), ":irc.example.com BATCH +b0 netsplit a");
+        let mut msg: IRCMessage = from_str(":foo!bar@baz QUIT :split").unwrap();
+        batch.tag_message(&mut msg);
+        assert_eq!(msg.tag("batch"), Some("b0"));
+        assert_eq!(line(&batch.close().unwrap()).as_slice(), ":irc.example.com BATCH -b0");
+    }
+
+    #[test]
+    fn test_nested_batch() {
+        let (parent, _) = Batch::open(true, || "b0".to_string(), "irc.example.com", "parent",
+                                      vec!(), None, vec!());
+        let (child, start) = Batch::open(true, || "b1".to_string(), "irc.example.com", "child",
+                                         vec!(), Some(&parent), vec!());
+        assert_eq!(child.reference(), Some("b1"));
+        assert_eq!(start.unwrap().tag("batch"), Some("b0"));
+        // the parent was not sent, so neither is the child
+        let orphan = Batch::new(None, "irc.example.com");
+        let (child, start) = Batch::open(true, || panic!("no reference needed"), "irc.example.com",
+                                         "child", vec!(), Some(&orphan), vec!());
+        assert!(start.is_none());
+        assert!(child.reference().is_none());
+    }
+
+    #[test]
+    fn test_labeled_response() {
+        let ack = labeled_response("l1", vec!(), "irc.example.com", true, || panic!("no batch needed"));
+        assert_eq!(ack.len(), 1);
+        assert_eq!(ack[0].command.as_slice(), "ACK");
+        assert_eq!(ack[0].tag("label"), Some("l1"));
+
+        let reply: IRCMessage = from_str(":irc.example.com 421 nick FOO :Unknown command").unwrap();
+        let single = labeled_response("l2", vec!(reply), "irc.example.com", true, || panic!("no batch needed"));
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].command.as_slice(), "421");
+        assert_eq!(single[0].tag("label"), Some("l2"));
+
+        let replies = vec!(
+            from_str(":irc.example.com 311 nick foo bar baz * :Foo").unwrap(),
+            from_str(":irc.example.com BATCH +b1 chathistory #chan").unwrap(),
+            from_str("@batch=b1 :foo!bar@baz PRIVMSG #chan :hi").unwrap(),
+            from_str(":irc.example.com BATCH -b1").unwrap(),
+            from_str(":irc.example.com 318 nick foo :End of WHOIS").unwrap()
+        );
+        let lines = labeled_response("l3", replies, "irc.example.com", true, || "b2".to_string());
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0].command.as_slice(), "BATCH");
+        assert_eq!(lines[0].args[0].as_slice(), "+b2");
+        assert_eq!(lines[0].tag("label"), Some("l3"));
+        // the replies and the batches opened by the command are within the labeled batch
+        assert_eq!(lines[1].tag("batch"), Some("b2"));
+        assert_eq!(lines[2].tag("batch"), Some("b2"));
+        assert_eq!(lines[3].tag("batch"), Some("b1"));
+        assert!(lines[4].tag("batch").is_none());
+        assert_eq!(lines[5].tag("batch"), Some("b2"));
+        assert_eq!(line(&lines[6]).as_slice(), ":irc.example.com BATCH -b2");
+
+        let replies = vec!(
+            from_str(":irc.example.com 311 nick foo bar baz * :Foo").unwrap(),
+            from_str(":irc.example.com 318 nick foo :End of WHOIS").unwrap()
+        );
+        let lines = labeled_response("l4", replies, "irc.example.com", false, || panic!("no batch needed"));
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|l| l.tag("batch").is_none()));
+    }
+}
@@ -0,0 +1,105 @@
+//! Negotiation of the IRCv3 client capabilities.
+
+#![experimental]
+
+use std::collections::HashSet;
+
+use messages::{IRCMessage, numericreply};
+
+/// The capabilities implemented by the server itself rather than by a module.
+#[experimental]
+pub const SERVER_CAPABILITIES: &'static [&'static str] = &["batch", "labeled-response", "server-time"];
+
+/// Effect of a CAP command on the registration of a client.
+#[experimental]
+#[deriving(PartialEq, Show)]
+pub enum CapNegotiation {
+    /// The client started negotiating, its registration must wait for CAP END.
+    CapStarted,
+    /// The client ended the negotiation.
+    CapEnded,
+    /// The command has no effect on the registration.
+    CapUnchanged
+}
+
+/// Handles a CAP command from a client identified by `nick` ("*" if not known yet),
+/// updating its `enabled` capabilities. Returns the replies to send to the client.
+#[experimental]
+pub fn handle_cap(cmd: &IRCMessage, nick: &str, server: &str, supported: &[String],
+                  enabled: &mut HashSet<String>) -> (Vec<IRCMessage>, CapNegotiation) {
+    let reply = |sub: &str, caps: String| -> IRCMessage {
+        IRCMessage {
+            tags: Vec::new(),
+            prefix: Some(server.to_string()),
+            command: "CAP".to_string(),
+            args: vec!(nick.to_string(), sub.to_string()),
+            suffix: Some(caps)
+        }
+    };
+    let args = match cmd.as_nparams(1, 1) {
+        Some(args) => args,
+        None => return (
+            vec!(numericreply::ERR_NEEDMOREPARAMS("CAP").into_prefixed_message(nick, server)),
+            CapUnchanged
+        )
+    };
+    match args[0].as_slice() {
+        "LS" => (vec!(reply("LS", supported.connect(" "))), CapStarted),
+        "LIST" => {
+            let mut list: Vec<&str> = enabled.iter().map(|c| c.as_slice()).collect();
+            list.sort();
+            (vec!(reply("LIST", list.connect(" "))), CapUnchanged)
+        },
+        "REQ" => {
+            let requested = args.get(1).map_or("", |s| s.as_slice());
+            let changes: Vec<(bool, &str)> = requested.words().map(|c|
+                if c.starts_with("-") { (false, c.slice_from(1)) } else { (true, c) }
+            ).collect();
+            // the request is accepted or refused as a whole
+            if changes.len() > 0 && changes.iter().all(|&(_, c)| supported.iter().any(|s| s.as_slice() == c)) {
+                for &(add, c) in changes.iter() {
+                    if add { enabled.insert(c.to_string()); } else { enabled.remove(&c.to_string()); }
+                }
+                (vec!(reply("ACK", requested.to_string())), CapStarted)
+            } else {
+                (vec!(reply("NAK", requested.to_string())), CapStarted)
+            }
+        },
+        "END" => (vec!(), CapEnded),
+        sub => (
+            vec!(numericreply::ERR_INVALIDCAPCMD(sub).into_prefixed_message(nick, server)),
+            CapUnchanged
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::HashSet;
+
+    use super::{handle_cap, CapStarted, CapEnded};
+
+    #[test]
+    fn test_cap_req() {
+        let supported = vec!("away-notify".to_string(), "chghost".to_string());
+        let mut enabled = HashSet::new();
+        let req = from_str("CAP REQ :away-notify chghost").unwrap();
+        let (replies, state) = handle_cap(&req, "*", "irc.example.com", supported.as_slice(), &mut enabled);
+        assert_eq!(state, CapStarted);
+        assert_eq!(replies[0].args[1].as_slice(), "ACK");
+        assert_eq!(enabled.len(), 2);
+        let req = from_str("CAP REQ :-chghost foo").unwrap();
+        let (replies, _) = handle_cap(&req, "*", "irc.example.com", supported.as_slice(), &mut enabled);
+        assert_eq!(replies[0].args[1].as_slice(), "NAK");
+        assert_eq!(enabled.len(), 2);
+        let req = from_str("CAP REQ -chghost").unwrap();
+        handle_cap(&req, "*", "irc.example.com", supported.as_slice(), &mut enabled);
+        assert!(enabled.contains(&"away-notify".to_string()));
+        assert!(!enabled.contains(&"chghost".to_string()));
+        let end = from_str("CAP END").unwrap();
+        let (replies, state) = handle_cap(&end, "*", "irc.example.com", supported.as_slice(), &mut enabled);
+        assert!(replies.is_empty());
+        assert_eq!(state, CapEnded);
+    }
+}
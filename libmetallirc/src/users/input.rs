@@ -0,0 +1,162 @@
+//! Flood control and activity tracking of user input.
+
+#![experimental]
+
+use std::cmp::min;
+use std::collections::RingBuf;
+
+use messages::IRCMessage;
+
+/// Result of the inactivity check of a user.
+#[experimental]
+#[deriving(PartialEq, Show)]
+pub enum PingStatus {
+    /// The user is active, nothing to do.
+    Alive,
+    /// The user has been inactive for too long, a PING should be sent.
+    PingNeeded,
+    /// The user did not answer our PING, it should be disconnected.
+    PingTimeout
+}
+
+/// State of the input of a user: a token bucket limiting the rate of processed
+/// commands, the commands waiting to be processed, and the activity of the user.
+#[experimental]
+pub struct InputControl {
+    /// Available tokens, in thousandths of command.
+    tokens: u64,
+    /// Last refill of the bucket, in milliseconds.
+    last_refill: u64,
+    /// Commands read but not yet processed.
+    pending: RingBuf<IRCMessage>,
+    /// Size in bytes of the pending commands.
+    pending_size: uint,
+    /// Timestamp of the last message received.
+    last_activity: i64,
+    /// Was a PING sent since the last activity ?
+    ping_sent: bool
+}
+
+#[experimental]
+impl InputControl {
+
+    /// Creates the input control of a new user, with an empty bucket
+    /// to be filled at first refill.
+    #[experimental]
+    pub fn new(now_ms: u64, now: i64) -> InputControl {
+        InputControl {
+            tokens: 0,
+            last_refill: now_ms,
+            pending: RingBuf::new(),
+            pending_size: 0,
+            last_activity: now,
+            ping_sent: false
+        }
+    }
+
+    /// Refills the bucket according to elapsed time, of `rate` commands per second
+    /// up to `burst` commands.
+    #[experimental]
+    pub fn refill(&mut self, burst: uint, rate: uint, now_ms: u64) {
+        let elapsed = if now_ms > self.last_refill { now_ms - self.last_refill } else { 0 };
+        self.tokens = min(self.tokens + elapsed * rate as u64, burst as u64 * 1000);
+        self.last_refill = now_ms;
+    }
+
+    /// Takes a token from the bucket for one command. Returns false if the bucket is empty.
+    #[experimental]
+    pub fn take_token(&mut self) -> bool {
+        if self.tokens >= 1000 {
+            self.tokens -= 1000;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Stores a command to be processed later.
+    #[experimental]
+    pub fn push_pending(&mut self, msg: IRCMessage) {
+        self.pending_size += msg.protocol_len();
+        self.pending.push_back(msg);
+    }
+
+    /// Retrieves the next pending command if there is a token for it.
+    #[experimental]
+    pub fn pop_pending(&mut self) -> Option<IRCMessage> {
+        if self.pending.is_empty() || !self.take_token() { return None; }
+        let msg = self.pending.pop_front().unwrap();
+        self.pending_size -= msg.protocol_len();
+        Some(msg)
+    }
+
+    /// Are there commands waiting to be processed ?
+    #[experimental]
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Size in bytes of the commands waiting to be processed.
+    #[experimental]
+    pub fn pending_size(&self) -> uint {
+        self.pending_size
+    }
+
+    /// Records some activity of the user.
+    #[experimental]
+    pub fn mark_activity(&mut self, now: i64) {
+        self.last_activity = now;
+        self.ping_sent = false;
+    }
+
+    /// Checks the activity of the user given the ping frequency of its class.
+    #[experimental]
+    pub fn check_ping(&mut self, now: i64, frequency: i64) -> PingStatus {
+        if now - self.last_activity < frequency {
+            Alive
+        } else if !self.ping_sent {
+            self.ping_sent = true;
+            PingNeeded
+        } else if now - self.last_activity >= 2 * frequency {
+            PingTimeout
+        } else {
+            Alive
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{InputControl, Alive, PingNeeded, PingTimeout};
+
+    #[test]
+    fn test_flood_bucket() {
+        let mut input = InputControl::new(0, 0);
+        input.refill(3, 1, 10000);
+        // burst is capped
+        assert!(input.take_token());
+        assert!(input.take_token());
+        assert!(input.take_token());
+        assert!(!input.take_token());
+        // one command per second
+        input.refill(3, 1, 10500);
+        assert!(!input.take_token());
+        input.refill(3, 1, 11000);
+        assert!(input.take_token());
+        assert!(!input.take_token());
+    }
+
+    #[test]
+    fn test_check_ping() {
+        let mut input = InputControl::new(0, 0);
+        assert_eq!(input.check_ping(50, 100), Alive);
+        assert_eq!(input.check_ping(100, 100), PingNeeded);
+        assert_eq!(input.check_ping(150, 100), Alive);
+        assert_eq!(input.check_ping(200, 100), PingTimeout);
+        input.mark_activity(200);
+        assert_eq!(input.check_ping(250, 100), Alive);
+    }
+
+}
@@ -3,9 +3,21 @@
 #![experimental]
 
 pub use self::user::{UserData, PrivateUserDataHandler};
+pub use self::batch::Batch;
 pub use self::usermanager::UserManager;
 pub use self::newuser::NewUser;
+pub use self::capabilities::{CapNegotiation, CapStarted, CapEnded, CapUnchanged, handle_cap};
+pub use self::capabilities::SERVER_CAPABILITIES;
+pub use self::whowas::{WhowasEntry, WhowasHistory};
+pub use self::input::{InputControl, PingStatus, Alive, PingNeeded, PingTimeout};
+pub use self::privileges::{Privilege, PrivKill, PrivKline, PrivDie, PrivRehash, PrivOverride,
+                           PrivSeeInvisible, PrivModules};
 
+mod batch;
+mod capabilities;
+mod input;
 mod newuser;
+mod privileges;
 mod user;
 mod usermanager;
+mod whowas;
@@ -1,14 +1,20 @@
 //! Structs for handling new connections
 
+use std::collections::HashSet;
 use std::io;
 use std::io::BufferedStream;
 use std::io::net::tcp::TcpStream;
 
 use messages::{IRCMessage, numericreply};
 
+use bans::{BanManager, QLine};
 use conf::ServerConf;
 use util;
 
+use super::capabilities::{handle_cap, CapStarted, CapEnded, CapUnchanged};
+
+use time::get_time;
+
 /// A user with possibly missing data, not to be shared until
 /// initial negociation is done and a proper user is created.
 #[experimental]
@@ -17,6 +23,17 @@ pub struct NewUser {
     pub nickname: Option<String>,
     pub username: Option<String>,
     pub realname: Option<String>,
+    pub hostname: String,
+    /// Local port the user connected to.
+    pub port: u16,
+    /// Password given with PASS, if any.
+    pub password: Option<String>,
+    /// Account the user authenticated to, if any.
+    pub account: Option<String>,
+    /// Capabilities the user enabled with CAP.
+    pub capabilities: HashSet<String>,
+    /// Is the user negociating its capabilities ? Its registration waits for CAP END.
+    pub cap_negociating: bool,
     pub zombie: bool
 }
 
@@ -25,12 +42,18 @@ impl NewUser {
 
     /// Creates a NewUser from a connection
     #[experimental]
-    pub fn new(socket: BufferedStream<TcpStream>) -> NewUser {
+    pub fn new(socket: BufferedStream<TcpStream>, hostname: String, port: u16) -> NewUser {
         NewUser {
             socket: socket,
             nickname: None,
             username: None,
             realname: None,
+            hostname: hostname,
+            port: port,
+            password: None,
+            account: None,
+            capabilities: HashSet::new(),
+            cap_negociating: false,
             zombie: false
         }
     }
@@ -46,13 +69,18 @@ impl NewUser {
     }
 
     /// Read next message in negociation of new user.
-    /// Returns whether the user is ready to be promoted to a real user or not.
+    /// `caps` are the capabilities supported by the server.
     #[experimental]
-    pub fn step_negociate(&mut self, server: &ServerConf) {
+    pub fn step_negociate(&mut self, server: &ServerConf, bans: &BanManager, caps: &[String]) {
         match self.socket.read_line() {
             // got a line
             Ok(txt) => match from_str::<IRCMessage>(txt.as_slice().lines_any().next().unwrap()) {
                 Some(msg) => match msg.command.as_slice() {
+                    "PASS" => if let Some(mut args) = msg.as_nparams(1,0) {
+                        self.password = args.pop();
+                    } else {
+                        self.err_reply(server, numericreply::ERR_NEEDMOREPARAMS("PASS"))
+                    },
                     "USER" => if let Some(args) = msg.as_nparams(4,0) {
                         // TODO : check validity
                         // TODO : allow only once
@@ -63,7 +91,9 @@ impl NewUser {
                     },
                     "NICK" => if let Some(mut args) = msg.as_nparams(1,0) {
                         let nick = args.pop().unwrap();
-                        if util::check_label(nick.as_slice()) {
+                        // reserved nicknames are refused as well
+                        if util::check_nickname(nick.as_slice())
+                        && bans.find(QLine, nick.as_slice(), get_time().sec).is_none() {
                             self.nickname = Some(nick);
                         } else {
                             self.err_reply(server, numericreply::ERR_ERRONEUSNICKNAME(nick.as_slice()));
@@ -71,6 +101,21 @@ impl NewUser {
                     }else {
                         self.err_reply(server, numericreply::ERR_NEEDMOREPARAMS("NICK"))
                     },
+                    "CAP" => {
+                        let nick = self.nickname.clone().unwrap_or("*".to_string());
+                        let (replies, state) = handle_cap(&msg, nick.as_slice(), server.name.as_slice(),
+                                                          caps, &mut self.capabilities);
+                        match state {
+                            CapStarted => self.cap_negociating = true,
+                            CapEnded => self.cap_negociating = false,
+                            CapUnchanged => {}
+                        }
+                        for r in replies.into_iter() {
+                            if util::write_message(&mut self.socket, r).is_err() {
+                                self.zombie = true;
+                            }
+                        }
+                    },
                     _ => {}
                 },
                 None => {}
@@ -92,6 +137,28 @@ impl NewUser {
     #[experimental]
     pub fn is_ready(&self) -> bool {
         self.nickname.is_some() && self.username.is_some() && self.realname.is_some()
+        && !self.cap_negociating
+    }
+
+    /// Returns the `user@host` of this new user. Assumes the username is known.
+    #[experimental]
+    pub fn get_userhost(&self) -> String {
+        format!("{}@{}", self.username.as_ref().map_or("*", |s| s.as_slice()), self.hostname)
+    }
+
+    /// Refuses the connection with given reason. The user is zombified.
+    #[experimental]
+    pub fn reject(&mut self, reason: &str) {
+        let _ = util::write_message(&mut self.socket,
+            IRCMessage {
+                tags: Vec::new(),
+                prefix: None,
+                command: "ERROR".to_string(),
+                args: vec!(),
+                suffix: Some(format!("Closing Link: {} ({})", self.hostname, reason))
+            }
+        );
+        self.zombie = true;
     }
 
     /// Invalidates the nick with ad "nick already in use" message
@@ -0,0 +1,57 @@
+//! Operator privileges.
+
+#![experimental]
+
+/// The privileges an operator can be granted.
+#[experimental]
+#[deriving(PartialEq, Eq, Hash, Clone, Show)]
+pub enum Privilege {
+    /// Can disconnect users with KILL.
+    PrivKill,
+    /// Can add and remove server bans.
+    PrivKline,
+    /// Can shut the server down.
+    PrivDie,
+    /// Can reload the configuration.
+    PrivRehash,
+    /// Can bypass channel permissions.
+    PrivOverride,
+    /// Can see invisible users and their modes.
+    PrivSeeInvisible,
+    /// Can load, unload and reload modules.
+    PrivModules
+}
+
+#[experimental]
+impl Privilege {
+
+    /// Name of the privilege, as used in config file.
+    #[experimental]
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PrivKill => "kill",
+            PrivKline => "kline",
+            PrivDie => "die",
+            PrivRehash => "rehash",
+            PrivOverride => "override",
+            PrivSeeInvisible => "see-invisible",
+            PrivModules => "modules"
+        }
+    }
+
+    /// Retrieves a privilege from its name.
+    #[experimental]
+    pub fn from_name(name: &str) -> Option<Privilege> {
+        match name {
+            "kill" => Some(PrivKill),
+            "kline" => Some(PrivKline),
+            "die" => Some(PrivDie),
+            "rehash" => Some(PrivRehash),
+            "override" => Some(PrivOverride),
+            "see-invisible" => Some(PrivSeeInvisible),
+            "modules" => Some(PrivModules),
+            _ => None
+        }
+    }
+
+}
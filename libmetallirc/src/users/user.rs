@@ -6,15 +6,26 @@ use std::collections::{HashMap, HashSet};
 use std::io::{BufferedStream, IoResult, IoError};
 use std::io::net::tcp::TcpStream;
 use std::sync::{Arc, Mutex, MutexGuard, RWLock};
+use std::sync::atomic::{AtomicUint, SeqCst};
 use std::sync::mpsc_queue::Queue as MPSCQueue;
 
+use time::{get_time, precise_time_ns};
+
 use channels::Membership;
+use extensions::Extensions;
 use messages::{IRCMessage, NumericReply};
 use modes::Modes;
 use util;
 
+use super::batch::{Batch, labeled_response};
+use super::input::InputControl;
+use super::privileges::Privilege;
+
 use uuid::Uuid;
 
+// The user whose labeled command is being handled by the current thread.
+local_data_key!(LABELED_USER: Uuid)
+
 /// Data describing a user.
 #[experimental]
 pub struct UserData {
@@ -22,22 +33,42 @@ pub struct UserData {
     socket: Mutex<BufferedStream<TcpStream>>,
     /// The queue of this user.
     queue: MPSCQueue<IRCMessage>,
+    /// Size in bytes of the messages in the queue.
+    queue_size: AtomicUint,
+    /// Flood control of this user. Mutex protected.
+    input: Mutex<InputControl>,
+    /// Label of the command being handled, with the replies held back for its response.
+    labeled: Mutex<Option<(String, Vec<IRCMessage>)>>,
+    /// Counter for the references of the batches sent to this user.
+    batch_counter: AtomicUint,
     pub id: Uuid,
     pub nickname: String,
     pub username: String,
     pub hostname: String,
     pub realname: String,
+    /// Name of the connection class of this user.
+    pub class: String,
     pub modes: RWLock<Modes>,
+    /// Privileges granted to this user if it is an operator.
+    pub privileges: RWLock<HashSet<Privilege>>,
+    /// Capabilities enabled by the client with CAP.
+    pub capabilities: RWLock<HashSet<String>>,
+    /// Account the user authenticated to when registering, if any. No module logs
+    /// users in or out yet.
+    pub account: RWLock<Option<String>>,
     pub channels: RWLock<HashMap<String, Arc<Membership>>>,
     /// is this user disconnected ?
-    zombie: RWLock<bool>
+    zombie: RWLock<bool>,
+    /// Data attached by modules.
+    pub extensions: Extensions
 }
 
 /// Private handler for this user_data
 #[experimental]
 pub struct PrivateUserDataHandler<'a> {
     data: &'a UserData,
-    socket: MutexGuard<'a BufferedStream<TcpStream>>
+    socket: MutexGuard<'a BufferedStream<TcpStream>>,
+    input: MutexGuard<'a InputControl>
 }
 
 #[experimental]
@@ -46,7 +77,17 @@ impl<'a> PrivateUserDataHandler<'a> {
     /// Retrieves next item in this user's private queue (if any).
     #[experimental]
     pub fn next_queued_message(&self) -> Option<IRCMessage> {
-        self.data.queue.casual_pop()
+        let msg = self.data.queue.casual_pop();
+        if let Some(ref m) = msg {
+            self.data.queue_size.fetch_sub(m.protocol_len() + 2, SeqCst);
+        }
+        msg
+    }
+
+    /// Access to the flood control of this user.
+    #[experimental]
+    pub fn input<'b>(&'b mut self) -> &'b mut InputControl {
+        &mut *self.input
     }
 
     /// Retrieves next message from this client.
@@ -81,37 +122,153 @@ impl UserData {
     #[experimental]
     /// Creates a new user
     pub fn new(tcpsocket: BufferedStream<TcpStream>, nick: String, id: Uuid,
-               username: String, hostname: String, realname: String) -> UserData {
+               username: String, hostname: String, realname: String, class: String) -> UserData {
         UserData {
             socket: Mutex::new(tcpsocket),
             queue: MPSCQueue::new(),
+            queue_size: AtomicUint::new(0),
+            input: Mutex::new(InputControl::new(precise_time_ns() / 1000000, get_time().sec)),
+            labeled: Mutex::new(None),
+            batch_counter: AtomicUint::new(0),
             id: id,
             nickname: nick,
             username: username,
             hostname: hostname,
             realname: realname,
+            class: class,
             channels: RWLock::new(HashMap::new()),
             modes: RWLock::new(Modes::new()),
-            zombie: RWLock::new(false)
+            privileges: RWLock::new(HashSet::new()),
+            capabilities: RWLock::new(HashSet::new()),
+            account: RWLock::new(None),
+            zombie: RWLock::new(false),
+            extensions: Extensions::new()
         }
     }
 
-    /// Pushes a message to this user's personnal queue.
+    /// Pushes a message to this user's personnal queue, with a `time` tag if it
+    /// enabled `server-time`.
     #[experimental]
-    pub fn push_message(&self, msg: IRCMessage) {
+    pub fn push_message(&self, mut msg: IRCMessage) {
+        {
+            let mut labeled = self.labeled.lock();
+            if let Some((_, ref mut held)) = *labeled {
+                if LABELED_USER.get().map_or(false, |id| *id == self.id) {
+                    held.push(msg);
+                    return;
+                }
+            }
+        }
+        if msg.tag("time").is_none() && self.has_capability("server-time") {
+            let now = get_time();
+            msg.set_tag("time", util::format_timestamp(now.sec * 1000 + (now.nsec / 1000000) as i64).as_slice());
+        }
+        self.queue_size.fetch_add(msg.protocol_len() + 2, SeqCst);
         self.queue.push(msg);
     }
 
     /// Pushes a numeric reply to this user's personnal queue.
     #[experimental]
     pub fn push_numreply(&self, rpl: NumericReply, prefix: &str) {
-        self.queue.push(
+        self.push_message(
             rpl.into_prefixed_message(self.nickname.as_slice(), prefix)
         );
     }
 
+    /// Starts the response to the command of given label: the messages sent to this user
+    /// by the current thread are held back, until `suspend_labeled_response` or
+    /// `end_labeled_response` is called.
+    #[experimental]
+    pub fn start_labeled_response(&self, label: &str) {
+        *self.labeled.lock() = Some((label.to_string(), Vec::new()));
+        LABELED_USER.replace(Some(self.id.clone()));
+    }
+
+    /// Stops holding back the messages sent by the current thread, the response staying
+    /// open for the thread running the action returned by the command.
+    #[experimental]
+    pub fn suspend_labeled_response(&self) {
+        if LABELED_USER.get().map_or(false, |id| *id == self.id) {
+            LABELED_USER.replace(None);
+        }
+    }
+
+    /// Holds back again the messages sent to this user by the current thread, if the
+    /// response to a command is open.
+    #[experimental]
+    pub fn resume_labeled_response(&self) {
+        if self.labeled.lock().is_some() {
+            LABELED_USER.replace(Some(self.id.clone()));
+        }
+    }
+
+    /// Ends the response to the labeled command, if any, and sends it.
+    #[experimental]
+    pub fn end_labeled_response(&self, server: &str) {
+        self.suspend_labeled_response();
+        let (label, replies) = match self.labeled.lock().take() {
+            Some(response) => response,
+            None => return
+        };
+        let lines = labeled_response(label.as_slice(), replies, server, self.has_capability("batch"),
+                                     || self.next_batch_reference());
+        for msg in lines.into_iter() {
+            self.push_message(msg);
+        }
+    }
+
+    /// Starts a batch of given type sent by given server, within `parent` if it is nested.
+    /// If the client did not enable `batch`, the messages of the batch are sent as plain lines.
+    #[experimental]
+    pub fn start_batch(&self, server: &str, kind: &str, params: Vec<String>, parent: Option<&Batch>) -> Batch {
+        self.start_batch_tagged(server, kind, params, parent, Vec::new())
+    }
+
+    /// Starts a batch like `start_batch`, with given tags on its opening line.
+    #[experimental]
+    pub fn start_batch_tagged(&self, server: &str, kind: &str, params: Vec<String>, parent: Option<&Batch>,
+                              tags: Vec<(String, String)>) -> Batch {
+        let (batch, start) = Batch::open(self.has_capability("batch"), || self.next_batch_reference(),
+                                         server, kind, params, parent, tags);
+        if let Some(msg) = start {
+            self.push_message(msg);
+        }
+        batch
+    }
+
+    /// Pushes a message of given batch to this user's personnal queue.
+    #[experimental]
+    pub fn push_batched(&self, batch: &Batch, mut msg: IRCMessage) {
+        batch.tag_message(&mut msg);
+        self.push_message(msg);
+    }
+
+    /// Ends given batch. Its nested batches must be ended first.
+    #[experimental]
+    pub fn end_batch(&self, batch: Batch) {
+        if let Some(msg) = batch.close() {
+            self.push_message(msg);
+        }
+    }
+
+    fn next_batch_reference(&self) -> String {
+        format!("b{}", self.batch_counter.fetch_add(1, SeqCst))
+    }
+
+    /// Size in bytes of the messages waiting to be sent to this user (its SendQ).
+    #[experimental]
+    pub fn queue_size(&self) -> uint {
+        self.queue_size.load(SeqCst)
+    }
+
     /// Sends given message to all known users
     pub fn send_to_known(&self, msg: IRCMessage) {
+        self.send_to_known_filtered(msg, |_| true);
+    }
+
+    /// Sends given message to the known users accepted by the filter.
+    #[experimental]
+    pub fn send_to_known_filtered(&self, msg: IRCMessage, filter: |&UserData| -> bool) {
         let mut done: HashSet<Uuid> = HashSet::new();
         for c in self.channels.read().values() {
             if let Some(chan) = c.channel.upgrade() {
@@ -119,7 +276,8 @@ impl UserData {
                     if !done.contains(u) {
                         done.insert(u.clone());
                         if let Some(other) = m.user.upgrade() {
-                            other.read().push_message(msg.clone());
+                            let other = other.read();
+                            if filter(&*other) { other.push_message(msg.clone()); }
                         }
                     }
                 });
@@ -127,13 +285,26 @@ impl UserData {
         }
     }
 
+    /// Sends given message to the known users having enabled given capability.
+    #[experimental]
+    pub fn send_to_known_with_cap(&self, msg: IRCMessage, cap: &str) {
+        self.send_to_known_filtered(msg, |u| u.has_capability(cap));
+    }
+
+    /// Has the client of this user enabled given capability ?
+    #[experimental]
+    pub fn has_capability(&self, cap: &str) -> bool {
+        self.capabilities.read().contains(&cap.to_string())
+    }
+
     /// Retrieves the private handler to this user. Only on can exist at a given time.
     /// If an other one exists, will block util it is released.
     #[experimental]
     pub fn private_handler<'a>(&'a self) -> PrivateUserDataHandler<'a> {
         PrivateUserDataHandler {
             data: self,
-            socket: self.socket.lock()
+            socket: self.socket.lock(),
+            input: self.input.lock()
         }
     }
 
@@ -154,6 +325,12 @@ impl UserData {
         *self.zombie.read()
     }
 
+    /// Is this user an operator with given privilege ?
+    #[experimental]
+    pub fn has_privilege(&self, privilege: Privilege) -> bool {
+        self.modes.read().get('o'.to_ascii()) && self.privileges.read().contains(&privilege)
+    }
+
     /// Is this user in given chan ?
     #[experimental]
     pub fn membership<'a>(&'a self, chan: &str) -> Option<Arc<Membership>> {
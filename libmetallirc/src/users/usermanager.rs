@@ -34,7 +34,7 @@ impl UserManager {
     /// Returns the new Uuid on success, returns the NewUser on failure
     /// (if a field was missing or the nickname already used).
     #[experimental]
-    pub fn insert(&mut self, user: NewUser) -> Result<Uuid, NewUser> {
+    pub fn insert(&mut self, user: NewUser, class: String) -> Result<Uuid, NewUser> {
         if user.nickname.is_none() || user.username.is_none() || user.username.is_none() {
             Err(user)
         } else {
@@ -46,13 +46,15 @@ impl UserManager {
                 // better safe than sorry ?
                 while self.users.contains_key(&id) { id = Uuid::new_v4(); }
 
-                // TODO auto-detect host
                 let full_user = UserData::new(user.socket,
                                               user.nickname.unwrap(),
                                               id.clone(),
                                               user.username.unwrap(),
-                                              "metallirc".to_string(),
-                                              user.realname.unwrap());
+                                              user.hostname,
+                                              user.realname.unwrap(),
+                                              class);
+                *full_user.capabilities.write() = user.capabilities;
+                *full_user.account.write() = user.account;
 
                 self.users.insert(id.clone(), Arc::new(RWLock::new(full_user)));
                 self.nicks.insert(lower_nick, id.clone());
@@ -126,6 +128,27 @@ impl UserManager {
         true
     }
 
+    /// Changes the username and hostname of given uuid.
+    /// Fails if the uuid does not exists.
+    #[experimental]
+    pub fn change_host(&mut self, id: &Uuid, username: String, hostname: String) {
+        let mut user = self.users[*id].write();
+        user.username = username;
+        user.hostname = hostname;
+    }
+
+    /// Counts the users in given connection class.
+    #[experimental]
+    pub fn count_in_class(&self, class: &str) -> uint {
+        self.users.values().filter(|u| u.read().class.as_slice() == class).count()
+    }
+
+    /// Number of registered users.
+    #[experimental]
+    pub fn len(&self) -> uint {
+        self.users.len()
+    }
+
     #[experimental]
     pub fn is_empty(&self) -> bool {
         self.users.is_empty()
@@ -134,7 +157,7 @@ impl UserManager {
     #[experimental]
     pub fn del_user(&mut self, id: &Uuid) {
         match self.users.remove(id) {
-            Some(user_data) => { self.nicks.remove(&user_data.read().nickname); }
+            Some(user_data) => { self.nicks.remove(&util::label_to_lower(user_data.read().nickname.as_slice())); }
             None => {}
         }
     }
@@ -0,0 +1,118 @@
+//! History of the nicknames of departed users, for WHOWAS.
+
+#![experimental]
+
+use std::collections::HashMap;
+
+use util;
+
+/// A record of a user who left or changed its nickname.
+#[experimental]
+#[deriving(Clone)]
+pub struct WhowasEntry {
+    pub nickname: String,
+    pub username: String,
+    pub hostname: String,
+    pub realname: String,
+    /// Name of the server the user was connected to.
+    pub server: String,
+    /// Timestamp of the departure.
+    pub signoff: i64
+}
+
+/// The history of the nicknames, keeping the most recent records of each one.
+#[experimental]
+pub struct WhowasHistory {
+    /// Records by lowercase nickname, the most recent first.
+    entries: HashMap<String, Vec<WhowasEntry>>,
+    /// Lowercase nicknames, in order of their last record.
+    order: Vec<String>
+}
+
+#[experimental]
+impl WhowasHistory {
+
+    /// Creates an empty history.
+    #[experimental]
+    pub fn new() -> WhowasHistory {
+        WhowasHistory {
+            entries: HashMap::new(),
+            order: Vec::new()
+        }
+    }
+
+    /// Records an entry, keeping at most `per_nick` records for each nickname and
+    /// `max_nicks` different nicknames, the oldest ones being dropped first.
+    #[experimental]
+    pub fn record(&mut self, entry: WhowasEntry, per_nick: uint, max_nicks: uint) {
+        if per_nick == 0 || max_nicks == 0 { return; }
+        let lower = util::label_to_lower(entry.nickname.as_slice());
+        // move the nick at the end of the order
+        if let Some(pos) = self.order.iter().position(|n| *n == lower) {
+            self.order.remove(pos);
+        }
+        self.order.push(lower.clone());
+        let list = match self.entries.remove(&lower) {
+            Some(mut list) => {
+                list.insert(0, entry);
+                list.truncate(per_nick);
+                list
+            },
+            None => vec!(entry)
+        };
+        self.entries.insert(lower, list);
+        while self.order.len() > max_nicks {
+            if let Some(old) = self.order.remove(0) {
+                self.entries.remove(&old);
+            }
+        }
+    }
+
+    /// The records of given nickname, the most recent first.
+    #[experimental]
+    pub fn lookup(&self, nick: &str) -> Vec<&WhowasEntry> {
+        match self.entries.get(&util::label_to_lower(nick)) {
+            Some(list) => list.iter().collect(),
+            None => Vec::new()
+        }
+    }
+
+    /// Number of nicknames in the history.
+    #[experimental]
+    pub fn len(&self) -> uint {
+        self.order.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{WhowasEntry, WhowasHistory};
+
+    fn entry(nick: &str, signoff: i64) -> WhowasEntry {
+        WhowasEntry {
+            nickname: nick.to_string(),
+            username: "user".to_string(),
+            hostname: "host".to_string(),
+            realname: "Real Name".to_string(),
+            server: "irc.example.com".to_string(),
+            signoff: signoff
+        }
+    }
+
+    #[test]
+    fn test_whowas_bounds() {
+        let mut history = WhowasHistory::new();
+        for i in range(0i64, 5) {
+            history.record(entry("Foo", i), 3, 2);
+        }
+        let found = history.lookup("foo");
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].signoff, 4);
+        history.record(entry("bar", 5), 3, 2);
+        history.record(entry("baz", 6), 3, 2);
+        assert_eq!(history.len(), 2);
+        assert!(history.lookup("foo").is_empty());
+        assert_eq!(history.lookup("BAR").len(), 1);
+    }
+}
@@ -30,6 +30,16 @@ pub fn check_label(label: &str) -> bool {
     true
 }
 
+/// Maximum length of a nickname.
+#[experimental]
+pub const NICKLEN: uint = 30;
+
+/// Checks if a nickname is valid
+#[experimental]
+pub fn check_nickname(nick: &str) -> bool {
+    nick.len() <= NICKLEN && check_label(nick)
+}
+
 /// Checks if a chan name (including prefix) is valid
 #[experimental]
 pub fn check_channame(mask: &str) -> bool {
@@ -83,10 +93,137 @@ pub fn matches_mask(mut label: &str, mut mask: &str) -> bool {
     mask.is_empty() || mask == "*"
 }
 
+/// Splits a text in lines of at most `width` bytes, breaking at spaces when possible.
+#[experimental]
+pub fn wrap_text(text: &str, width: uint) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut rest = text;
+    while rest.len() > width {
+        // last char boundary fitting in the line
+        let mut cut = width;
+        while !rest.is_char_boundary(cut) { cut -= 1; }
+        // a line holds at least one char
+        if cut == 0 { cut = rest.char_range_at(0).next; }
+        let (line, tail) = match rest.slice_to(cut).rfind(' ') {
+            Some(pos) if pos > 0 => (rest.slice_to(pos), rest.slice_from(pos + 1)),
+            _ => (rest.slice_to(cut), rest.slice_from(cut))
+        };
+        lines.push(line.to_string());
+        rest = tail;
+    }
+    lines.push(rest.to_string());
+    lines
+}
+
+/// Groups words so that each group, joined with spaces, holds in `width` bytes.
+/// A word longer than `width` makes a group on its own.
+#[experimental]
+pub fn group_by_length<'a>(words: &'a [String], width: uint) -> Vec<Vec<&'a str>> {
+    let mut groups = Vec::new();
+    let mut current = Vec::new();
+    let mut length = 0u;
+    for w in words.iter() {
+        if current.len() > 0 && length + 1 + w.len() > width {
+            groups.push(current);
+            current = Vec::new();
+            length = 0;
+        }
+        if current.len() > 0 { length += 1; }
+        length += w.len();
+        current.push(w.as_slice());
+    }
+    if current.len() > 0 { groups.push(current); }
+    groups
+}
+
+/// Date of given day counted from the epoch, as `(year, month, day)`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, month, day)
+}
+
+/// Number of days from the epoch to given date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Formats a time in milliseconds since the epoch as an IRCv3 timestamp,
+/// `YYYY-MM-DDThh:mm:ss.sssZ`.
+#[experimental]
+pub fn format_timestamp(ms: i64) -> String {
+    let secs = ms / 1000;
+    let (year, month, day) = civil_from_days(secs / 86400);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", year, month, day,
+            (secs % 86400) / 3600, (secs % 3600) / 60, secs % 60, ms % 1000)
+}
+
+/// Parses an IRCv3 timestamp, the milliseconds being optional.
+/// Returns the time in milliseconds since the epoch.
+#[experimental]
+pub fn parse_timestamp(text: &str) -> Option<i64> {
+    if text.len() < 20 || !text.is_char_boundary(19) || !text.ends_with("Z") { return None; }
+    let bytes = text.as_bytes();
+    if bytes[4] != b'-' || bytes[7] != b'-' || bytes[10] != b'T' || bytes[13] != b':' || bytes[16] != b':' {
+        return None;
+    }
+    let field = |start: uint, end: uint| -> Option<i64> {
+        let part = text.slice(start, end);
+        if part.chars().all(|c| c.is_digit()) { from_str::<i64>(part) } else { None }
+    };
+    let (year, month, day) = match (field(0, 4), field(5, 7), field(8, 10)) {
+        (Some(y), Some(m), Some(d)) if m >= 1 && m <= 12 && d >= 1 && d <= 31 => (y, m, d),
+        _ => return None
+    };
+    let (hour, min, sec) = match (field(11, 13), field(14, 16), field(17, 19)) {
+        (Some(h), Some(m), Some(s)) if h < 24 && m < 60 && s < 61 => (h, m, s),
+        _ => return None
+    };
+    let fraction = text.slice(19, text.len() - 1);
+    let millis = if fraction.len() == 0 {
+        0
+    } else if fraction.starts_with(".") && fraction.len() > 1 {
+        // only the milliseconds are kept
+        let digits = fraction.slice_from(1);
+        if !digits.chars().all(|c| c.is_digit()) { return None; }
+        let mut ms = 0i64;
+        for (i, c) in digits.chars().chain("00".chars()).take(3).enumerate() {
+            ms += (c as u32 - '0' as u32) as i64 * [100, 10, 1][i];
+        }
+        ms
+    } else {
+        return None;
+    };
+    let days = days_from_civil(year, month, day);
+    Some(((days * 86400 + hour * 3600 + min * 60 + sec) * 1000) + millis)
+}
+
 #[cfg(test)]
 mod test {
 
-    use super::matches_mask;
+    use super::{matches_mask, wrap_text, group_by_length, format_timestamp, parse_timestamp};
+    use super::{check_nickname, NICKLEN};
+
+    #[test]
+    fn test_check_nickname() {
+        assert!(check_nickname("foo[bar]"));
+        assert!(!check_nickname("1foo"));
+        assert!(!check_nickname(""));
+        assert!(check_nickname(String::from_char(NICKLEN, 'a').as_slice()));
+        assert!(!check_nickname(String::from_char(NICKLEN + 1, 'a').as_slice()));
+    }
 
     #[test]
     fn test_matches_mask() {
@@ -103,4 +240,32 @@ mod test {
         assert!(!matches_mask("foo", "fo"));
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(wrap_text("foo bar", 10), vec!("foo bar".to_string()));
+        assert_eq!(wrap_text("foo bar baz", 7), vec!("foo bar".to_string(), "baz".to_string()));
+        assert_eq!(wrap_text("foobarbaz", 4),
+                   vec!("foob".to_string(), "arba".to_string(), "z".to_string()));
+        assert_eq!(wrap_text("", 4), vec!("".to_string()));
+    }
+
+    #[test]
+    fn test_group_by_length() {
+        let words = vec!("foo".to_string(), "bar".to_string(), "bazinga".to_string(), "qux".to_string());
+        assert_eq!(group_by_length(words.as_slice(), 7), vec!(vec!("foo", "bar"), vec!("bazinga"), vec!("qux")));
+        assert_eq!(group_by_length(words.as_slice(), 5), vec!(vec!("foo"), vec!("bar"), vec!("bazinga"), vec!("qux")));
+        assert!(group_by_length(&[], 5).is_empty());
+    }
+
+    #[test]
+    fn test_timestamps() {
+        assert_eq!(format_timestamp(0).as_slice(), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_timestamp(1546612406123).as_slice(), "2019-01-04T14:33:26.123Z");
+        assert_eq!(parse_timestamp("2019-01-04T14:33:26.123Z"), Some(1546612406123));
+        assert_eq!(parse_timestamp("2000-02-29T23:59:59Z"), Some(951868799000));
+        assert_eq!(parse_timestamp("2000-02-29T23:59:59.5Z"), Some(951868799500));
+        assert_eq!(parse_timestamp("2000-02-29 23:59:59Z"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+}
@@ -0,0 +1,100 @@
+//! Welcome burst sent to newly registered users.
+
+//! The burst is made of RPL_WELCOME, RPL_YOURHOST, RPL_CREATED, RPL_MYINFO and
+//! the RPL_ISUPPORT lines, the modes and most tokens being given by the modules.
+
+#![experimental]
+
+use time::at;
+
+use messages::numericreply;
+use users::UserData;
+use util::NICKLEN;
+use ServerData;
+use {NETWORK, VERSION};
+
+/// Maximum number of tokens in one RPL_ISUPPORT line.
+const MAX_TOKENS_PER_LINE: uint = 13;
+/// Maximum total length of the tokens of one RPL_ISUPPORT line, leaving room in
+/// the 512 bytes for the prefix, the nick and the final text.
+const MAX_TOKENS_LENGTH: uint = 350;
+
+/// The ISUPPORT tokens given by the server itself.
+#[experimental]
+pub fn server_isupport_tokens() -> Vec<String> {
+    vec!(
+        "CHANTYPES=#".to_string(),
+        "CASEMAPPING=strict-rfc1459".to_string(),
+        format!("NETWORK={}", NETWORK),
+        format!("NICKLEN={}", NICKLEN)
+    )
+}
+
+/// Splits the tokens in groups fitting in RPL_ISUPPORT lines.
+#[experimental]
+pub fn split_isupport_tokens<'a>(tokens: &'a [String]) -> Vec<Vec<&'a str>> {
+    let mut lines = Vec::new();
+    let mut current = Vec::new();
+    let mut length = 0u;
+    for t in tokens.iter() {
+        if current.len() > 0 && (current.len() == MAX_TOKENS_PER_LINE
+                                 || length + t.len() + 1 > MAX_TOKENS_LENGTH) {
+            lines.push(current);
+            current = Vec::new();
+            length = 0;
+        }
+        length += t.len() + 1;
+        current.push(t.as_slice());
+    }
+    if current.len() > 0 { lines.push(current); }
+    lines
+}
+
+/// Sends the welcome burst to given user.
+#[experimental]
+pub fn send_welcome(user: &UserData, srv: &ServerData) {
+    let settings = srv.settings.read();
+    let name = settings.name.as_slice();
+    user.push_numreply(
+        numericreply::RPL_WELCOME(format!("{} IRC Network", NETWORK).as_slice(), user.get_fullname().as_slice()),
+        name
+    );
+    user.push_numreply(numericreply::RPL_YOURHOST(name, VERSION), name);
+    user.push_numreply(
+        numericreply::RPL_CREATED(at(srv.start_time).rfc822z().to_string().as_slice()),
+        name
+    );
+    let modules = srv.modules_handler.read();
+    user.push_numreply(
+        numericreply::RPL_MYINFO(
+            name,
+            VERSION,
+            modules.supported_usermodes().as_slice(),
+            modules.supported_chanmodes().as_slice()
+        ),
+        name
+    );
+    let mut tokens = server_isupport_tokens();
+    tokens.extend(modules.isupport_tokens().into_iter());
+    for line in split_isupport_tokens(tokens.as_slice()).into_iter() {
+        user.push_numreply(numericreply::RPL_ISUPPORT(line), name);
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::split_isupport_tokens;
+
+    #[test]
+    fn test_split_isupport_tokens() {
+        let few: Vec<String> = range(0u, 5).map(|i| format!("T{}", i)).collect();
+        assert_eq!(split_isupport_tokens(few.as_slice()).len(), 1);
+        let many: Vec<String> = range(0u, 30).map(|i| format!("T{}", i)).collect();
+        let lines = split_isupport_tokens(many.as_slice());
+        assert_eq!(lines.iter().map(|l| l.len()).collect::<Vec<uint>>(), vec!(13, 13, 4));
+        let long: Vec<String> = range(0u, 4).map(|_| String::from_char(150, 'X')).collect();
+        assert_eq!(split_isupport_tokens(long.as_slice()).len(), 2);
+        assert!(split_isupport_tokens(&[]).is_empty());
+    }
+}
@@ -7,45 +7,59 @@ extern crate metallirc;
 extern crate uuid;
 extern crate toml;
 
-use std::collections::HashMap;
-use std::sync::RWLock;
+use std::ascii::Ascii;
+use std::sync::Arc;
 
 use uuid::Uuid;
 
-use metallirc::messages::{IRCMessage, TextMessage, User, numericreply};
+use metallirc::extensions::SerializableExtension;
+use metallirc::messages::{IRCMessage, TextMessage, User, Privmsg, numericreply};
 use metallirc::ServerData;
 use metallirc::users::UserData;
 
 use metallirc::modules::{RecyclingAction, Nothing};
-use metallirc::modules::{MessageSendingHandler, CommandHandler};
+use metallirc::modules::{MessageSendingHandler, CommandHandler, EventHandler, UserModeHandler, UnloadHandler};
 
 // Public init()
-use metallirc::modules::Module;
+use metallirc::modules::{ModuleRegistrar, PRIORITY_NORMAL};
 use metallirc::logging::Logger;
 
-pub struct ModAway {
-    messages: RWLock<HashMap<Uuid, String>>
+/// The away message of a user, stored in its extensions.
+struct AwayMessage {
+    text: String
 }
 
-impl ModAway {
-    pub fn init() -> ModAway {
-        ModAway {
-            messages: RWLock::new(HashMap::new())
-        }
+impl SerializableExtension for AwayMessage {
+    fn serialize(&self) -> String {
+        self.text.clone()
+    }
+
+    fn deserialize(data: &str) -> Option<AwayMessage> {
+        Some(AwayMessage { text: data.to_string() })
+    }
+}
+
+/// The AWAY message announcing the status of a user to `away-notify` clients.
+fn away_notification(user: &UserData, text: Option<String>) -> IRCMessage {
+    IRCMessage {
+        tags: Vec::new(),
+        prefix: Some(user.get_fullname()),
+        command: "AWAY".to_string(),
+        args: vec!(),
+        suffix: text
     }
 }
 
-module!(ModAway is CommandHandler, MessageSendingHandler)
+pub struct ModAway;
 
 impl CommandHandler for ModAway {
-    fn handle_command(&self, user: &UserData, user_uuid: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+    fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "AWAY" { return (false, Nothing); }
-
         if let Some(mut args) = cmd.as_nparams(0,1) {
             if let Some(msg) = args.pop() {
                 // new away message
-                self.messages.write().insert(user_uuid.clone(), msg);
+                user.send_to_known_with_cap(away_notification(user, Some(msg.clone())), "away-notify");
+                user.extensions.insert(AwayMessage { text: msg });
                 user.modes.write().set('a'.to_ascii(), true);
                 user.push_numreply(
                     numericreply::RPL_NOWAWAY,
@@ -53,7 +67,10 @@ impl CommandHandler for ModAway {
                 );
             } else {
                 // unmark away status
-                self.messages.write().remove(user_uuid);
+                if user.extensions.contains::<AwayMessage>() {
+                    user.send_to_known_with_cap(away_notification(user, None), "away-notify");
+                }
+                user.extensions.remove::<AwayMessage>();
                 user.modes.write().set('a'.to_ascii(), false);
                 user.push_numreply(
                     numericreply::RPL_UNAWAY,
@@ -67,22 +84,76 @@ impl CommandHandler for ModAway {
 
 impl MessageSendingHandler for ModAway {
     fn handle_message_sending(&self, msg: TextMessage, srv: &ServerData) -> Option<TextMessage> {
-        if !msg.notice { // it's a PRIVMSG
+        if msg.kind == Privmsg { // it's a PRIVMSG
         if let User(ref tid, ref tnick) = msg.target { // from a user
         if let User(ref sid, _) = msg.source { // to a user
-        if let Some(txt) = self.messages.read().get(tid) { // and the target is away
-            srv.users.read().get_user_by_uuid(sid).unwrap().push_numreply(
+        let manager = srv.users.read();
+        if let Some(target) = manager.get_user_by_uuid(tid) {
+        if let Some(txt) = target.extensions.with(|a: &AwayMessage| a.text.clone()) { // and the target is away
+            manager.get_user_by_uuid(sid).unwrap().push_numreply(
                 numericreply::RPL_AWAY(tnick.as_slice(), txt.as_slice()),
                 srv.settings.read().name.as_slice()
             );
-        }}}}
+        }}}}}
         Some(msg)
     }
 }
 
-#[no_mangle]
-pub fn init(_: &toml::TomlTable, _: &Logger) -> Vec<Box<Module + 'static + Send + Sync>> {
-    init_modules!(
-        ModAway::init()
-    )
-}
\ No newline at end of file
+impl EventHandler for ModAway {
+    fn on_join(&self, user: &UserData, chan: &str, srv: &ServerData) {
+        // the members of the chan may not have known the user was away
+        if let Some(txt) = user.extensions.with(|a: &AwayMessage| a.text.clone()) {
+            let msg = away_notification(user, Some(txt));
+            if let Some(handle) = srv.channels.read().chan_handle(chan) {
+                handle.read().apply_to_members(|id, m| {
+                    if *id == user.id { return; }
+                    let member = m.user.upgrade().unwrap();
+                    let member = member.read();
+                    if member.has_capability("away-notify") {
+                        member.push_message(msg.clone());
+                    }
+                });
+            }
+        }
+    }
+}
+
+impl UserModeHandler for ModAway {
+    fn handle_usermode_request(&self, _: &UserData, _: &UserData, flag: Ascii, _: bool,
+                               _: &ServerData) -> Option<bool> {
+        // the away status is only changed by the AWAY command
+        if flag == 'a'.to_ascii() { Some(false) } else { None }
+    }
+
+    fn usermodes(&self) -> Vec<char> {
+        vec!('a')
+    }
+}
+
+impl UnloadHandler for ModAway {
+    fn pre_unload(&self, reloading: bool, srv: &ServerData) -> Option<String> {
+        if !reloading {
+            // nobody will handle away status anymore
+            srv.users.read().apply_to_all(|user| {
+                if user.extensions.contains::<AwayMessage>() {
+                    user.modes.write().set('a'.to_ascii(), false);
+                }
+            });
+        }
+        // the messages themselves are kept by the extension
+        None
+    }
+}
+
+declare_module!("away", "0.0.1", ["core"], init)
+
+fn init(reg: &mut ModuleRegistrar, _: &toml::TomlTable, _: &Logger) {
+    let away = Arc::new(ModAway);
+    reg.add_serializable_extension::<AwayMessage>("AwayMessage");
+    reg.add_capability("away-notify");
+    reg.add_command_handler("ModAway", &["AWAY"], PRIORITY_NORMAL, away.clone());
+    reg.add_message_handler("ModAway", PRIORITY_NORMAL, away.clone());
+    reg.add_event_handler("ModAway", PRIORITY_NORMAL, away.clone());
+    reg.add_usermode_handler("ModAway", PRIORITY_NORMAL, away.clone());
+    reg.add_unload_handler("ModAway", away);
+}
@@ -0,0 +1,161 @@
+//! Server bans commands.
+
+#![experimental]
+
+use metallirc::bans::{Ban, BanKind, BanManager, KLine, DLine, QLine, check_cidr};
+use metallirc::logging::{Error, Info};
+use metallirc::messages::{IRCMessage, numericreply};
+use metallirc::ServerData;
+use metallirc::users::{UserData, PrivKline};
+
+use time::get_time;
+use uuid::Uuid;
+
+use metallirc::modules::{RecyclingAction, Nothing, Kill};
+use metallirc::modules::{CommandHandler, send_needmoreparams};
+
+/// Handles KLINE, DLINE and QLINE (and their GLINE and ZLINE aliases)
+/// as well as their removal.
+pub struct CmdBans;
+
+impl CommandHandler for CmdBans {
+    fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        let (kind, adding) = match cmd.command.as_slice() {
+            "KLINE" | "GLINE" => (KLine, true),
+            "DLINE" | "ZLINE" => (DLine, true),
+            "QLINE" => (QLine, true),
+            "UNKLINE" | "UNGLINE" => (KLine, false),
+            "UNDLINE" | "UNZLINE" => (DLine, false),
+            "UNQLINE" => (QLine, false),
+            _ => return (false, Nothing)
+        };
+
+        if !user.has_privilege(PrivKline) {
+            user.push_numreply(
+                numericreply::ERR_NOPRIVILIGES,
+                srv.settings.read().name.as_slice()
+            );
+            return (true, Nothing);
+        }
+
+        if adding {
+            (true, add_ban(user, kind, cmd, srv))
+        } else {
+            if let Some(args) = cmd.as_nparams(1,0) {
+                let removed = {
+                    let mut bans = srv.bans.write();
+                    let removed = bans.remove(kind, args[0].as_slice());
+                    if removed { save_bans(&*bans, srv); }
+                    removed
+                };
+                if removed {
+                    srv.logger.log(Info, format!("{} removed {} for {}.",
+                        user.get_fullname(), kind.name(), args[0]));
+                    send_notice(user, format!("Removed {} for {}.", kind.name(), args[0]), srv);
+                } else {
+                    send_notice(user, format!("No {} for {}.", kind.name(), args[0]), srv);
+                }
+            } else {
+                send_needmoreparams(user, cmd.command.as_slice(), srv);
+            }
+            (true, Nothing)
+        }
+    }
+}
+
+/// Parses the arguments of a ban command: `[duration] <mask> [:reason]`,
+/// the duration being in minutes. Returns the duration in seconds.
+fn parse_ban_args(cmd: &IRCMessage) -> Option<(Option<i64>, String, String)> {
+    let duration = cmd.args.get(0).and_then(|a| from_str::<i64>(a.as_slice()));
+    let params = if duration.is_some() {
+        cmd.as_nparams(2,1).map(|mut v| { v.remove(0); v })
+    } else {
+        cmd.as_nparams(1,1)
+    };
+    params.map(|mut v| {
+        let reason = if v.len() > 1 { v.pop().unwrap() } else { "No reason".to_string() };
+        (duration.map(|m| m * 60), v.pop().unwrap(), reason)
+    })
+}
+
+fn add_ban(user: &UserData, kind: BanKind, cmd: &IRCMessage, srv: &ServerData) -> RecyclingAction {
+    let (duration, mask, reason) = match parse_ban_args(cmd) {
+        Some(t) => t,
+        None => {
+            send_needmoreparams(user, cmd.command.as_slice(), srv);
+            return Nothing;
+        }
+    };
+    let mask = match kind {
+        KLine if !mask.as_slice().contains_char('@') => format!("*@{}", mask),
+        DLine if !check_cidr(mask.as_slice()) => {
+            send_notice(user, format!("Invalid IP or CIDR range: {}.", mask), srv);
+            return Nothing;
+        },
+        _ => mask
+    };
+
+    let now = get_time().sec;
+    let ban = Ban {
+        kind: kind,
+        mask: mask.clone(),
+        reason: reason.clone(),
+        setter: user.nickname.clone(),
+        set_at: now,
+        expires: duration.map(|d| now + d)
+    };
+
+    // find the already connected users to disconnect, including the ones
+    // holding a nickname which is now reserved
+    let mut victims = Vec::new();
+    srv.users.read().apply_to_all(|u| {
+        let label = match kind {
+            KLine => format!("{}@{}", u.username, u.hostname),
+            DLine => u.hostname.clone(),
+            QLine => u.nickname.clone()
+        };
+        if ban.matches(label.as_slice()) { victims.push(u.id.clone()); }
+    });
+
+    {
+        let mut bans = srv.bans.write();
+        for old in bans.expire(now).iter() {
+            srv.logger.log(Info, format!("{} for {} expired.", old.kind.name(), old.mask));
+        }
+        bans.add(ban);
+        save_bans(&*bans, srv);
+    }
+    srv.logger.log(Info, format!("{} added {} for {} ({}).",
+        user.get_fullname(), kind.name(), mask, reason));
+    send_notice(user, match duration {
+        Some(d) => format!("Added temporary {} for {} ({} minutes).", kind.name(), mask, d / 60),
+        None => format!("Added {} for {}.", kind.name(), mask)
+    }, srv);
+
+    if victims.is_empty() {
+        Nothing
+    } else {
+        Kill(victims, format!("{}: {}", kind.name(), reason))
+    }
+}
+
+/// Writes the bans to the ban file, logging any error.
+fn save_bans(bans: &BanManager, srv: &ServerData) {
+    if let Err(e) = bans.save() {
+        srv.logger.log(Error, format!("Unable to save bans : {}", e));
+    }
+}
+
+/// Sends a NOTICE from the server to the user.
+pub fn send_notice(user: &UserData, text: String, srv: &ServerData) {
+    user.push_message(
+        IRCMessage {
+            tags: Vec::new(),
+            prefix: Some(srv.settings.read().name.clone()),
+            command: "NOTICE".to_string(),
+            args: vec!(user.nickname.clone()),
+            suffix: Some(text)
+        }
+    );
+}
@@ -5,9 +5,11 @@
 use std::collections::hash_map::{Vacant, Occupied};
 
 use metallirc::messages::{IRCMessage, numericreply};
+use metallirc::modes::{membership_prefix, can_kick, HALFOP_RANK};
 use metallirc::ServerData;
-use metallirc::users::UserData;
+use metallirc::users::{UserData, PrivOverride};
 use metallirc::util;
+use metallirc::util::group_by_length;
 
 use uuid::Uuid;
 
@@ -16,33 +18,35 @@ use metallirc::modules::{CommandHandler, send_needmoreparams};
 
 pub struct CmdJoin;
 
-module!(CmdJoin is CommandHandler)
-
 impl CommandHandler for CmdJoin {
     fn handle_command(&self, user: &UserData, user_uuid: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "JOIN" { return (false, Nothing); }
-
         if let Some(args) = cmd.as_nparams(1,1) {
             // TODO handle chan with passwords
             for chan in args[0].as_slice().split_terminator(',') {
                 if util::check_channame(chan) {
+                    if user.membership(chan).is_some() {
+                        // already there, nothing to do
+                        continue;
+                    }
+                    if user.channels.read().len()
+                        >= srv.settings.read().get_class(user.class.as_slice()).max_channels {
+                        user.push_numreply(
+                            numericreply::ERR_TOOMANYCHANNELS(chan),
+                            srv.settings.read().name.as_slice()
+                        );
+                        continue;
+                    }
+                    if !srv.modules_handler.read().allow_join(user, chan, srv) {
+                        continue;
+                    }
                     let has_chan = srv.channels.read().has_chan(chan);
                     if has_chan {
                         srv.channels.read().join(srv.users.read().arc_ref(user_uuid).unwrap(), chan);
                     } else {
                         srv.channels.write().join_create(srv.users.read().arc_ref(user_uuid).unwrap(), chan);
                     }
-                    srv.channels.read().send_to_chan(
-                        chan,
-                        IRCMessage {
-                            prefix: Some(user.get_fullname()),
-                            command: "JOIN".to_string(),
-                            args: vec!(chan.to_string()),
-                            suffix: None
-                        },
-                        None
-                    );
+                    send_join(user, chan, srv);
                     send_names(user, chan, srv);
                     super::topic::send_topic_to_user(
                         user,
@@ -50,6 +54,7 @@ impl CommandHandler for CmdJoin {
                         chan,
                         srv
                     );
+                    srv.modules_handler.read().notify_join(user, chan, srv);
                 } else {
                     // invalid chan name
                     user.push_numreply(
@@ -66,23 +71,56 @@ impl CommandHandler for CmdJoin {
     }
 }
 
-pub struct CmdPart;
+/// Announces the arrival of a user in a chan to its members. Those having enabled
+/// `extended-join` also get the account and real name of the user.
+fn send_join(user: &UserData, chan: &str, srv: &ServerData) {
+    let join = IRCMessage {
+        tags: Vec::new(),
+        prefix: Some(user.get_fullname()),
+        command: "JOIN".to_string(),
+        args: vec!(chan.to_string()),
+        suffix: None
+    };
+    let extended = IRCMessage {
+        tags: Vec::new(),
+        prefix: Some(user.get_fullname()),
+        command: "JOIN".to_string(),
+        args: vec!(chan.to_string(), user.account.read().clone().unwrap_or("*".to_string())),
+        suffix: Some(user.realname.clone())
+    };
+    if let Some(handle) = srv.channels.read().chan_handle(chan) {
+        handle.read().apply_to_members(|_, m| {
+            let member = m.user.upgrade().unwrap();
+            let member = member.read();
+            if member.has_capability("extended-join") {
+                member.push_message(extended.clone());
+            } else {
+                member.push_message(join.clone());
+            }
+        });
+    }
+}
 
-module!(CmdPart is CommandHandler)
+pub struct CmdPart;
 
 impl CommandHandler for CmdPart {
     fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "PART" { return (false, Nothing); }
-
         if let Some(args) = cmd.as_nparams(1,1) {
             let partmsg = if args.len() > 1 { args[1].as_slice() } else { "Leaving." };
             for chan in args[0].as_slice().split_terminator(',') {
-                match user.channels.write().entry(chan.to_string()) {
-                    Occupied(e) => {
-                        e.take().channel.upgrade().unwrap().read().apply_to_members(|_, m| {
+                // the membership is taken out first, so that the handlers notified
+                // of the part can lock the channels of the user
+                let membership = match user.channels.write().entry(chan.to_string()) {
+                    Occupied(e) => Some(e.take()),
+                    Vacant(_) => None
+                };
+                match membership {
+                    Some(membership) => {
+                        membership.channel.upgrade().unwrap().read().apply_to_members(|_, m| {
                             m.user.upgrade().unwrap().read().push_message(
                                 IRCMessage {
+                                    tags: Vec::new(),
                                     prefix: Some(user.get_fullname()),
                                     command: "PART".to_string(),
                                     args: cmd.args.clone(),
@@ -90,8 +128,9 @@ impl CommandHandler for CmdPart {
                                 }
                             );
                         });
+                        srv.modules_handler.read().notify_part(user, chan, partmsg, srv);
                     },
-                    Vacant(_) => {
+                    None => {
                         user.push_numreply(
                             numericreply::ERR_NOTONCHANNEL(chan),
                             srv.settings.read().name.as_slice()
@@ -99,7 +138,9 @@ impl CommandHandler for CmdPart {
                     }
                 }
                 let empty = srv.channels.read().chan_handle(chan).unwrap().write().cleanup();
-                if empty { srv.channels.write().destroy_if_empty(chan); }
+                if empty && srv.channels.write().destroy_if_empty(chan) {
+                    srv.modules_handler.read().notify_chan_destroy(chan, srv);
+                }
             }
         } else {
             send_needmoreparams(user, "PART", srv);
@@ -109,15 +150,95 @@ impl CommandHandler for CmdPart {
     }
 }
 
-pub struct CmdNames;
+pub struct CmdKick;
+
+impl CommandHandler for CmdKick {
+    fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        if let Some(args) = cmd.as_nparams(2,1) {
+            let chan = args[0].as_slice();
+            let reason = if args.len() > 2 { args[2].clone() } else { user.nickname.clone() };
+            // don't keep the membership, the kicker may be kicking itself
+            let my_rank = match user.membership(chan) {
+                Some(m) => m.rank(),
+                None => {
+                    user.push_numreply(
+                        numericreply::ERR_NOTONCHANNEL(chan),
+                        srv.settings.read().name.as_slice()
+                    );
+                    return (true, Nothing);
+                }
+            };
+            let overriding = user.has_privilege(PrivOverride);
+            if my_rank < HALFOP_RANK && !overriding {
+                user.push_numreply(
+                    numericreply::ERR_CHANOPRIVSNEEDED(chan),
+                    srv.settings.read().name.as_slice()
+                );
+                return (true, Nothing);
+            }
+            for nick in args[1].as_slice().split_terminator(',') {
+                let manager = srv.users.read();
+                let victim = match manager.get_user_by_nickname(nick) {
+                    Some(v) => v,
+                    None => {
+                        user.push_numreply(
+                            numericreply::ERR_NOSUCHNICK(nick),
+                            srv.settings.read().name.as_slice()
+                        );
+                        continue;
+                    }
+                };
+                match victim.membership(chan) {
+                    Some(m) => if !can_kick(my_rank, m.rank()) && !overriding {
+                        user.push_numreply(
+                            numericreply::ERR_CHANOPRIVSNEEDED(chan),
+                            srv.settings.read().name.as_slice()
+                        );
+                        continue;
+                    },
+                    None => {
+                        user.push_numreply(
+                            numericreply::ERR_USERNOTINCHANNEL(nick, chan),
+                            srv.settings.read().name.as_slice()
+                        );
+                        continue;
+                    }
+                }
+                if !srv.modules_handler.read().allow_kick(user, &*victim, chan, srv) {
+                    continue;
+                }
+                srv.channels.read().send_to_chan(
+                    chan,
+                    IRCMessage {
+                        tags: Vec::new(),
+                        prefix: Some(user.get_fullname()),
+                        command: "KICK".to_string(),
+                        args: vec!(chan.to_string(), victim.nickname.clone()),
+                        suffix: Some(reason.clone())
+                    },
+                    None
+                );
+                victim.channels.write().remove(&util::label_to_lower(chan));
+                srv.modules_handler.read().notify_kick(user, &*victim, chan, reason.as_slice(), srv);
+            }
+            let empty = srv.channels.read().chan_handle(chan).unwrap().write().cleanup();
+            if empty && srv.channels.write().destroy_if_empty(chan) {
+                srv.modules_handler.read().notify_chan_destroy(chan, srv);
+            }
+        } else {
+            send_needmoreparams(user, "KICK", srv);
+        }
 
-module!(CmdNames is CommandHandler)
+        (true, Nothing)
+    }
+}
+
+pub struct CmdNames;
 
 impl CommandHandler for CmdNames {
     fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "NAMES" { return (false, Nothing); }
-
         if let Some(args) = cmd.as_nparams(1,1) {
             for chan in args[0].as_slice().split_terminator(',') {
                 if let Some(handle) = srv.channels.read().chan_handle(chan) {
@@ -145,36 +266,33 @@ impl CommandHandler for CmdNames {
 /// Assumes the chan exists.
 #[experimental]
 pub fn send_names(me: &UserData, chan: &str, srv: &ServerData) {
-    let mut buffer = Vec::new();
+    let multi = me.has_capability("multi-prefix");
+    let userhost = me.has_capability("userhost-in-names");
+    let mut entries = Vec::new();
     if let Some(handle) = srv.channels.read().chan_handle(chan) {
         handle.read().apply_to_members(|_, m| {
-            buffer.push((
-                if m.modes.read().get('o'.to_ascii()) {
-                    Some('@')
-                } else if m.modes.read().get('v'.to_ascii()) {
-                    Some('+')
-                } else {
-                    None
-                },
-                m.user.upgrade().unwrap().read().nickname.as_slice().into_string()
-            ));
-            if buffer.len() == 10 {
-                let oldbuff = ::std::mem::replace(&mut buffer, Vec::new());
-                me.push_numreply(
-                    numericreply::RPL_NAMEREPLY('=', chan, oldbuff),
-                    srv.settings.read().name.as_slice()
-                );
+            let other = m.user.upgrade().unwrap();
+            let other = other.read();
+            let mut entry = membership_prefix(&*m.modes.read(), multi);
+            if userhost {
+                entry.push_str(other.get_fullname().as_slice());
+            } else {
+                entry.push_str(other.nickname.as_slice());
             }
+            entries.push(entry);
         });
     }
-    if buffer.len() > 0 {
+    let srv_name = srv.settings.read().name.clone();
+    // room left in the 512 bytes for ":<server> 353 <nick> = <chan> :<entries>\r\n"
+    let width = 512u - (srv_name.len() + me.nickname.len() + chan.len() + 13);
+    for group in group_by_length(entries.as_slice(), width).into_iter() {
         me.push_numreply(
-            numericreply::RPL_NAMEREPLY('=', chan, buffer),
-            srv.settings.read().name.as_slice()
+            numericreply::RPL_NAMEREPLY('=', chan, group),
+            srv_name.as_slice()
         );
     }
     me.push_numreply(
         numericreply::RPL_ENDOFNAMES(chan),
-        srv.settings.read().name.as_slice()
+        srv_name.as_slice()
     );
-}
\ No newline at end of file
+}
@@ -2,34 +2,50 @@
 
 #![experimental]
 
+use metallirc::bans::QLine;
 use metallirc::messages::{IRCMessage, numericreply};
 use metallirc::ServerData;
-use metallirc::users::UserData;
+use metallirc::users::{UserData, handle_cap};
 use metallirc::util;
 
+use time::get_time;
 use uuid::Uuid;
 
 use metallirc::modules::{RecyclingAction, ChangeNick, Nothing, Zombify};
 use metallirc::modules::{CommandHandler, send_needmoreparams};
 
-pub struct CmdNick;
+pub struct CmdCap;
+
+impl CommandHandler for CmdCap {
+    fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        // once registered, the negociation has no effect on the registration
+        let caps = srv.modules_handler.read().supported_capabilities();
+        let (replies, _) = handle_cap(cmd, user.nickname.as_slice(), srv.settings.read().name.as_slice(),
+                                      caps.as_slice(), &mut *user.capabilities.write());
+        for r in replies.into_iter() {
+            user.push_message(r);
+        }
+        (true, Nothing)
+    }
+}
 
-module!(CmdNick is CommandHandler)
+pub struct CmdNick;
 
 impl CommandHandler for CmdNick {
     fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "NICK" { return (false, Nothing); }
-
         if let Some(mut args) = cmd.as_nparams(1,0) {
             let nick = args.pop().unwrap();
-            if util::check_label(nick.as_slice()) {
-                if nick != user.nickname { return (true, ChangeNick(nick)) }
-            } else {
+            if !util::check_nickname(nick.as_slice())
+            || srv.bans.read().find(QLine, nick.as_slice(), get_time().sec).is_some() {
+                // invalid or reserved
                 user.push_numreply(
                     numericreply::ERR_ERRONEUSNICKNAME(nick.as_slice()),
                     srv.settings.read().name.as_slice()
                 );
+            } else if nick != user.nickname {
+                return (true, ChangeNick(nick))
             }
         } else {
             send_needmoreparams(user, "NICK", srv);
@@ -40,15 +56,12 @@ impl CommandHandler for CmdNick {
 
 pub struct CmdQuit;
 
-module!(CmdQuit is CommandHandler)
-
 impl CommandHandler for CmdQuit {
     fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, _: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "QUIT" { return (false, Nothing); }
-
         user.send_to_known(
             IRCMessage {
+                tags: Vec::new(),
                 prefix: Some(user.get_fullname()),
                 command: "QUIT".to_string(),
                 args: cmd.args.clone(),
@@ -61,17 +74,14 @@ impl CommandHandler for CmdQuit {
 
 pub struct CmdPing;
 
-module!(CmdPing is CommandHandler)
-
 impl CommandHandler for CmdPing {
     fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "PING" { return (false, Nothing); }
-
         if let Some(args) = cmd.as_nparams(1,1) {
             // TODO : more precise understanding of expected behavior !!
             user.push_message(
                 IRCMessage {
+                    tags: Vec::new(),
                     prefix: None,
                     command: "PONG".to_string(),
                     args: vec!(srv.settings.read().name.clone(), args[0].clone()),
@@ -83,4 +93,14 @@ impl CommandHandler for CmdPing {
         }
         (true, Nothing)
     }
+}
+
+pub struct CmdPong;
+
+impl CommandHandler for CmdPong {
+    fn handle_command(&self, _: &UserData, _: &Uuid, _: &IRCMessage, _: &ServerData)
+        -> (bool, RecyclingAction) {
+        // activity of the user is already recorded, nothing more to do
+        (true, Nothing)
+    }
 }
\ No newline at end of file
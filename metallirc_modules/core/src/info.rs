@@ -0,0 +1,97 @@
+//! Informational commands about the server.
+
+#![experimental]
+
+use metallirc::messages::{IRCMessage, numericreply};
+use metallirc::ServerData;
+use metallirc::users::UserData;
+use metallirc::VERSION;
+
+use std::sync::atomic::SeqCst;
+
+use time::at;
+use uuid::Uuid;
+
+use metallirc::modules::{RecyclingAction, Nothing};
+use metallirc::modules::CommandHandler;
+
+pub struct CmdLusers;
+
+impl CommandHandler for CmdLusers {
+    fn handle_command(&self, user: &UserData, _: &Uuid, _: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        let (mut visible, mut invisible, mut opers) = (0u32, 0u32, 0u32);
+        srv.users.read().apply_to_all(|u| {
+            let modes = u.modes.read();
+            if modes.get('i'.to_ascii()) { invisible += 1; } else { visible += 1; }
+            if modes.get('o'.to_ascii()) { opers += 1; }
+        });
+        let channels = srv.channels.read().len() as u32;
+        let unknown = srv.unregistered.load(SeqCst) as u32;
+        let name = srv.settings.read().name.clone();
+        user.push_numreply(numericreply::RPL_LUSERCLIENT(visible, invisible, 1), name.as_slice());
+        user.push_numreply(numericreply::RPL_LUSEROP(opers), name.as_slice());
+        user.push_numreply(numericreply::RPL_LUSERUNKNOWN(unknown), name.as_slice());
+        user.push_numreply(numericreply::RPL_LUSERCHANNELS(channels), name.as_slice());
+        // no other server is linked to this one
+        user.push_numreply(numericreply::RPL_LUSERME(visible + invisible, 0), name.as_slice());
+        (true, Nothing)
+    }
+}
+
+pub struct CmdVersion;
+
+impl CommandHandler for CmdVersion {
+    fn handle_command(&self, user: &UserData, _: &Uuid, _: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        let name = srv.settings.read().name.clone();
+        user.push_numreply(
+            numericreply::RPL_VERSION(VERSION, "0", name.as_slice(), "metallirc IRC server"),
+            name.as_slice()
+        );
+        (true, Nothing)
+    }
+}
+
+pub struct CmdAdmin;
+
+impl CommandHandler for CmdAdmin {
+    fn handle_command(&self, user: &UserData, _: &Uuid, _: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        let settings = srv.settings.read();
+        let name = settings.name.as_slice();
+        match settings.admin {
+            Some(ref admin) => {
+                user.push_numreply(numericreply::RPL_ADMINME(name), name);
+                user.push_numreply(numericreply::RPL_ADMINLOC1(admin.location1.as_slice()), name);
+                user.push_numreply(numericreply::RPL_ADMINLOC2(admin.location2.as_slice()), name);
+                user.push_numreply(numericreply::RPL_ADMINEMAIL(admin.email.as_slice()), name);
+            },
+            None => user.push_numreply(numericreply::ERR_NOADMININFO(name), name)
+        }
+        (true, Nothing)
+    }
+}
+
+pub struct CmdInfo;
+
+impl CommandHandler for CmdInfo {
+    fn handle_command(&self, user: &UserData, _: &Uuid, _: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        let name = srv.settings.read().name.clone();
+        let mut lines = vec!(
+            format!("{}, a modular IRC server written in Rust.", VERSION),
+            "By Victor Berger <victor.berger@m4x.org> and contributors.".to_string(),
+            format!("Running since {}.", at(srv.start_time).rfc822z()),
+            "Loaded modules:".to_string()
+        );
+        for module in srv.modules_handler.read().list_modules().into_iter() {
+            lines.push(format!("  {} {}", module.name, module.version));
+        }
+        for line in lines.iter() {
+            user.push_numreply(numericreply::RPL_INFO(line.as_slice()), name.as_slice());
+        }
+        user.push_numreply(numericreply::RPL_ENDOFINFO, name.as_slice());
+        (true, Nothing)
+    }
+}
@@ -4,42 +4,99 @@
 
 #[phase(plugin)] extern crate metallirc;
 extern crate metallirc;
+extern crate crypto;
 extern crate time;
 extern crate toml;
 extern crate uuid;
 
-use metallirc::modules::Module;
+use std::sync::Arc;
+
+use metallirc::modules::{ModuleRegistrar, PRIORITY_FIRST, PRIORITY_NORMAL, PRIORITY_LAST};
 use metallirc::logging::Logger;
 
+mod bans;
 mod channels;
 mod commands;
+mod info;
 mod list;
 mod misc;
 mod modes;
+mod modload;
+mod monitor;
+mod motd;
 mod oper;
+mod stats;
 mod textmessages;
 mod topic;
 mod who;
 
-#[no_mangle]
-pub fn init(conf: &toml::TomlTable, logger: &Logger) -> Vec<Box<Module + 'static + Send + Sync>> {
-    init_modules!(
-        commands::CmdPing,
-        textmessages::CmdPrivmsgOrNotice,
-        channels::CmdJoin,
-        channels::CmdPart,
-        channels::CmdNames,
-        modes::CmdMode,
-        topic::CmdTopic,
-        list::CmdList,
-        who::CmdWho,
-        who::CmdWhois,
-        oper::CmdOper::init(conf, logger),
-        commands::CmdNick,
-        commands::CmdQuit,
-        misc::CmdTime,
-        textmessages::QueryDispatcher,
-        textmessages::ChannelDispatcher,
-        oper::CmdDie
-    )
-}
\ No newline at end of file
+declare_module!("core", "0.0.1", [], init)
+
+fn init(reg: &mut ModuleRegistrar, conf: &toml::TomlTable, logger: &Logger) {
+    // account-notify is left to the module providing the logins, there is none yet
+    reg.add_capability("extended-join");
+    reg.add_capability("chghost");
+    reg.add_capability("multi-prefix");
+    reg.add_capability("userhost-in-names");
+    reg.add_capability("echo-message");
+    reg.add_capability("message-tags");
+    reg.add_command_handler("CmdCap", &["CAP"], PRIORITY_NORMAL, commands::CmdCap);
+    reg.add_command_handler("CmdPing", &["PING"], PRIORITY_NORMAL, commands::CmdPing);
+    reg.add_command_handler("CmdPong", &["PONG"], PRIORITY_NORMAL, commands::CmdPong);
+    let privmsg = textmessages::CmdPrivmsgOrNotice::init(conf, logger);
+    if let Some(denied) = privmsg.clienttagdeny() {
+        reg.add_isupport_token("CLIENTTAGDENY", Some(denied.as_slice()));
+    }
+    reg.add_command_handler("CmdPrivmsgOrNotice", &["PRIVMSG", "NOTICE", "TAGMSG"], PRIORITY_NORMAL, privmsg);
+    reg.add_command_handler("CmdJoin", &["JOIN"], PRIORITY_NORMAL, channels::CmdJoin);
+    reg.add_command_handler("CmdPart", &["PART"], PRIORITY_NORMAL, channels::CmdPart);
+    reg.add_command_handler("CmdKick", &["KICK"], PRIORITY_NORMAL, channels::CmdKick);
+    reg.add_command_handler("CmdNames", &["NAMES"], PRIORITY_NORMAL, channels::CmdNames);
+    let mode = Arc::new(modes::CmdMode::init(conf, logger));
+    reg.add_command_handler("CmdMode", &["MODE"], PRIORITY_NORMAL, mode.clone());
+    reg.add_usermode_handler("CmdMode", PRIORITY_NORMAL, mode.clone());
+    reg.add_chanmode_handler("CmdMode", PRIORITY_NORMAL, mode.clone());
+    reg.add_event_handler("CmdMode", PRIORITY_NORMAL, mode);
+    reg.add_serializable_extension::<modes::ChanBans>("ChanBans");
+    reg.add_command_handler("CmdTopic", &["TOPIC"], PRIORITY_NORMAL, topic::CmdTopic);
+    reg.add_command_handler("CmdList", &["LIST"], PRIORITY_NORMAL, list::CmdList);
+    reg.add_command_handler("CmdWho", &["WHO"], PRIORITY_NORMAL, who::CmdWho);
+    reg.add_command_handler("CmdWhois", &["WHOIS"], PRIORITY_NORMAL, who::CmdWhois);
+    reg.add_command_handler("CmdWhowas", &["WHOWAS"], PRIORITY_NORMAL, who::CmdWhowas);
+    reg.add_command_handler("CmdIson", &["ISON"], PRIORITY_NORMAL, who::CmdIson);
+    reg.add_command_handler("CmdUserhost", &["USERHOST"], PRIORITY_NORMAL, who::CmdUserhost);
+    let monitor_limit = monitor::monitor_limit(conf);
+    let monitor = Arc::new(monitor::CmdMonitor::new(monitor_limit));
+    reg.add_serializable_extension::<monitor::MonitorList>("MonitorList");
+    reg.add_isupport_token("MONITOR", Some(monitor_limit.to_string().as_slice()));
+    reg.add_command_handler("CmdMonitor", &["MONITOR"], PRIORITY_NORMAL, monitor.clone());
+    reg.add_event_handler("CmdMonitor", PRIORITY_NORMAL, monitor.clone());
+    reg.add_unload_handler("CmdMonitor", monitor);
+    let oper = Arc::new(oper::CmdOper::init(conf, logger));
+    reg.add_command_handler("CmdOper", &["OPER"], PRIORITY_NORMAL, oper.clone());
+    reg.add_config_reload_handler("CmdOper", oper.clone());
+    reg.add_command_handler("CmdStats", &["STATS"], PRIORITY_NORMAL, stats::CmdStats::new(oper));
+    reg.add_command_handler("CmdLusers", &["LUSERS"], PRIORITY_NORMAL, info::CmdLusers);
+    reg.add_command_handler("CmdVersion", &["VERSION"], PRIORITY_NORMAL, info::CmdVersion);
+    reg.add_command_handler("CmdAdmin", &["ADMIN"], PRIORITY_NORMAL, info::CmdAdmin);
+    reg.add_command_handler("CmdInfo", &["INFO"], PRIORITY_NORMAL, info::CmdInfo);
+    reg.add_command_handler("CmdNick", &["NICK"], PRIORITY_NORMAL, commands::CmdNick);
+    reg.add_command_handler("CmdQuit", &["QUIT"], PRIORITY_NORMAL, commands::CmdQuit);
+    reg.add_command_handler("CmdTime", &["TIME"], PRIORITY_NORMAL, misc::CmdTime);
+    let motd = Arc::new(motd::CmdMotd::init(conf, logger));
+    reg.add_command_handler("CmdMotd", &["MOTD"], PRIORITY_NORMAL, motd.clone());
+    reg.add_event_handler("CmdMotd", PRIORITY_NORMAL, motd.clone());
+    reg.add_config_reload_handler("CmdMotd", motd);
+    reg.add_message_handler("ChannelPolicy", PRIORITY_FIRST, textmessages::ChannelPolicy);
+    reg.add_message_handler("QueryDispatcher", PRIORITY_LAST, textmessages::QueryDispatcher);
+    reg.add_message_handler("ChannelDispatcher", PRIORITY_LAST, textmessages::ChannelDispatcher);
+    reg.add_command_handler("CmdDie", &["DIE"], PRIORITY_NORMAL, oper::CmdDie);
+    reg.add_command_handler("CmdKill", &["KILL"], PRIORITY_NORMAL, oper::CmdKill);
+    reg.add_command_handler("CmdChghost", &["CHGHOST"], PRIORITY_NORMAL, oper::CmdChghost);
+    reg.add_command_handler("CmdRehash", &["REHASH"], PRIORITY_NORMAL, oper::CmdRehash);
+    reg.add_command_handler("CmdModules", &["MODLOAD", "MODUNLOAD", "MODRELOAD", "MODLIST"], PRIORITY_NORMAL,
+                            modload::CmdModules);
+    reg.add_command_handler("CmdBans", &["KLINE", "GLINE", "DLINE", "ZLINE", "QLINE",
+                                         "UNKLINE", "UNGLINE", "UNDLINE", "UNZLINE", "UNQLINE"],
+                            PRIORITY_NORMAL, bans::CmdBans);
+}
@@ -10,13 +10,9 @@ use metallirc::modules::CommandHandler;
 
 pub struct CmdList;
 
-module!(CmdList is CommandHandler)
-
 impl CommandHandler for CmdList {
     fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "LIST" { return (false, Nothing); }
-
         if let Some(args) = cmd.as_nparams(0,2) { // always true !
             if args.len() > 0 {
                 for chan in args[0].as_slice().split_terminator(',') {
@@ -11,13 +11,9 @@ use time::now;
 
 pub struct CmdTime;
 
-module!(CmdTime is CommandHandler)
-
 impl CommandHandler for CmdTime {
-    fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+    fn handle_command(&self, user: &UserData, _: &Uuid, _: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "TIME" { return (false, Nothing); }
-
         user.push_numreply(
             numericreply::RPL_TIME(
                 srv.settings.read().name.as_slice(),
@@ -1,33 +1,125 @@
 //! Modes handling.
 
-use metallirc::channels::Membership;
+use metallirc::channels::{Channel, Membership};
+use metallirc::extensions::SerializableExtension;
+use metallirc::logging::{Logger, Warning};
 use metallirc::messages::{IRCMessage, numericreply};
+use metallirc::modes::{MEMBERSHIP_PREFIXES, HALFOP_RANK, OP_RANK, can_change_membership};
 use metallirc::ServerData;
-use metallirc::users::UserData;
+use metallirc::users::{UserData, PrivOverride, PrivSeeInvisible};
+use metallirc::util::matches_mask;
 
+use std::ascii::AsciiExt;
 use std::slice::Items;
 
+use toml;
 use uuid::Uuid;
 
 use metallirc::modules::{RecyclingAction, Nothing};
-use metallirc::modules::{CommandHandler, UserModeHandler, ChannelModeHandler};
+use metallirc::modules::{CommandHandler, EventHandler, UserModeHandler, ChannelModeHandler};
+use metallirc::modules::{ChanModeKind, ListMode, NoParam, PrefixMode};
 use metallirc::modules::send_needmoreparams;
 
-pub struct CmdMode;
+/// The membership modes enabled when the configuration does not say otherwise.
+pub const DEFAULT_PREFIXES: &'static str = "ov";
+/// The maximum number of bans of a chan.
+pub const MAX_BANS: uint = 100;
 
-module!(CmdMode is CommandHandler, UserModeHandler, ChannelModeHandler)
+/// The ban masks of a chan, stored in its extensions.
+pub struct ChanBans {
+    pub masks: Vec<String>
+}
+
+impl SerializableExtension for ChanBans {
+    fn serialize(&self) -> String {
+        // the masks have no spaces
+        self.masks.connect(" ")
+    }
+
+    fn deserialize(data: &str) -> Option<ChanBans> {
+        Some(ChanBans { masks: data.words().map(|m| m.to_string()).collect() })
+    }
+}
+
+/// Completes a ban mask given as a nickname or a host, as `nick!*@*` or `*!user@host`.
+pub fn full_ban_mask(mask: &str) -> String {
+    if mask.contains_char('!') {
+        mask.to_string()
+    } else if mask.contains_char('@') {
+        format!("*!{}", mask)
+    } else {
+        format!("{}!*@*", mask)
+    }
+}
+
+/// Whether a user of given full name is banned from given chan.
+pub fn is_banned(chan: &Channel, fullname: &str) -> bool {
+    chan.extensions.with(|b: &ChanBans| b.masks.iter().any(|m| matches_mask(fullname, m.as_slice())))
+                   .unwrap_or(false)
+}
+
+/// Sends the ban list of a chan to a user.
+fn send_ban_list(user: &UserData, chan: &str, srv: &ServerData) {
+    let masks = srv.channels.read().chan_handle(chan).and_then(
+        |c| c.read().extensions.with(|b: &ChanBans| b.masks.clone())
+    ).unwrap_or(Vec::new());
+    for mask in masks.iter() {
+        user.push_numreply(
+            numericreply::RPL_BANLIST(chan, mask.as_slice()),
+            srv.settings.read().name.as_slice()
+        );
+    }
+    user.push_numreply(
+        numericreply::RPL_ENDOFBANLIST(chan),
+        srv.settings.read().name.as_slice()
+    );
+}
+
+/// Handles MODE, and the user and channel modes of the core.
+pub struct CmdMode {
+    /// The membership modes enabled by the `prefixes` setting, as `(mode, prefix, rank)`,
+    /// the most powerful first.
+    prefixes: Vec<(char, char, uint)>
+}
+
+impl CmdMode {
+    pub fn init(conf: &toml::TomlTable, logger: &Logger) -> CmdMode {
+        let letters = match conf.get(&"prefixes".to_string()) {
+            Some(&toml::String(ref s)) => s.clone(),
+            Some(_) => {
+                logger.log(Warning, format!("(mod_core) Invalid prefixes, using \"{}\".", DEFAULT_PREFIXES));
+                DEFAULT_PREFIXES.to_string()
+            },
+            None => DEFAULT_PREFIXES.to_string()
+        };
+        let mut prefixes = Vec::new();
+        for c in letters.as_slice().chars() {
+            match MEMBERSHIP_PREFIXES.iter().find(|&&(m, _, _)| m == c) {
+                Some(p) => if !prefixes.contains(p) { prefixes.push(*p); },
+                None => logger.log(Warning, format!("(mod_core) Unknown prefix mode {}, ignored.", c))
+            }
+        }
+        // the creator of a chan is made operator
+        if !prefixes.iter().any(|&(m, _, _)| m == 'o') {
+            logger.log(Warning, "(mod_core) The o prefix mode cannot be disabled.".to_string());
+            prefixes.push(('o', '@', OP_RANK));
+        }
+        prefixes.sort_by(|&(_, _, a), &(_, _, b)| b.cmp(&a));
+        CmdMode {
+            prefixes: prefixes
+        }
+    }
+}
 
 impl CommandHandler for CmdMode {
     fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "MODE" { return (false, Nothing); }
-
         if let Some(args) = cmd.as_nparams(1,14) {
             if let Some(other) = srv.users.read().get_user_by_nickname(args[0].as_slice()) {
                 // it is me !
                 if args.len() == 1 {
-                    // only checking modes, oper can read all
-                    if user.id == other.id || user.modes.read().get('o'.to_ascii()) {
+                    // only checking modes, some opers can read all
+                    if user.id == other.id || user.has_privilege(PrivSeeInvisible) {
                         user.push_numreply(
                             numericreply::RPL_UMODEIS(other.modes.read().to_modestring().as_slice()),
                             srv.settings.read().name.as_slice()
@@ -125,6 +217,7 @@ fn update_user_mode(user: &UserData, other: &UserData, args: Vec<String>, srv: &
         if response.len() > 1 {
             user.push_message(
                 IRCMessage {
+                    tags: Vec::new(),
                     prefix: Some(user.get_fullname()),
                     command: "MODE".to_string(),
                     args: vec!(response),
@@ -142,6 +235,11 @@ fn update_chan_mode(user: &UserData, membership: &Membership,
     let mut words = args.iter();
     let handler = srv.modules_handler.read();
     while let Some(ref txt) = words.next() {
+        // asking for the ban list
+        if (txt.as_slice() == "b" || txt.as_slice() == "+b") && words.len() == 0 {
+            send_ban_list(user, args[0].as_slice(), srv);
+            continue;
+        }
         let mut chars = txt.as_slice().chars();
         let set = match chars.next() {
             Some('+') => true,
@@ -176,6 +274,7 @@ fn update_chan_mode(user: &UserData, membership: &Membership,
             }
             messages.push(
                 IRCMessage {
+                    tags: Vec::new(),
                     prefix: Some(user.get_fullname()),
                     command: "MODE".to_string(),
                     args: msg_args,
@@ -216,6 +315,9 @@ impl UserModeHandler for CmdMode {
             if "io".to_ascii().contains(&flag) {
                 // only certain flags are removable
                 target.modes.write().set(flag, false);
+                if flag == 'o'.to_ascii() {
+                    target.privileges.write().clear();
+                }
                 Some(true)
             } else if "".to_ascii().contains(&flag) {
                 // others are forbidden
@@ -226,6 +328,10 @@ impl UserModeHandler for CmdMode {
             }
         }
     }
+
+    fn usermodes(&self) -> Vec<char> {
+        vec!('i', 'o')
+    }
 }
 
 impl ChannelModeHandler for CmdMode {
@@ -238,22 +344,23 @@ impl ChannelModeHandler for CmdMode {
                                srv: &ServerData) -> Option<bool> {
         let _me = asker.user.upgrade().unwrap();
         let me = _me.read();
-        // forbid change if not on oper for modes I handle, except
-        // network operatorswho can do as they please
-        if !asker.modes.read().get('o'.to_ascii())
-        && "snmtvo".to_ascii().contains(&flag)
-        &&  !me.modes.read().get('o'.to_ascii()){
-            return Some(false);
-        }
+        // network operators allowed to override can do as they please
+        let overriding = me.has_privilege(PrivOverride);
+        let my_rank = asker.rank();
 
         let chan = asker.channel.upgrade().unwrap();
 
-        if "vo".to_ascii().contains(&flag) {
+        if let Some(&(_, _, rank)) = self.prefixes.iter().find(|&&(m, _, _)| m.to_ascii() == flag) {
             // it's a membership
             if let Some(nick) = args.next() {
 
                 if let Some(target) = srv.users.read().get_user_by_nickname(nick.as_slice()) {
                     if let Some(membership) = target.channels.read().get(&chan.read().name) {
+                        let allowed = can_change_membership(my_rank, rank, set, membership.rank(),
+                                                            target.id == me.id);
+                        if !allowed && !overriding {
+                            return Some(false);
+                        }
                         membership.modes.write().set(flag, set);
                     } else {
                         me.push_numreply(
@@ -272,11 +379,94 @@ impl ChannelModeHandler for CmdMode {
                 }
             }
             Some(true)
+        } else if flag == 'b'.to_ascii() {
+            // the list itself is sent by update_chan_mode
+            if let Some(mask) = args.next() {
+                if my_rank < HALFOP_RANK && !overriding {
+                    return Some(false);
+                }
+                let mask = full_ban_mask(mask.as_slice());
+                let chan = chan.read();
+                let full = chan.extensions.with_mut(|b: &mut ChanBans| {
+                    if set {
+                        if b.masks.len() >= MAX_BANS { return true; }
+                        if !b.masks.iter().any(|m| m.as_slice().eq_ignore_ascii_case(mask.as_slice())) {
+                            b.masks.push(mask.clone());
+                        }
+                    } else {
+                        b.masks.retain(|m| !m.as_slice().eq_ignore_ascii_case(mask.as_slice()));
+                    }
+                    false
+                });
+                match full {
+                    Some(true) => {
+                        me.push_numreply(
+                            numericreply::ERR_BANLISTFULL(chan.name.as_slice(), 'b'),
+                            srv.settings.read().name.as_slice()
+                        );
+                        return Some(false);
+                    },
+                    Some(false) => {},
+                    None => if set { chan.extensions.insert(ChanBans { masks: vec!(mask) }); }
+                }
+            }
+            Some(true)
         } else if "snmt".to_ascii().contains(&flag) {
+            if my_rank < OP_RANK && !overriding {
+                return Some(false);
+            }
             chan.write().modes.set(flag, set);
             Some(true)
         } else {
             None
         }
     }
+
+    fn chanmodes(&self) -> Vec<(char, ChanModeKind)> {
+        let mut modes: Vec<(char, ChanModeKind)> = self.prefixes.iter()
+            .map(|&(m, prefix, rank)| (m, PrefixMode(prefix, rank)))
+            .collect();
+        modes.push_all(&[('b', ListMode), ('s', NoParam), ('n', NoParam), ('m', NoParam), ('t', NoParam)]);
+        modes
+    }
+}
+
+impl EventHandler for CmdMode {
+    fn before_join(&self, user: &UserData, chan: &str, srv: &ServerData) -> bool {
+        let banned = srv.channels.read().chan_handle(chan)
+                                 .map_or(false, |c| is_banned(&*c.read(), user.get_fullname().as_slice()));
+        if banned && !user.has_privilege(PrivOverride) {
+            user.push_numreply(
+                numericreply::ERR_BANNEDFROMCHAN(chan),
+                srv.settings.read().name.as_slice()
+            );
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use metallirc::channels::Channel;
+
+    use super::{ChanBans, full_ban_mask, is_banned};
+
+    #[test]
+    fn test_full_ban_mask() {
+        assert_eq!(full_ban_mask("foo").as_slice(), "foo!*@*");
+        assert_eq!(full_ban_mask("*@host").as_slice(), "*!*@host");
+        assert_eq!(full_ban_mask("foo!bar@baz").as_slice(), "foo!bar@baz");
+    }
+
+    #[test]
+    fn test_is_banned() {
+        let chan = Channel::new("#chan".to_string());
+        assert!(!is_banned(&chan, "foo!bar@baz"));
+        chan.extensions.insert(ChanBans { masks: vec!("foo!*@*".to_string(), "*!*@*.example.com".to_string()) });
+        assert!(is_banned(&chan, "Foo!bar@baz"));
+        assert!(is_banned(&chan, "other!u@host.example.com"));
+        assert!(!is_banned(&chan, "other!u@example.org"));
+    }
 }
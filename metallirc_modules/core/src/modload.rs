@@ -0,0 +1,62 @@
+//! Module management commands.
+
+#![experimental]
+
+use metallirc::messages::{IRCMessage, numericreply};
+use metallirc::ServerData;
+use metallirc::users::{UserData, PrivModules};
+
+use uuid::Uuid;
+
+use metallirc::modules::{RecyclingAction, Nothing, LoadModule, UnloadModule, ReloadModule};
+use metallirc::modules::{CommandHandler, send_needmoreparams};
+
+use bans::send_notice;
+
+/// Handles MODLOAD, MODUNLOAD, MODRELOAD and MODLIST.
+///
+/// The actual loading is done by the recycler, as it requires `&mut` access to the
+/// modules handler.
+pub struct CmdModules;
+
+impl CommandHandler for CmdModules {
+    fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        if !user.has_privilege(PrivModules) {
+            user.push_numreply(
+                numericreply::ERR_NOPRIVILIGES,
+                srv.settings.read().name.as_slice()
+            );
+            return (true, Nothing);
+        }
+
+        if cmd.command.as_slice() == "MODLIST" {
+            for module in srv.modules_handler.read().list_modules().into_iter() {
+                let handlers: Vec<String> = module.handlers.iter()
+                                                  .map(|&(ref unit, kind)| format!("{}({})", unit, kind))
+                                                  .collect();
+                send_notice(user, format!("*** Module {} {}: {}",
+                    module.name, module.version, handlers.connect(", ")), srv);
+            }
+            send_notice(user, "*** End of MODLIST".to_string(), srv);
+            return (true, Nothing);
+        }
+
+        if let Some(mut args) = cmd.as_nparams(1,0) {
+            let name = args.pop().unwrap();
+            match cmd.command.as_slice() {
+                "MODLOAD" => (true, LoadModule(name)),
+                // MODRELOAD is the only way to update the core module, as it provides these commands
+                "MODUNLOAD" if name.as_slice() == "core" => {
+                    send_notice(user, "*** The core module cannot be unloaded, use MODRELOAD.".to_string(), srv);
+                    (true, Nothing)
+                },
+                "MODUNLOAD" => (true, UnloadModule(name)),
+                _ => (true, ReloadModule(name))
+            }
+        } else {
+            send_needmoreparams(user, cmd.command.as_slice(), srv);
+            (true, Nothing)
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! MONITOR command: notifications of the presence of nicknames.
+
+#![experimental]
+
+use metallirc::extensions::SerializableExtension;
+use metallirc::messages::{IRCMessage, numericreply};
+use metallirc::ServerData;
+use metallirc::users::UserData;
+use metallirc::util::{check_nickname, group_by_length, label_to_lower};
+
+use std::collections::{HashMap, HashSet};
+use std::sync::RWLock;
+
+use toml;
+use uuid::Uuid;
+
+use metallirc::modules::{RecyclingAction, Nothing};
+use metallirc::modules::{CommandHandler, EventHandler, UnloadHandler, send_needmoreparams};
+
+/// Default maximum number of nicknames a user can monitor.
+pub const DEFAULT_MONITOR_LIMIT: uint = 100;
+/// Maximum length of the list of targets in one reply.
+const MAX_TARGETS_LENGTH: uint = 400;
+
+/// The nicknames monitored by a user, as given.
+pub struct MonitorList {
+    targets: Vec<String>
+}
+
+impl SerializableExtension for MonitorList {
+    fn serialize(&self) -> String {
+        self.targets.connect(",")
+    }
+
+    fn deserialize(data: &str) -> Option<MonitorList> {
+        Some(MonitorList {
+            targets: data.split_terminator(',').map(|t| t.to_string()).collect()
+        })
+    }
+}
+
+/// Reads the monitor limit in the module config.
+pub fn monitor_limit(conf: &toml::TomlTable) -> uint {
+    match conf.get(&"monitor_limit".to_string()) {
+        Some(&toml::Integer(i)) if i >= 0 => i as uint,
+        _ => DEFAULT_MONITOR_LIMIT
+    }
+}
+
+/// Handles MONITOR, and notifies the watchers when a monitored nickname comes or leaves.
+pub struct CmdMonitor {
+    limit: uint,
+    /// The users monitoring each lowercase nickname.
+    watchers: RWLock<HashMap<String, HashSet<Uuid>>>
+}
+
+impl CmdMonitor {
+    pub fn new(limit: uint) -> CmdMonitor {
+        CmdMonitor {
+            limit: limit,
+            watchers: RWLock::new(HashMap::new())
+        }
+    }
+
+    fn watch(&self, id: &Uuid, target: &str) {
+        let lower = label_to_lower(target);
+        let mut watchers = self.watchers.write();
+        if let Some(set) = watchers.get_mut(&lower) {
+            set.insert(id.clone());
+            return;
+        }
+        let mut set = HashSet::new();
+        set.insert(id.clone());
+        watchers.insert(lower, set);
+    }
+
+    fn unwatch(&self, id: &Uuid, target: &str) {
+        let lower = label_to_lower(target);
+        let mut watchers = self.watchers.write();
+        let empty = match watchers.get_mut(&lower) {
+            Some(set) => { set.remove(id); set.is_empty() },
+            None => false
+        };
+        if empty { watchers.remove(&lower); }
+    }
+
+    /// Sends RPL_MONONLINE or RPL_MONOFFLINE about given nickname to the users monitoring it.
+    fn notify(&self, nick: &str, online: Option<String>, srv: &ServerData) {
+        let watchers = match self.watchers.read().get(&label_to_lower(nick)) {
+            Some(set) => set.clone(),
+            None => return
+        };
+        let srv_name = srv.settings.read().name.clone();
+        let manager = srv.users.read();
+        for id in watchers.iter() {
+            if let Some(watcher) = manager.get_user_by_uuid(id) {
+                watcher.push_numreply(
+                    match online {
+                        Some(ref fullname) => numericreply::RPL_MONONLINE(vec!(fullname.as_slice())),
+                        None => numericreply::RPL_MONOFFLINE(vec!(nick))
+                    },
+                    srv_name.as_slice()
+                );
+            }
+        }
+    }
+
+    /// Adds targets to the list of the user, then sends their status.
+    fn add_targets(&self, user: &UserData, id: &Uuid, targets: Vec<&str>, srv: &ServerData) {
+        let mut added = Vec::new();
+        let mut full = None;
+        let count = user.extensions.with(|l: &MonitorList| l.targets.len()).unwrap_or(0);
+        if !user.extensions.contains::<MonitorList>() {
+            user.extensions.insert(MonitorList { targets: Vec::new() });
+        }
+        user.extensions.with_mut(|list: &mut MonitorList| {
+            let mut count = count;
+            for (i, t) in targets.iter().enumerate() {
+                if !check_nickname(*t) { continue; }
+                let lower = label_to_lower(*t);
+                if list.targets.iter().any(|o| label_to_lower(o.as_slice()) == lower) { continue; }
+                if count >= self.limit {
+                    full = Some(targets.slice_from(i).connect(","));
+                    break;
+                }
+                list.targets.push(t.to_string());
+                added.push(t.to_string());
+                count += 1;
+            }
+        });
+        for t in added.iter() {
+            self.watch(id, t.as_slice());
+        }
+        if let Some(rest) = full {
+            user.push_numreply(
+                numericreply::ERR_MONLISTFULL(self.limit as u32, rest.as_slice()),
+                srv.settings.read().name.as_slice()
+            );
+        }
+        send_status(user, added, srv);
+    }
+
+    /// Removes targets from the list of the user.
+    fn remove_targets(&self, user: &UserData, id: &Uuid, targets: Vec<&str>) {
+        let lowers: Vec<String> = targets.iter().map(|t| label_to_lower(*t)).collect();
+        let mut removed = Vec::new();
+        user.extensions.with_mut(|list: &mut MonitorList| {
+            list.targets.retain(|t| {
+                if lowers.contains(&label_to_lower(t.as_slice())) {
+                    removed.push(t.clone());
+                    false
+                } else {
+                    true
+                }
+            });
+        });
+        for t in removed.iter() {
+            self.unwatch(id, t.as_slice());
+        }
+    }
+
+    /// Clears the list of the user.
+    fn clear_targets(&self, user: &UserData, id: &Uuid) {
+        let targets = user.extensions.with(|l: &MonitorList| l.targets.clone()).unwrap_or(Vec::new());
+        for t in targets.iter() {
+            self.unwatch(id, t.as_slice());
+        }
+        user.extensions.remove::<MonitorList>();
+    }
+}
+
+/// Sends the online and offline status of the targets, in lines of reasonable length.
+fn send_status(user: &UserData, targets: Vec<String>, srv: &ServerData) {
+    let mut online = Vec::new();
+    let mut offline = Vec::new();
+    {
+        let manager = srv.users.read();
+        for t in targets.into_iter() {
+            match manager.get_user_by_nickname(t.as_slice()) {
+                Some(u) => online.push(u.get_fullname()),
+                None => offline.push(t)
+            }
+        }
+    }
+    let srv_name = srv.settings.read().name.clone();
+    for chunk in group_by_length(online.as_slice(), MAX_TARGETS_LENGTH).into_iter() {
+        user.push_numreply(numericreply::RPL_MONONLINE(chunk), srv_name.as_slice());
+    }
+    for chunk in group_by_length(offline.as_slice(), MAX_TARGETS_LENGTH).into_iter() {
+        user.push_numreply(numericreply::RPL_MONOFFLINE(chunk), srv_name.as_slice());
+    }
+}
+
+impl CommandHandler for CmdMonitor {
+    fn handle_command(&self, user: &UserData, user_uuid: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        if let Some(args) = cmd.as_nparams(1,1) {
+            let targets: Vec<&str> = match args.get(1) {
+                Some(list) => list.as_slice().split_terminator(',').filter(|t| !t.is_empty()).collect(),
+                None => Vec::new()
+            };
+            match args[0].as_slice() {
+                "+" if targets.len() > 0 => self.add_targets(user, user_uuid, targets, srv),
+                "-" if targets.len() > 0 => self.remove_targets(user, user_uuid, targets),
+                "+" | "-" => send_needmoreparams(user, "MONITOR", srv),
+                "C" | "c" => self.clear_targets(user, user_uuid),
+                "L" | "l" => {
+                    let list = user.extensions.with(|l: &MonitorList| l.targets.clone())
+                                              .unwrap_or(Vec::new());
+                    let srv_name = srv.settings.read().name.clone();
+                    for chunk in group_by_length(list.as_slice(), MAX_TARGETS_LENGTH).into_iter() {
+                        user.push_numreply(numericreply::RPL_MONLIST(chunk), srv_name.as_slice());
+                    }
+                    user.push_numreply(numericreply::RPL_ENDOFMONLIST, srv_name.as_slice());
+                },
+                "S" | "s" => {
+                    let list = user.extensions.with(|l: &MonitorList| l.targets.clone())
+                                              .unwrap_or(Vec::new());
+                    send_status(user, list, srv);
+                },
+                _ => {}
+            }
+        } else {
+            send_needmoreparams(user, "MONITOR", srv);
+        }
+        (true, Nothing)
+    }
+}
+
+impl EventHandler for CmdMonitor {
+    fn on_register(&self, user: &UserData, _: &Uuid, srv: &ServerData) {
+        self.notify(user.nickname.as_slice(), Some(user.get_fullname()), srv);
+    }
+
+    fn on_nick_change(&self, user: &UserData, old_nick: &str, srv: &ServerData) {
+        // a change of case only is not a departure
+        if label_to_lower(old_nick) != label_to_lower(user.nickname.as_slice()) {
+            self.notify(old_nick, None, srv);
+            self.notify(user.nickname.as_slice(), Some(user.get_fullname()), srv);
+        }
+    }
+
+    fn on_destroy(&self, user: &UserData, user_uuid: &Uuid, srv: &ServerData) {
+        self.clear_targets(user, user_uuid);
+        self.notify(user.nickname.as_slice(), None, srv);
+    }
+}
+
+impl UnloadHandler for CmdMonitor {
+    fn pre_unload(&self, reloading: bool, _: &ServerData) -> Option<String> {
+        // the lists are kept in the extensions, the index is rebuilt from them
+        if reloading { Some(String::new()) } else { None }
+    }
+
+    fn restore_state(&self, _: String, srv: &ServerData) {
+        srv.users.read().apply_to_all(|u| {
+            let targets = u.extensions.with(|l: &MonitorList| l.targets.clone()).unwrap_or(Vec::new());
+            for t in targets.iter() {
+                self.watch(&u.id, t.as_slice());
+            }
+        });
+    }
+}
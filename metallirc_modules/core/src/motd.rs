@@ -0,0 +1,178 @@
+//! Message of the day.
+
+#![experimental]
+
+use metallirc::logging::{Logger, Info, Warning};
+use metallirc::messages::{IRCMessage, numericreply};
+use metallirc::ServerData;
+use metallirc::users::UserData;
+use metallirc::util::wrap_text;
+
+use std::collections::HashMap;
+use std::io::File;
+use std::sync::RWLock;
+
+use time::get_time;
+use toml;
+use uuid::Uuid;
+
+use metallirc::modules::{RecyclingAction, Nothing};
+use metallirc::modules::{CommandHandler, ConfigReloadHandler, EventHandler};
+
+/// The messages of the day, as lines of text.
+struct MotdFiles {
+    /// The MOTD of the connection classes without one of their own.
+    default: Option<Vec<String>>,
+    /// The MOTD of some connection classes, by class name.
+    classes: HashMap<String, Vec<String>>
+}
+
+/// Reads a MOTD file.
+fn read_motd(path_str: &str) -> Result<Vec<String>, String> {
+    let path = match from_str::<Path>(path_str) {
+        Some(p) => p,
+        None => return Err(format!("Invalid path for MOTD file: {}.", path_str))
+    };
+    match File::open(&path).read_to_string() {
+        Ok(text) => Ok(text.as_slice().lines_any().map(|l| l.to_string()).collect()),
+        Err(e) => Err(format!("Unable to read MOTD file {} : {}", path_str, e))
+    }
+}
+
+/// Reads the MOTD files given in the `[module.core.motd]` section of the module config,
+/// returns them with the list of errors encountered.
+fn parse_motd(conf: &toml::TomlTable) -> (MotdFiles, Vec<String>) {
+    let mut files = MotdFiles { default: None, classes: HashMap::new() };
+    let mut errors = Vec::new();
+    let table = match conf.get(&"motd".to_string()) {
+        Some(&toml::Table(ref t)) => t,
+        Some(_) => {
+            errors.push("The MOTD should be defined in a [module.core.motd] section.".to_string());
+            return (files, errors);
+        },
+        None => return (files, errors)
+    };
+    match table.get(&"file".to_string()) {
+        Some(&toml::String(ref path)) => match read_motd(path.as_slice()) {
+            Ok(lines) => files.default = Some(lines),
+            Err(e) => errors.push(e)
+        },
+        Some(_) => errors.push("Invalid MOTD file path.".to_string()),
+        None => {}
+    }
+    match table.get(&"classes".to_string()) {
+        Some(&toml::Table(ref classes)) => for (class, v) in classes.iter() {
+            match v {
+                &toml::String(ref path) => match read_motd(path.as_slice()) {
+                    Ok(lines) => { files.classes.insert(class.clone(), lines); },
+                    Err(e) => errors.push(e)
+                },
+                _ => errors.push(format!("Invalid MOTD file path for class {}.", class))
+            }
+        },
+        Some(_) => errors.push(
+            "The MOTD of the classes should be defined in a [module.core.motd.classes] section.".to_string()
+        ),
+        None => {}
+    }
+    (files, errors)
+}
+
+/// Formats a duration in seconds as `<days> days, hh:mm:ss`.
+pub fn format_uptime(secs: i64) -> String {
+    format!("{} days, {:02}:{:02}:{:02}", secs / 86400, (secs % 86400) / 3600, (secs % 3600) / 60, secs % 60)
+}
+
+/// Replaces the variables of a MOTD line by their values.
+fn expand(line: &str, vars: &[(&str, String)]) -> String {
+    let mut result = line.to_string();
+    for &(name, ref value) in vars.iter() {
+        result = result.replace(format!("${{{}}}", name).as_slice(), value.as_slice());
+    }
+    result
+}
+
+/// Handles the MOTD command, and sends the MOTD on connection.
+///
+/// The MOTD can contain the variables `${server}`, `${nick}`, `${users}` and `${uptime}`.
+pub struct CmdMotd {
+    motd: RWLock<MotdFiles>
+}
+
+impl CmdMotd {
+    pub fn init(conf: &toml::TomlTable, logger: &Logger) -> CmdMotd {
+        let (files, errors) = parse_motd(conf);
+        for e in errors.into_iter() {
+            logger.log(Warning, format!("(mod_core) {}", e));
+        }
+        logger.log(Info, format!("(mod_core) {} MOTD files were loaded.",
+            files.classes.len() + if files.default.is_some() { 1 } else { 0 }));
+        CmdMotd {
+            motd: RWLock::new(files)
+        }
+    }
+
+    /// Sends the MOTD of the class of the user.
+    fn send_motd(&self, user: &UserData, srv: &ServerData) {
+        let settings = srv.settings.read();
+        let name = settings.name.as_slice();
+        let motd = self.motd.read();
+        let lines = match motd.classes.get(&user.class) {
+            Some(lines) => lines,
+            None => match motd.default {
+                Some(ref lines) => lines,
+                None => {
+                    user.push_numreply(numericreply::ERR_NOMOTD, name);
+                    return;
+                }
+            }
+        };
+        let vars = [
+            ("server", settings.name.clone()),
+            ("nick", user.nickname.clone()),
+            ("users", srv.users.read().len().to_string()),
+            ("uptime", format_uptime(get_time().sec - srv.start_time.sec))
+        ];
+        // room left in the 512 bytes for ":<server> 372 <nick> :- <text>\r\n"
+        let width = 512u - (name.len() + user.nickname.len() + 14);
+        user.push_numreply(numericreply::RPL_MOTDSTART(name), name);
+        for line in lines.iter() {
+            for part in wrap_text(expand(line.as_slice(), vars.as_slice()).as_slice(), width).iter() {
+                user.push_numreply(numericreply::RPL_MOTD(part.as_slice()), name);
+            }
+        }
+        user.push_numreply(numericreply::RPL_ENDOFMOTD, name);
+    }
+}
+
+impl ConfigReloadHandler for CmdMotd {
+    fn check_config(&self, conf: &toml::TomlTable) -> Result<(), String> {
+        let (_, errors) = parse_motd(conf);
+        if errors.len() > 0 { Err(errors.connect(" ")) } else { Ok(()) }
+    }
+
+    fn reload_config(&self, conf: &toml::TomlTable, logger: &Logger) -> Result<(), String> {
+        let (files, errors) = parse_motd(conf);
+        if errors.len() > 0 {
+            // keep the previous MOTD rather than losing it
+            return Err(errors.connect(" "));
+        }
+        logger.log(Info, "(mod_core) MOTD files were reloaded.".to_string());
+        *self.motd.write() = files;
+        Ok(())
+    }
+}
+
+impl CommandHandler for CmdMotd {
+    fn handle_command(&self, user: &UserData, _: &Uuid, _: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        self.send_motd(user, srv);
+        (true, Nothing)
+    }
+}
+
+impl EventHandler for CmdMotd {
+    fn on_register(&self, user: &UserData, _: &Uuid, srv: &ServerData) {
+        self.send_motd(user, srv);
+    }
+}
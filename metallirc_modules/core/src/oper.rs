@@ -2,104 +2,363 @@
 
 #![experimental]
 
-use metallirc::logging::{Logger, Warning, Info};
+use metallirc::logging::{Logger, Error, Warning, Info};
 use metallirc::messages::{IRCMessage, numericreply};
 use metallirc::ServerData;
-use metallirc::users::UserData;
+use metallirc::users::{UserData, Privilege, PrivDie, PrivKill, PrivOverride, PrivRehash};
+use metallirc::util::matches_mask;
 
-use std::collections::TreeMap;
+use std::collections::{HashSet, TreeMap};
+use std::sync::RWLock;
 
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
 use toml;
 use uuid::Uuid;
 
-use metallirc::modules::{RecyclingAction, Nothing};
-use metallirc::modules::{CommandHandler, send_needmoreparams};
+use metallirc::modules::{RecyclingAction, Nothing, Kill, ChangeHost};
+use metallirc::modules::{CommandHandler, ConfigReloadHandler, send_needmoreparams};
+
+use bans::send_notice;
+
+/// An operator block of the config file.
+struct OperBlock {
+    /// Password hash, in the form `sha256:<salt>:<hex digest of salt + password>`.
+    password: String,
+    /// Masks of the `user@host` the operator can log in from.
+    hosts: Vec<String>,
+    /// Fingerprint of the TLS client certificate required to log in.
+    fingerprint: Option<String>,
+    privileges: HashSet<Privilege>
+}
+
+impl OperBlock {
+    /// Parses an operator block, returns an error message if invalid.
+    fn parse(login: &str, table: &toml::TomlTable) -> Result<OperBlock, String> {
+        let password = match table.get(&"password".to_string()) {
+            Some(&toml::String(ref s)) if s.as_slice().split(':').count() == 3
+                                       && s.as_slice().starts_with("sha256:") => s.clone(),
+            _ => return Err(format!("Missing or invalid password hash for operator {}.", login))
+        };
+        let mut hosts = Vec::new();
+        match table.get(&"hosts".to_string()) {
+            Some(&toml::Array(ref list)) => for h in list.iter() {
+                match h {
+                    &toml::String(ref mask) => hosts.push(mask.clone()),
+                    _ => return Err(format!("Invalid host mask for operator {}.", login))
+                }
+            },
+            _ => return Err(format!("Missing host masks for operator {}.", login))
+        }
+        let fingerprint = match table.get(&"fingerprint".to_string()) {
+            Some(&toml::String(ref s)) => Some(s.clone()),
+            _ => None
+        };
+        let mut privileges = HashSet::new();
+        if let Some(&toml::Array(ref list)) = table.get(&"privileges".to_string()) {
+            for p in list.iter() {
+                match p {
+                    &toml::String(ref name) => match Privilege::from_name(name.as_slice()) {
+                        Some(privilege) => { privileges.insert(privilege); },
+                        None => return Err(format!("Unknown privilege {} for operator {}.", name, login))
+                    },
+                    _ => return Err(format!("Invalid privilege for operator {}.", login))
+                }
+            }
+        }
+        Ok(OperBlock {
+            password: password,
+            hosts: hosts,
+            fingerprint: fingerprint,
+            privileges: privileges
+        })
+    }
+
+    /// Can this operator log in from given `user@host` ?
+    fn host_allowed(&self, userhost: &str) -> bool {
+        self.hosts.iter().any(|m| matches_mask(userhost, m.as_slice()))
+    }
+
+    /// Checks given password against the stored hash.
+    fn check_password(&self, password: &str) -> bool {
+        let fields: Vec<&str> = self.password.as_slice().split(':').collect();
+        let mut hasher = Sha256::new();
+        hasher.input_str(fields[1]);
+        hasher.input_str(password);
+        let digest = hasher.result_str();
+        // compare in constant time
+        digest.len() == fields[2].len()
+        && digest.as_bytes().iter().zip(fields[2].as_bytes().iter())
+                 .fold(0u8, |acc, (a, b)| acc | (*a ^ *b)) == 0
+    }
+}
+
+/// Parses all the operator blocks of the module config, returns them with the list
+/// of errors encountered.
+fn parse_opers(conf: &toml::TomlTable) -> (TreeMap<String, OperBlock>, Vec<String>) {
+    let mut opers = TreeMap::new();
+    let mut errors = Vec::new();
+    match conf.get(&"operators".to_string()) {
+        Some(&toml::Table(ref oper_list)) => for (login, v) in oper_list.iter() {
+            if let &toml::Table(ref block) = v {
+                match OperBlock::parse(login.as_slice(), block) {
+                    Ok(oper) => { opers.insert(login.clone(), oper); },
+                    Err(e) => errors.push(e)
+                }
+            } else {
+                errors.push(format!("Operator {} should be a [module.core.operators.{}] section.",
+                    login, login));
+            }
+        },
+        Some(_) => errors.push(
+            String::from_str("Operators should be defined in [module.core.operators.<login>] sections.")
+        ),
+        None => {}
+    }
+    (opers, errors)
+}
 
 pub struct CmdOper {
-    opers: TreeMap<String, String>
+    opers: RWLock<TreeMap<String, OperBlock>>
 }
 
 impl CmdOper {
     pub fn init(conf: &toml::TomlTable, logger: &Logger) -> CmdOper {
-        let mut opers = TreeMap::new();
-        if let Some(&toml::Array(ref oper_list)) = conf.get(&"operators".to_string()) {
-            for v in oper_list.iter() {
-                if let &toml::Array(ref oper) = v {
-                if oper.len() >= 2 {
-                if let toml::String(ref login) = oper[0] {
-                if let toml::String(ref pass) = oper[1] {
-                    opers.insert(login.clone(), pass.clone());
-                    continue;
-                }}}}
-                logger.log(Warning,
-                    String::from_str("Bad syntax in operators list.\
-                                      Each entry should be in the format [\"login\", \"password\"].")
-                );
-            }
+        let (opers, errors) = parse_opers(conf);
+        for e in errors.into_iter() {
+            logger.log(Warning, format!("(mod_core) {}", e));
         }
         logger.log(Info, format!("(mod_core) {} operators were loaded from config file.", opers.len()));
         CmdOper {
-            opers: opers
+            opers: RWLock::new(opers)
         }
     }
 }
 
-module!(CmdOper is CommandHandler)
+impl CmdOper {
+    /// Lists the host masks each operator can log in from, as `(mask, login)`.
+    pub fn oper_hosts(&self) -> Vec<(String, String)> {
+        let mut list = Vec::new();
+        for (login, oper) in self.opers.read().iter() {
+            for host in oper.hosts.iter() {
+                list.push((host.clone(), login.clone()));
+            }
+        }
+        list
+    }
+}
+
+impl ConfigReloadHandler for CmdOper {
+    fn check_config(&self, conf: &toml::TomlTable) -> Result<(), String> {
+        let (_, errors) = parse_opers(conf);
+        if errors.len() > 0 { Err(errors.connect(" ")) } else { Ok(()) }
+    }
+
+    fn reload_config(&self, conf: &toml::TomlTable, logger: &Logger) -> Result<(), String> {
+        let (opers, errors) = parse_opers(conf);
+        if errors.len() > 0 {
+            // keep the previous operators rather than locking some of them out
+            return Err(errors.connect(" "));
+        }
+        logger.log(Info, format!("(mod_core) {} operators were reloaded from config file.", opers.len()));
+        *self.opers.write() = opers;
+        Ok(())
+    }
+}
 
 impl CommandHandler for CmdOper {
     fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "OPER" { return (false, Nothing); }
+        if let Some(args) = cmd.as_nparams(2,0) {
+            let userhost = format!("{}@{}", user.username, user.hostname);
+            match self.opers.read().get(&args[0]) {
+                Some(oper) if oper.host_allowed(userhost.as_slice()) && oper.fingerprint.is_none() => {
+                    if oper.check_password(args[1].as_slice()) {
+                        // login successful
+                        user.modes.write().set('o'.to_ascii(), true);
+                        *user.privileges.write() = oper.privileges.clone();
+                        srv.logger.log(Info, format!("Operator {} logged in from user {}.",
+                            args[0], user.get_fullname()));
+                        user.push_numreply(
+                            numericreply::RPL_YOUREOPER,
+                            srv.settings.read().name.as_slice()
+                        );
+                        user.push_message(
+                            IRCMessage {
+                                tags: Vec::new(),
+                                prefix: Some(srv.settings.read().name.clone()),
+                                command: "MODE".to_string(),
+                                args: vec!(user.nickname.clone(), "+o".to_string()),
+                                suffix: None
+                            }
+                        );
+                    } else {
+                        srv.logger.log(Warning, format!("Failed OPER attempt as {} by {} : bad password.",
+                            args[0], user.get_fullname()));
+                        user.push_numreply(
+                            numericreply::ERR_PASSWDMISMATCH,
+                            srv.settings.read().name.as_slice()
+                        );
+                    }
+                },
+                Some(oper) => {
+                    if oper.fingerprint.is_some() {
+                        // certificates cannot be checked without TLS
+                        srv.logger.log(Warning, format!("Failed OPER attempt as {} by {} : \
+                            certificate fingerprint required but TLS is not available.",
+                            args[0], user.get_fullname()));
+                    } else {
+                        srv.logger.log(Warning, format!("Failed OPER attempt as {} by {} : host not allowed.",
+                            args[0], user.get_fullname()));
+                    }
+                    user.push_numreply(
+                        numericreply::ERR_NOOPERHOST,
+                        srv.settings.read().name.as_slice()
+                    );
+                },
+                None => {
+                    user.push_numreply(
+                        numericreply::ERR_NOOPERHOST,
+                        srv.settings.read().name.as_slice()
+                    );
+                }
+            }
+        } else {
+            send_needmoreparams(user, "OPER", srv);
+        }
+        (true, Nothing)
+    }
+}
+
+pub struct CmdDie;
+
+impl CommandHandler for CmdDie {
+    fn handle_command(&self, user: &UserData, _: &Uuid, _: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        if user.has_privilege(PrivDie) {
+            srv.logger.log(Info, format!("Server Shutdown was requested by {}.", user.get_fullname()));
+            *srv.signal_shutdown.write() = true
+        } else {
+            user.push_numreply(
+                numericreply::ERR_NOPRIVILIGES,
+                srv.settings.read().name.as_slice()
+            );
+        }
+
+        (true, Nothing)
+    }
+}
+
+pub struct CmdKill;
+
+impl CommandHandler for CmdKill {
+    fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        if !user.has_privilege(PrivKill) {
+            user.push_numreply(
+                numericreply::ERR_NOPRIVILIGES,
+                srv.settings.read().name.as_slice()
+            );
+            return (true, Nothing);
+        }
 
         if let Some(args) = cmd.as_nparams(2,0) {
-            if self.opers.get(&args[0]).map(|s| s == &args[1]).unwrap_or(false) {
-                // login successful
-                user.modes.write().set('o'.to_ascii(), true);
-                srv.logger.log(Info, format!("Operator {} logged in from user {}.", args[0], user.get_fullname()));
+            if args[0] == srv.settings.read().name {
                 user.push_numreply(
-                    numericreply::RPL_YOUREOPER,
+                    numericreply::ERR_CANTKILLSERVER,
                     srv.settings.read().name.as_slice()
                 );
-                user.push_message(
-                    IRCMessage {
-                        prefix: Some(srv.settings.read().name.clone()),
-                        command: "MODE".to_string(),
-                        args: vec!(user.nickname.clone(), "+o".to_string()),
-                        suffix: None
-                    }
-                );
+            } else if let Some(victim) = srv.users.read().get_user_by_nickname(args[0].as_slice()) {
+                srv.logger.log(Info, format!("{} was killed by operator {} ({}).",
+                    victim.get_fullname(), user.get_fullname(), args[1]));
+                return (true, Kill(
+                    vec!(victim.id.clone()),
+                    format!("Killed ({} ({}))", user.nickname, args[1])
+                ));
             } else {
                 user.push_numreply(
-                    numericreply::ERR_PASSWDMISMATCH,
+                    numericreply::ERR_NOSUCHNICK(args[0].as_slice()),
                     srv.settings.read().name.as_slice()
                 );
             }
         } else {
-            send_needmoreparams(user, "OPER", srv);
+            send_needmoreparams(user, "KILL", srv);
         }
         (true, Nothing)
     }
 }
 
-pub struct CmdDie;
+/// Is this a valid username or hostname to give to a user ?
+fn check_host_part(part: &str) -> bool {
+    part.len() > 0 && part.len() <= 63 && !part.starts_with(":")
+    && !part.chars().any(|c| c == ' ' || c == '!' || c == '@' || c == ',' || c == '*')
+}
 
-module!(CmdDie is CommandHandler)
+pub struct CmdChghost;
 
-impl CommandHandler for CmdDie {
+impl CommandHandler for CmdChghost {
     fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "DIE" { return (false, Nothing); }
+        if !user.has_privilege(PrivOverride) {
+            user.push_numreply(
+                numericreply::ERR_NOPRIVILIGES,
+                srv.settings.read().name.as_slice()
+            );
+            return (true, Nothing);
+        }
 
-        if user.modes.read().get('o'.to_ascii()) {
-            srv.logger.log(Info, format!("Server Shutdown was requested by {}.", user.get_fullname()));
-            *srv.signal_shutdown.write() = true
+        if let Some(args) = cmd.as_nparams(3,0) {
+            if !check_host_part(args[1].as_slice()) || !check_host_part(args[2].as_slice()) {
+                send_notice(user, format!("*** Invalid username or hostname: {}@{}", args[1], args[2]), srv);
+            } else if let Some(target) = srv.users.read().get_user_by_nickname(args[0].as_slice()) {
+                srv.logger.log(Info, format!("{} was changed to {}@{} by operator {}.",
+                    target.get_fullname(), args[1], args[2], user.get_fullname()));
+                return (true, ChangeHost(target.id.clone(), args[1].clone(), args[2].clone()));
+            } else {
+                user.push_numreply(
+                    numericreply::ERR_NOSUCHNICK(args[0].as_slice()),
+                    srv.settings.read().name.as_slice()
+                );
+            }
         } else {
+            send_needmoreparams(user, "CHGHOST", srv);
+        }
+        (true, Nothing)
+    }
+}
+
+pub struct CmdRehash;
+
+impl CommandHandler for CmdRehash {
+    fn handle_command(&self, user: &UserData, _: &Uuid, _: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        if !user.has_privilege(PrivRehash) {
             user.push_numreply(
                 numericreply::ERR_NOPRIVILIGES,
                 srv.settings.read().name.as_slice()
             );
+            return (true, Nothing);
         }
 
+        let file = format!("{}", srv.settings.read().config_file.display());
+        user.push_numreply(
+            numericreply::RPL_REHASHING(file.as_slice()),
+            srv.settings.read().name.as_slice()
+        );
+        srv.logger.log(Info, format!("Rehash requested by {}.", user.get_fullname()));
+        match srv.rehash() {
+            Ok(warnings) => {
+                for w in warnings.into_iter() {
+                    srv.logger.log(Warning, format!("Rehash: {}", w));
+                    send_notice(user, format!("*** Rehash: {}", w), srv);
+                }
+                send_notice(user, "*** Configuration reloaded.".to_string(), srv);
+            },
+            Err(e) => {
+                srv.logger.log(Error, format!("Rehash failed: {}", e));
+                send_notice(user, format!("*** Rehash failed, configuration unchanged: {}", e), srv);
+            }
+        }
         (true, Nothing)
     }
 }
\ No newline at end of file
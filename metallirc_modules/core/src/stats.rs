@@ -0,0 +1,144 @@
+//! STATS command.
+
+#![experimental]
+
+use metallirc::bans::{BanKind, KLine, DLine, QLine};
+use metallirc::messages::{IRCMessage, numericreply};
+use metallirc::ServerData;
+use metallirc::users::{UserData, Privilege, PrivKline};
+
+use std::sync::Arc;
+
+use time::get_time;
+use uuid::Uuid;
+
+use metallirc::modules::{RecyclingAction, Nothing};
+use metallirc::modules::{CommandHandler, send_needmoreparams};
+
+use oper::CmdOper;
+
+pub struct CmdStats {
+    oper: Arc<CmdOper>
+}
+
+impl CmdStats {
+    pub fn new(oper: Arc<CmdOper>) -> CmdStats {
+        CmdStats {
+            oper: oper
+        }
+    }
+}
+
+impl CommandHandler for CmdStats {
+    fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        if let Some(args) = cmd.as_nparams(1,1) {
+            let query = args[0].as_slice();
+            match query {
+                "y" | "Y" => if check_oper(user, None, srv) { send_classes(user, srv); },
+                "k" | "K" => if check_oper(user, Some(PrivKline), srv) { send_bans(user, KLine, srv); },
+                "d" | "D" => if check_oper(user, Some(PrivKline), srv) { send_bans(user, DLine, srv); },
+                "q" | "Q" => if check_oper(user, Some(PrivKline), srv) { send_bans(user, QLine, srv); },
+                "u" | "U" => send_uptime(user, srv),
+                "m" | "M" => send_commands(user, srv),
+                "o" | "O" => if check_oper(user, None, srv) { self.send_opers(user, srv); },
+                _ => {}
+            }
+            user.push_numreply(
+                numericreply::RPL_ENDOFSTATS(query),
+                srv.settings.read().name.as_slice()
+            );
+        } else {
+            send_needmoreparams(user, "STATS", srv);
+        }
+        (true, Nothing)
+    }
+}
+
+/// Checks that the user is an operator with given privilege (if any), sending an error if not.
+fn check_oper(user: &UserData, privilege: Option<Privilege>, srv: &ServerData) -> bool {
+    if privilege.map_or(user.modes.read().get('o'.to_ascii()), |p| user.has_privilege(p)) {
+        true
+    } else {
+        user.push_numreply(
+            numericreply::ERR_NOPRIVILIGES,
+            srv.settings.read().name.as_slice()
+        );
+        false
+    }
+}
+
+/// Sends the list of connection classes.
+fn send_classes(user: &UserData, srv: &ServerData) {
+    let settings = srv.settings.read();
+    for class in settings.classes.iter() {
+        user.push_numreply(
+            numericreply::RPL_STATSYLINE(
+                class.name.as_slice(),
+                class.ping_frequency as u32,
+                class.max_clients as u32,
+                class.sendq as u32,
+                class.recvq as u32
+            ),
+            settings.name.as_slice()
+        );
+    }
+}
+
+/// Sends the list of active bans of given kind.
+fn send_bans(user: &UserData, kind: BanKind, srv: &ServerData) {
+    let settings = srv.settings.read();
+    for ban in srv.bans.read().list(kind, get_time().sec).iter() {
+        let reason = match ban.expires {
+            Some(t) => format!("{} (expires in {} minutes)", ban.reason, (t - get_time().sec) / 60 + 1),
+            None => ban.reason.clone()
+        };
+        user.push_numreply(
+            match kind {
+                KLine => numericreply::RPL_STATSKLINE(ban.mask.as_slice(), reason.as_slice()),
+                DLine => numericreply::RPL_STATSDLINE(ban.mask.as_slice(), reason.as_slice()),
+                QLine => numericreply::RPL_STATSQLINE(ban.mask.as_slice(), reason.as_slice())
+            },
+            settings.name.as_slice()
+        );
+    }
+}
+
+impl CmdStats {
+    /// Sends the list of operator blocks.
+    fn send_opers(&self, user: &UserData, srv: &ServerData) {
+        let name = srv.settings.read().name.clone();
+        for (host, login) in self.oper.oper_hosts().into_iter() {
+            user.push_numreply(
+                numericreply::RPL_STATSOLINE(host.as_slice(), login.as_slice()),
+                name.as_slice()
+            );
+        }
+    }
+}
+
+/// Sends the time since the server was started.
+fn send_uptime(user: &UserData, srv: &ServerData) {
+    let up = get_time().sec - srv.start_time.sec;
+    user.push_numreply(
+        numericreply::RPL_STATSUPTIME(
+            (up / 86400) as u32,
+            ((up % 86400) / 3600) as u8,
+            ((up % 3600) / 60) as u8,
+            (up % 60) as u8
+        ),
+        srv.settings.read().name.as_slice()
+    );
+}
+
+/// Sends the usage counts of the commands.
+fn send_commands(user: &UserData, srv: &ServerData) {
+    let name = srv.settings.read().name.clone();
+    for (command, count, bytes) in srv.modules_handler.read().command_stats().into_iter() {
+        user.push_numreply(
+            // no remote server can send commands
+            numericreply::RPL_STATSCOMMANDS(command.as_slice(), count as u32, bytes as u32, 0),
+            name.as_slice()
+        );
+    }
+}
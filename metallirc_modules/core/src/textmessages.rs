@@ -2,52 +2,101 @@
 
 #![experimental]
 
+use metallirc::logging::{Logger, Warning};
 use metallirc::messages::{IRCMessage, TextMessage, Channel, User, Everybody, numericreply};
+use metallirc::messages::{Privmsg, Notice, Tagmsg};
 use metallirc::ServerData;
 use metallirc::users::UserData;
 
+use toml;
 use uuid::Uuid;
 
 use metallirc::modules::{RecyclingAction, Nothing};
 use metallirc::modules::{MessageSendingHandler, CommandHandler};
 
-pub struct CmdPrivmsgOrNotice;
+use modes::is_banned;
 
-module!(CmdPrivmsgOrNotice is CommandHandler)
+/// Handles PRIVMSG, NOTICE and TAGMSG, relaying the client-only tags not denied
+/// by the configuration.
+pub struct CmdPrivmsgOrNotice {
+    /// The denied client tags, without their `+`. `*` denies all the tags except
+    /// the ones given as `-<tag>`.
+    denied_tags: Vec<String>
+}
+
+impl CmdPrivmsgOrNotice {
+    pub fn init(conf: &toml::TomlTable, logger: &Logger) -> CmdPrivmsgOrNotice {
+        let mut denied_tags = Vec::new();
+        match conf.get(&"client_tag_deny".to_string()) {
+            Some(&toml::Array(ref tags)) => for t in tags.iter() {
+                match t {
+                    &toml::String(ref tag) => denied_tags.push(tag.as_slice().trim_left_chars('+').to_string()),
+                    _ => logger.log(Warning, "(mod_core) Invalid denied client tag, ignored.".to_string())
+                }
+            },
+            Some(_) => logger.log(Warning, "(mod_core) The denied client tags should be a list.".to_string()),
+            None => {}
+        }
+        CmdPrivmsgOrNotice {
+            denied_tags: denied_tags
+        }
+    }
+
+    /// The value of the CLIENTTAGDENY ISUPPORT token, None if no tag is denied.
+    pub fn clienttagdeny(&self) -> Option<String> {
+        if self.denied_tags.is_empty() { None } else { Some(self.denied_tags.connect(",")) }
+    }
+
+    /// Whether the client tag of given name (without its `+`) can be relayed.
+    fn allows(&self, name: &str) -> bool {
+        if self.denied_tags.iter().any(|t| t.as_slice() == "*") {
+            self.denied_tags.iter().any(|t| t.as_slice().starts_with("-") && t.as_slice().slice_from(1) == name)
+        } else {
+            !self.denied_tags.iter().any(|t| t.as_slice() == name)
+        }
+    }
+
+    /// The tags of a command to relay with the message.
+    fn client_tags(&self, cmd: &IRCMessage) -> Vec<(String, String)> {
+        cmd.tags.iter().filter(|&&(ref key, _)| {
+            key.as_slice().starts_with("+") && self.allows(key.as_slice().slice_from(1))
+        }).map(|t| t.clone()).collect()
+    }
+}
 
 impl CommandHandler for CmdPrivmsgOrNotice {
     fn handle_command(&self, user: &UserData, user_uuid: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        let notice = match cmd.command.as_slice() {
-            "NOTICE" => true,
-            "PRIVMSG" => false,
+        let kind = match cmd.command.as_slice() {
+            "NOTICE" => Notice,
+            "PRIVMSG" => Privmsg,
+            "TAGMSG" => Tagmsg,
             _ => return (false, Nothing)
         };
 
-        if let Some(args) = cmd.as_nparams(2, 0) {
+        // a TAGMSG has no text
+        let args = if kind == Tagmsg { cmd.as_nparams(1, 0) } else { cmd.as_nparams(2, 0) };
+        if let Some(args) = args {
 
-            if let Some(id) = srv.users.read().get_uuid_of_nickname(args[0].as_slice()) {
-                srv.modules_handler.read().send_message(
-                    TextMessage {
-                        notice: notice,
-                        source: User(user_uuid.clone(), user.nickname.clone()),
-                        target: User(id, args[0].clone()),
-                        text: args[1].clone()
-                    }, srv);
+            let target = if let Some(id) = srv.users.read().get_uuid_of_nickname(args[0].as_slice()) {
+                User(id, args[0].clone())
             } else if srv.channels.read().has_chan(args[0].as_slice()) {
-                srv.modules_handler.read().send_message(
-                    TextMessage {
-                        notice: notice,
-                        source: User(user_uuid.clone(), user.nickname.clone()),
-                        target: Channel(args[0].clone()),
-                        text: args[1].clone()
-                    }, srv);
+                Channel(args[0].clone())
             } else {
                 user.push_numreply(
                     numericreply::ERR_NOSUCHNICK(args[0].as_slice()),
                     srv.settings.read().name.as_slice()
                 );
-            }
+                return (true, Nothing);
+            };
+            srv.modules_handler.read().send_message(
+                TextMessage {
+                    kind: kind,
+                    source: User(user_uuid.clone(), user.nickname.clone()),
+                    target: target,
+                    text: args.get(1).map_or(String::new(), |t| t.clone()),
+                    tags: self.client_tags(cmd)
+                }, srv);
         } else if cmd.args.len() >= 1 {
             user.push_numreply(
                 numericreply::ERR_NOTEXTTOSEND,
@@ -63,94 +112,163 @@ impl CommandHandler for CmdPrivmsgOrNotice {
     }
 }
 
-pub struct QueryDispatcher;
+/// Sends a message back to the user who sent it, if it enabled `echo-message`.
+fn echo_to_sender(msg: &TextMessage, target: &str, srv: &ServerData) {
+    if let User(ref id, ref nick) = msg.source {
+        if let Some(sender) = srv.users.read().get_user_by_uuid(id) {
+            if sender.has_capability("echo-message") {
+                if let Some(m) = msg.message_for(nick.clone(), target.to_string(), &*sender) {
+                    sender.push_message(m);
+                }
+            }
+        }
+    }
+}
 
-module!(QueryDispatcher is MessageSendingHandler)
+pub struct QueryDispatcher;
 
 impl MessageSendingHandler for QueryDispatcher {
     fn handle_message_sending(&self, cmd: TextMessage, srv: &ServerData) -> Option<TextMessage> {
+        let prefix = cmd.source.clone().into_text();
         match cmd.target {
             Everybody => {
                 srv.users.read().apply_to_all(|u| {
-                    u.push_message(
-                        IRCMessage {
-                            prefix: Some(cmd.source.clone().into_text()),
-                            command: if cmd.notice { "NOTICE" } else { "PRIVMSG" }.to_string(),
-                            args: vec!(u.nickname.clone()),
-                            suffix: Some(cmd.text.clone())
-                        }
-                    )
+                    if let Some(msg) = cmd.message_for(prefix.clone(), u.nickname.clone(), u) {
+                        u.push_message(msg);
+                    }
                 });
-                None
+                return None;
             },
-            User(id, _) => {
-                srv.users.read().get_user_by_uuid(&id).unwrap().push_message(
-                    IRCMessage {
-                        prefix: Some(cmd.source.into_text()),
-                        command: if cmd.notice { "NOTICE" } else { "PRIVMSG" }.to_string(),
-                        args: vec!(cmd.target.into_text()),
-                        suffix: Some(cmd.text)
+            User(ref id, ref nick) => {
+                if let Some(recipient) = srv.users.read().get_user_by_uuid(id) {
+                    if let Some(msg) = cmd.message_for(prefix.clone(), nick.clone(), &*recipient) {
+                        recipient.push_message(msg);
                     }
-                );
-                None
+                }
+                echo_to_sender(&cmd, nick.as_slice(), srv);
+                return None;
             },
-            _ => Some(cmd)
+            _ => {}
         }
+        Some(cmd)
     }
 }
 
-pub struct ChannelDispatcher;
-
-module!(ChannelDispatcher is MessageSendingHandler)
+/// Checks that users are allowed to send messages to the chans, before the other
+/// handlers see the messages: the `n` and `m` modes, and the bans, which do not keep
+/// the voiced members from speaking.
+pub struct ChannelPolicy;
 
-impl MessageSendingHandler for ChannelDispatcher {
+impl MessageSendingHandler for ChannelPolicy {
     fn handle_message_sending(&self, cmd: TextMessage, srv: &ServerData) -> Option<TextMessage> {
-        match cmd.target {
-            Channel(chan) => {
-                // Check external messages
-                if let User(ref id, _) = cmd.source {
-                    let umanager_handle = srv.users.read();
-                    let user = umanager_handle.get_user_by_uuid(id).unwrap();
-                    // check external messages
-                    if srv.channels.read().chan_handle(chan.as_slice())
-                        .map(|c| c.read().modes.get('n'.to_ascii())).unwrap_or(false) {
-                        // There is some checking to do
-                        if user.membership(chan.as_slice()).is_none() {
-                            user.push_numreply(
-                                numericreply::ERR_CANNOTSENDTOCHAN(chan.as_slice()),
-                                srv.settings.read().name.as_slice()
-                            );
-                            return None;
-                        }
+        if let Channel(ref chan) = cmd.target {
+            if let User(ref id, _) = cmd.source {
+                let umanager_handle = srv.users.read();
+                let user = umanager_handle.get_user_by_uuid(id).unwrap();
+                // check external messages
+                if srv.channels.read().chan_handle(chan.as_slice())
+                    .map(|c| c.read().modes.get('n'.to_ascii())).unwrap_or(false) {
+                    // There is some checking to do
+                    if user.membership(chan.as_slice()).is_none() {
+                        user.push_numreply(
+                            numericreply::ERR_CANNOTSENDTOCHAN(chan.as_slice()),
+                            srv.settings.read().name.as_slice()
+                        );
+                        return None;
                     }
-                    // check moderated chan
-                    if srv.channels.read().chan_handle(chan.as_slice())
-                        .map(|c| c.read().modes.get('m'.to_ascii())).unwrap_or(false) {
-                        // There is some checking to do
-                        if !user.membership(chan.as_slice())
-                            .map(|m| !m.modes.read().none()).unwrap_or(false) {
-                            user.push_numreply(
-                                numericreply::ERR_CANNOTSENDTOCHAN(chan.as_slice()),
-                                srv.settings.read().name.as_slice()
-                            );
-                            return None;
-                        }
+                }
+                // check moderated chan
+                if srv.channels.read().chan_handle(chan.as_slice())
+                    .map(|c| c.read().modes.get('m'.to_ascii())).unwrap_or(false) {
+                    // There is some checking to do
+                    if !user.membership(chan.as_slice())
+                        .map(|m| !m.modes.read().none()).unwrap_or(false) {
+                        user.push_numreply(
+                            numericreply::ERR_CANNOTSENDTOCHAN(chan.as_slice()),
+                            srv.settings.read().name.as_slice()
+                        );
+                        return None;
+                    }
+                }
+                // check bans
+                if srv.channels.read().chan_handle(chan.as_slice())
+                    .map(|c| is_banned(&*c.read(), user.get_fullname().as_slice())).unwrap_or(false) {
+                    if user.membership(chan.as_slice()).map(|m| m.modes.read().none()).unwrap_or(true) {
+                        user.push_numreply(
+                            numericreply::ERR_CANNOTSENDTOCHAN(chan.as_slice()),
+                            srv.settings.read().name.as_slice()
+                        );
+                        return None;
                     }
                 }
-                // sending is valid
-                srv.channels.read().send_to_chan(
-                    chan.as_slice(),
-                    IRCMessage {
-                        prefix: Some(cmd.source.clone().into_text()),
-                        command: if cmd.notice { "NOTICE" } else { "PRIVMSG" }.to_string(),
-                        args: vec!(chan.clone()),
-                        suffix: Some(cmd.text)
-                    },
-                    if let User(id, _) = cmd.source { Some(id) } else { None }
-                );
-                None
             }
-            _ => Some(cmd)
         }
+        Some(cmd)
     }
-}
\ No newline at end of file
+}
+
+pub struct ChannelDispatcher;
+
+impl MessageSendingHandler for ChannelDispatcher {
+    fn handle_message_sending(&self, cmd: TextMessage, srv: &ServerData) -> Option<TextMessage> {
+        if let Channel(ref chan) = cmd.target {
+            // the sending was allowed by ChannelPolicy
+            let prefix = cmd.source.clone().into_text();
+            let sender = if let User(ref id, _) = cmd.source { Some(id.clone()) } else { None };
+            if let Some(handle) = srv.channels.read().chan_handle(chan.as_slice()) {
+                handle.read().apply_to_members(|id, m| {
+                    if sender.as_ref() == Some(id) { return; }
+                    let member = m.user.upgrade().unwrap();
+                    let member = member.read();
+                    if let Some(msg) = cmd.message_for(prefix.clone(), chan.clone(), &*member) {
+                        member.push_message(msg);
+                    }
+                });
+            }
+            echo_to_sender(&cmd, chan.as_slice(), srv);
+            return None;
+        }
+        Some(cmd)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use metallirc::logging::{Logger, Error};
+    use metallirc::messages::IRCMessage;
+
+    use toml;
+
+    use super::CmdPrivmsgOrNotice;
+
+    fn denying(tags: &str) -> CmdPrivmsgOrNotice {
+        let conf = toml::Parser::new(format!("client_tag_deny = [{}]", tags).as_slice()).parse().unwrap();
+        CmdPrivmsgOrNotice::init(&conf, &Logger::new(Error))
+    }
+
+    #[test]
+    fn test_allows() {
+        assert!(denying("").allows("draft/react"));
+        // the leading + is stripped
+        let some = denying("\"+draft/react\", \"typing\"");
+        assert!(!some.allows("draft/react"));
+        assert!(!some.allows("typing"));
+        assert!(some.allows("draft/reply"));
+        assert_eq!(some.clienttagdeny(), Some("draft/react,typing".to_string()));
+        // all but the ones given as -<tag>
+        let all = denying("\"*\", \"-typing\"");
+        assert!(all.allows("typing"));
+        assert!(!all.allows("draft/react"));
+        assert!(!all.allows("-typing"));
+    }
+
+    #[test]
+    fn test_client_tags() {
+        let cmd: IRCMessage = from_str("@+draft/react=lol;+typing=active;label=l1 PRIVMSG #chan :hi").unwrap();
+        assert_eq!(denying("\"typing\"").client_tags(&cmd),
+                   vec!(("+draft/react".to_string(), "lol".to_string())));
+        assert_eq!(denying("").client_tags(&cmd).len(), 2);
+        assert!(denying("\"*\"").client_tags(&cmd).is_empty());
+    }
+}
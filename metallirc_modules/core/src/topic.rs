@@ -1,4 +1,5 @@
 use metallirc::messages::{IRCMessage, numericreply};
+use metallirc::modes::HALFOP_RANK;
 use metallirc::ServerData;
 use metallirc::users::UserData;
 
@@ -9,13 +10,9 @@ use metallirc::modules::{CommandHandler, send_needmoreparams};
 
 pub struct CmdTopic;
 
-module!(CmdTopic is CommandHandler)
-
 impl CommandHandler for CmdTopic {
     fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "TOPIC" { return (false, Nothing); }
-
         if let Some(args) = cmd.as_nparams(1, 1) {
             // first, find the chan
             let channels_handle = srv.channels.read();
@@ -49,7 +46,7 @@ impl CommandHandler for CmdTopic {
                 // it's an attempt to modify it.
                 let can_modify = if let Some(m) = user.membership(args[0].as_slice()) {
                     if !m.channel.upgrade().unwrap().read().modes.get('t'.to_ascii())
-                    || m.modes.read().get('o'.to_ascii()) {
+                    || m.rank() >= HALFOP_RANK {
                         true
                     } else {
                         user.push_numreply(
@@ -72,6 +69,7 @@ impl CommandHandler for CmdTopic {
                     channels_handle.send_to_chan(
                         args[0].as_slice(),
                         IRCMessage {
+                            tags: Vec::new(),
                             prefix: Some(user.get_fullname()),
                             command: "TOPIC".to_string(),
                             args: vec!(args[0].clone()),
@@ -1,12 +1,13 @@
 use metallirc::channels::Membership;
 use metallirc::messages::{IRCMessage, numericreply};
-use metallirc::modes::letter_for_membership;
+use metallirc::modes::membership_prefix;
 use metallirc::ServerData;
-use metallirc::users::UserData;
+use metallirc::users::{UserData, PrivSeeInvisible};
 use metallirc::util::matches_mask;
 
 use std::sync::Arc;
 
+use time::{Timespec, at};
 use uuid::Uuid;
 
 use metallirc::modules::{RecyclingAction, Nothing};
@@ -18,25 +19,23 @@ use metallirc::modules::{CommandHandler, send_needmoreparams};
 
 pub struct CmdWho;
 
-module!(CmdWho is CommandHandler)
-
 impl CommandHandler for CmdWho {
     fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "WHO" { return (false, Nothing); }
-
         if let Some(mut args) = cmd.as_nparams(0, 2) { // always true
             let (mask, only_oper) = match (args.pop(), args.pop()) {
                 (None, _) => ("*".into_string(), false),
                 (Some(m), None) => (m, false),
                 (Some(o), Some(m)) => (m, o.as_slice() == "o")
             };
+            let multi = user.has_capability("multi-prefix");
             if let Some(chandle) = srv.channels.read().chan_handle(mask.as_slice()) {
                 let me_in_chan = user.channels.read().contains_key(&mask);
                 chandle.read().apply_to_members(|_, membership| {
                     if let Some(other_lock) = membership.user.upgrade() {
                         let other = other_lock.read();
-                        if (me_in_chan || first_common_chan(user, &*other).is_some())
+                        if (me_in_chan || first_common_chan(user, &*other).is_some()
+                            || user.has_privilege(PrivSeeInvisible))
                         && (!only_oper || other.modes.read().get('o'.to_ascii())) {
                             user.push_numreply(
                                 numericreply::RPL_WHOREPLY(
@@ -47,7 +46,7 @@ impl CommandHandler for CmdWho {
                                     other.nickname.as_slice(),
                                     if other.modes.read().get('a'.to_ascii()) { 'G' } else { 'H' },
                                     false,
-                                    letter_for_membership(&*membership.modes.read()),
+                                    membership_prefix(&*membership.modes.read(), multi).as_slice(),
                                     0,
                                     other.realname.as_slice()
                                 ),
@@ -64,12 +63,13 @@ impl CommandHandler for CmdWho {
                     || matches_mask(other.realname.as_slice(), mask.as_slice()) {
                         let (chan, membership_tag) = {
                             if let Some((c, m)) = first_common_chan(user, other) {
-                                (c, letter_for_membership(&*m.modes.read()))
+                                (c, membership_prefix(&*m.modes.read(), multi))
                             } else {
-                                ('*'.to_string(), None)
+                                ('*'.to_string(), String::new())
                             }
                         };
-                        if (!other.modes.read().get('i'.to_ascii()) || chan.as_slice() != "*")
+                        if (!other.modes.read().get('i'.to_ascii()) || chan.as_slice() != "*"
+                            || user.has_privilege(PrivSeeInvisible))
                         && (!only_oper || other.modes.read().get('o'.to_ascii())) {
                             user.push_numreply(
                                 numericreply::RPL_WHOREPLY(
@@ -80,7 +80,7 @@ impl CommandHandler for CmdWho {
                                     other.nickname.as_slice(),
                                     if other.modes.read().get('a'.to_ascii()) { 'G' } else { 'H' },
                                     false,
-                                    membership_tag,
+                                    membership_tag.as_slice(),
                                     0,
                                     other.realname.as_slice()
                                 ),
@@ -117,20 +117,16 @@ fn first_common_chan(a: &UserData, b: &UserData) -> Option<(String, Arc<Membersh
 
 pub struct CmdWhois;
 
-module!(CmdWhois is CommandHandler)
-
 impl CommandHandler for CmdWhois {
     fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "WHOIS" { return (false, Nothing); }
-
         if let Some(mut args) = cmd.as_nparams(1,1) {
             let masks = args.pop().unwrap();
             for mask in masks.as_slice().split_terminator(',') {
                 if mask.contains_char('?') || mask.contains_char('*') {
                     srv.users.read().apply_to_all(|u| {
                         if matches_mask(u.nickname.as_slice(), mask) {
-                            if !u.modes.read().get('i'.to_ascii()) {
+                            if !u.modes.read().get('i'.to_ascii()) || user.has_privilege(PrivSeeInvisible) {
                                 // Invisible users can only be WHOISed with exact match
                                 send_whois(user, u, srv.settings.read().name.as_slice());
                             }
@@ -175,7 +171,7 @@ fn send_whois(me: &UserData, other: &UserData, srv_name: &str) {
         numericreply::RPL_WHOISCHANNELS(
             other.nickname.as_slice(),
             other.channels.read().iter().map(|(name, membership)| {
-                (letter_for_membership(&*membership.modes.read()),
+                (membership_prefix(&*membership.modes.read(), me.has_capability("multi-prefix")),
                 name.as_slice())
             }).collect::<Vec<_>>()
             ),
@@ -192,4 +188,119 @@ fn send_whois(me: &UserData, other: &UserData, srv_name: &str) {
         numericreply::RPL_WHOISIDLE(other.nickname.as_slice(), 0),
         srv_name
     );
-}
\ No newline at end of file
+}
+
+/*
+ * WHOWAS
+ */
+
+pub struct CmdWhowas;
+
+impl CommandHandler for CmdWhowas {
+    fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        if let Some(args) = cmd.as_nparams(1,2) {
+            // a null or negative count means no limit
+            let count = args.get(1).and_then(|c| from_str::<int>(c.as_slice()))
+                                   .and_then(|c| if c > 0 { Some(c as uint) } else { None });
+            let srv_name = srv.settings.read().name.clone();
+            let history = srv.whowas.read();
+            for nick in args[0].as_slice().split_terminator(',') {
+                let entries = history.lookup(nick);
+                if entries.is_empty() {
+                    user.push_numreply(numericreply::ERR_WASNOSUCHNICK(nick), srv_name.as_slice());
+                }
+                for entry in entries.iter().take(count.unwrap_or(entries.len())) {
+                    user.push_numreply(
+                        numericreply::RPL_WHOWASUSER(
+                            entry.nickname.as_slice(),
+                            entry.username.as_slice(),
+                            entry.hostname.as_slice(),
+                            entry.realname.as_slice()
+                        ),
+                        srv_name.as_slice()
+                    );
+                    user.push_numreply(
+                        numericreply::RPL_WHOISSERVER(
+                            entry.nickname.as_slice(),
+                            entry.server.as_slice(),
+                            at(Timespec::new(entry.signoff, 0)).rfc822z().to_string().as_slice()
+                        ),
+                        srv_name.as_slice()
+                    );
+                }
+            }
+            user.push_numreply(
+                numericreply::RPL_ENDOFWHOWAS(args[0].as_slice()),
+                srv_name.as_slice()
+            );
+        } else {
+            send_needmoreparams(user, "WHOWAS", srv);
+        }
+
+        (true, Nothing)
+    }
+}
+
+/*
+ * ISON & USERHOST
+ */
+
+/// Maximum number of nicknames in a USERHOST query.
+const USERHOST_MAX_NICKS: uint = 5;
+
+pub struct CmdIson;
+
+impl CommandHandler for CmdIson {
+    fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        if let Some(args) = cmd.as_nparams(1,0) {
+            let manager = srv.users.read();
+            // reply with the nicknames as they are currently written
+            let online: Vec<String> = args[0].as_slice().words()
+                                             .filter_map(|nick| manager.get_user_by_nickname(nick))
+                                             .map(|u| u.nickname.clone())
+                                             .collect();
+            user.push_numreply(
+                numericreply::RPL_ISON(online.iter().map(|n| n.as_slice()).collect()),
+                srv.settings.read().name.as_slice()
+            );
+        } else {
+            send_needmoreparams(user, "ISON", srv);
+        }
+
+        (true, Nothing)
+    }
+}
+
+pub struct CmdUserhost;
+
+impl CommandHandler for CmdUserhost {
+    fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        if let Some(args) = cmd.as_nparams(1,0) {
+            let manager = srv.users.read();
+            let found: Vec<(String, bool, bool, String)> = args[0].as_slice().words()
+                .take(USERHOST_MAX_NICKS)
+                .filter_map(|nick| manager.get_user_by_nickname(nick))
+                .map(|u| {
+                    let modes = u.modes.read();
+                    (u.nickname.clone(), modes.get('o'.to_ascii()), modes.get('a'.to_ascii()),
+                     format!("{}@{}", u.username, u.hostname))
+                })
+                .collect();
+            user.push_numreply(
+                numericreply::RPL_USERHOST(
+                    found.iter().map(|&(ref nick, oper, away, ref host)| {
+                        (nick.as_slice(), oper, away, host.as_slice())
+                    }).collect()
+                ),
+                srv.settings.read().name.as_slice()
+            );
+        } else {
+            send_needmoreparams(user, "USERHOST", srv);
+        }
+
+        (true, Nothing)
+    }
+}
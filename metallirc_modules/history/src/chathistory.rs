@@ -0,0 +1,534 @@
+//! Recording of the messages, and the CHATHISTORY command replaying them.
+
+#![experimental]
+
+use metallirc::extensions::SerializableExtension;
+use metallirc::logging::Error;
+use metallirc::messages::{IRCMessage, TextMessage, Channel, User};
+use metallirc::timers::TimedTask;
+use metallirc::ServerData;
+use metallirc::users::UserData;
+use metallirc::util::{check_nickname, format_timestamp, label_to_lower, parse_timestamp};
+
+use std::ascii::AsciiExt;
+use std::collections::HashMap;
+use std::cmp::{max, min};
+use std::sync::{Arc, RWLock};
+use std::sync::atomic::{AtomicBool, SeqCst};
+use std::time::duration::Duration;
+
+use time::get_time;
+use uuid::Uuid;
+
+use metallirc::modules::{RecyclingAction, Nothing};
+use metallirc::modules::{CommandHandler, EventHandler, MessageSendingHandler, UnloadHandler};
+
+use store::{HistoryEntry, HistoryStore, is_chan, query_key};
+
+/// Default maximum number of messages sent for one CHATHISTORY command.
+pub const DEFAULT_MAX_LIMIT: uint = 100;
+/// Default interval between two compactions of the history file, in seconds.
+pub const DEFAULT_COMPACT_INTERVAL: uint = 3600;
+
+/// Time at which a user took its nickname, stored in its extensions: the queries
+/// sent to or by this nickname before are not its own.
+pub struct NickSince {
+    time: i64
+}
+
+impl SerializableExtension for NickSince {
+    fn serialize(&self) -> String {
+        self.time.to_string()
+    }
+
+    fn deserialize(data: &str) -> Option<NickSince> {
+        from_str(data).map(|t| NickSince { time: t })
+    }
+}
+
+/// The current time, in milliseconds since the epoch.
+fn now_ms() -> i64 {
+    let now = get_time();
+    now.sec * 1000 + (now.nsec / 1000000) as i64
+}
+
+/// Time at which given chan was created, in milliseconds: the messages sent before are
+/// the ones of a former chan of the same name. None if it does not exist.
+fn chan_since(chan: &str, srv: &ServerData) -> Option<i64> {
+    srv.channels.read().chan_handle(chan).map(|c| c.read().creation_time * 1000)
+}
+
+/// Time at which the user took its nickname, now if unknown.
+fn nick_since(user: &UserData) -> i64 {
+    user.extensions.with(|s: &NickSince| s.time).unwrap_or_else(|| now_ms())
+}
+
+/// `a - b`, or 0 if `b` is greater.
+fn minus(a: uint, b: uint) -> uint {
+    if a > b { a - b } else { 0 }
+}
+
+/// A point in a history, as given to CHATHISTORY.
+enum Selector {
+    /// `*`, no constraint.
+    Anything,
+    /// `timestamp=<time>`
+    Time(i64),
+    /// `msgid=<id>`
+    MsgId(String)
+}
+
+fn parse_selector(text: &str) -> Option<Selector> {
+    if text == "*" {
+        Some(Anything)
+    } else if text.starts_with("timestamp=") {
+        parse_timestamp(text.slice_from(10)).map(|t| Time(t))
+    } else if text.starts_with("msgid=") && text.len() > 6 {
+        Some(MsgId(text.slice_from(6).to_string()))
+    } else {
+        None
+    }
+}
+
+/// Index of the first entry not before the selector, None if the msgid is unknown.
+fn index_before(entries: &[&HistoryEntry], sel: &Selector) -> Option<uint> {
+    match *sel {
+        Anything => Some(entries.len()),
+        Time(t) => Some(entries.iter().position(|e| e.time >= t).unwrap_or(entries.len())),
+        MsgId(ref id) => entries.iter().position(|e| e.msgid == *id)
+    }
+}
+
+/// Index of the first entry after the selector, None if the msgid is unknown.
+fn index_after(entries: &[&HistoryEntry], sel: &Selector) -> Option<uint> {
+    match *sel {
+        Anything => Some(0),
+        Time(t) => Some(entries.iter().position(|e| e.time > t).unwrap_or(entries.len())),
+        MsgId(ref id) => entries.iter().position(|e| e.msgid == *id).map(|i| i + 1)
+    }
+}
+
+/// Range of the entries selected by a subcommand reading a history, given its message
+/// references. An unknown msgid gives no message.
+fn select_range(sub: &str, entries: &[&HistoryEntry], selectors: &[Selector], limit: uint) -> (uint, uint) {
+    let len = entries.len();
+    let (start, end) = match sub {
+        "LATEST" => match index_after(entries, &selectors[0]) {
+            Some(a) => (max(a, minus(len, limit)), len),
+            None => (0, 0)
+        },
+        "BEFORE" => match index_before(entries, &selectors[0]) {
+            Some(b) => (minus(b, limit), b),
+            None => (0, 0)
+        },
+        "AFTER" => match index_after(entries, &selectors[0]) {
+            Some(a) => (a, min(len, a + limit)),
+            None => (0, 0)
+        },
+        "AROUND" => match index_before(entries, &selectors[0]) {
+            Some(b) => {
+                let start = minus(b, limit / 2);
+                (start, min(len, start + limit))
+            },
+            None => (0, 0)
+        },
+        _ => match (index_before(entries, &selectors[0]), index_after(entries, &selectors[0]),
+                    index_before(entries, &selectors[1]), index_after(entries, &selectors[1])) {
+            // the first reference can be before or after the second one
+            (Some(b1), Some(a1), Some(b2), Some(a2)) => if b1 <= b2 {
+                (a1, min(b2, a1 + limit))
+            } else {
+                (max(a2, minus(b1, limit)), b1)
+            },
+            _ => (0, 0)
+        }
+    };
+    (start, max(start, end))
+}
+
+/// Sends a FAIL reply about the CHATHISTORY command.
+fn send_fail(user: &UserData, code: &str, context: Vec<&str>, description: &str, srv: &ServerData) {
+    let mut args = vec!("CHATHISTORY".to_string(), code.to_string());
+    args.extend(context.into_iter().map(|c| c.to_string()));
+    user.push_message(
+        IRCMessage {
+            tags: Vec::new(),
+            prefix: Some(srv.settings.read().name.clone()),
+            command: "FAIL".to_string(),
+            args: args,
+            suffix: Some(description.to_string())
+        }
+    );
+}
+
+/// Rewrites the history file without the expired entries.
+struct CompactHistory {
+    store: Arc<RWLock<HistoryStore>>
+}
+
+impl TimedTask for CompactHistory {
+    fn run(&self, srv: &ServerData) {
+        if let Err(e) = self.store.write().compact(now_ms()) {
+            srv.logger.log(Error, format!("(mod_history) Unable to write history file : {}", e));
+        }
+    }
+}
+
+/// Records the messages, and handles CHATHISTORY.
+pub struct ModHistory {
+    store: Arc<RWLock<HistoryStore>>,
+    /// Maximum number of messages sent for one command.
+    max_limit: uint,
+    /// Interval between two compactions of the history file, in seconds.
+    compact_interval: uint,
+    /// The compaction needs the server, so it is scheduled with the first message.
+    compaction_scheduled: AtomicBool
+}
+
+impl ModHistory {
+    pub fn new(store: HistoryStore, max_limit: uint, compact_interval: uint) -> ModHistory {
+        ModHistory {
+            store: Arc::new(RWLock::new(store)),
+            max_limit: max_limit,
+            compact_interval: compact_interval,
+            compaction_scheduled: AtomicBool::new(false)
+        }
+    }
+
+    /// The key of the history of given target if the user can read it, with the time
+    /// from which it can.
+    ///
+    /// The history of a chan can only be read by its members, from its creation, and the
+    /// messages of a query by the user who held one of its nicknames since they were sent.
+    fn readable_key(&self, user: &UserData, target: &str, srv: &ServerData) -> Option<(String, i64)> {
+        if is_chan(target) {
+            if user.membership(target).is_none() { return None; }
+            chan_since(target, srv).map(|since| (label_to_lower(target), since))
+        } else if check_nickname(target) {
+            Some((query_key(user.nickname.as_slice(), target), nick_since(user)))
+        } else {
+            None
+        }
+    }
+
+    /// A recorded message as sent again to given user, None if it cannot receive it.
+    fn replay(&self, entry: &HistoryEntry, user: &UserData) -> Option<IRCMessage> {
+        let tagmsg = entry.command.as_slice() == "TAGMSG";
+        let with_tags = user.has_capability("message-tags");
+        if tagmsg && !with_tags { return None; }
+        let mut msg = IRCMessage {
+            tags: if with_tags { entry.tags.clone() } else { Vec::new() },
+            prefix: Some(entry.source.clone()),
+            command: entry.command.clone(),
+            args: vec!(entry.target.clone()),
+            suffix: if tagmsg { None } else { Some(entry.text.clone()) }
+        };
+        if user.has_capability("server-time") {
+            msg.set_tag("time", format_timestamp(entry.time).as_slice());
+        }
+        if with_tags {
+            msg.set_tag("msgid", entry.msgid.as_slice());
+        }
+        Some(msg)
+    }
+
+    /// Sends the entries of a history in a `chathistory` batch.
+    fn send_entries(&self, user: &UserData, target: &str, entries: &[&HistoryEntry], srv: &ServerData) {
+        let batch = user.start_batch(
+            srv.settings.read().name.as_slice(), "chathistory", vec!(target.to_string()), None
+        );
+        for entry in entries.iter() {
+            if let Some(msg) = self.replay(*entry, user) {
+                user.push_batched(&batch, msg);
+            }
+        }
+        user.end_batch(batch);
+    }
+
+    /// Handles the subcommands reading the history of a target.
+    fn send_history(&self, user: &UserData, sub: &str, args: &[String], srv: &ServerData) {
+        let needed = if sub == "BETWEEN" { 5 } else { 4 };
+        if args.len() < needed {
+            send_fail(user, "NEED_MORE_PARAMS", vec!(sub), "Missing parameters", srv);
+            return;
+        }
+        let target = args[1].as_slice();
+        let limit = match from_str::<uint>(args[needed - 1].as_slice()) {
+            Some(l) if l > 0 => min(l, self.max_limit),
+            _ => {
+                send_fail(user, "INVALID_PARAMS", vec!(sub), "Invalid limit", srv);
+                return;
+            }
+        };
+        let mut selectors = Vec::new();
+        for text in args.slice(2, needed - 1).iter() {
+            match parse_selector(text.as_slice()) {
+                // only LATEST accepts a wildcard
+                Some(Anything) if sub != "LATEST" => {},
+                Some(sel) => { selectors.push(sel); continue; },
+                None => {}
+            }
+            send_fail(user, "INVALID_PARAMS", vec!(sub, text.as_slice()), "Invalid message reference", srv);
+            return;
+        }
+        let (key, since) = match self.readable_key(user, target, srv) {
+            Some(k) => k,
+            None => {
+                send_fail(user, "INVALID_TARGET", vec!(sub, target), "Messages could not be retrieved", srv);
+                return;
+            }
+        };
+        let store = self.store.read();
+        let entries = store.entries_since(key.as_slice(), since);
+        let (start, end) = select_range(sub, entries.as_slice(), selectors.as_slice(), limit);
+        self.send_entries(user, target, entries.slice(start, end), srv);
+    }
+
+    /// Handles TARGETS, listing the targets with messages between two times.
+    fn send_targets(&self, user: &UserData, args: &[String], srv: &ServerData) {
+        if args.len() < 4 {
+            send_fail(user, "NEED_MORE_PARAMS", vec!("TARGETS"), "Missing parameters", srv);
+            return;
+        }
+        let (from, to) = match (parse_selector(args[1].as_slice()), parse_selector(args[2].as_slice())) {
+            (Some(Time(a)), Some(Time(b))) => if a <= b { (a, b) } else { (b, a) },
+            _ => {
+                send_fail(user, "INVALID_PARAMS", vec!("TARGETS"), "Invalid timestamps", srv);
+                return;
+            }
+        };
+        let limit = match from_str::<uint>(args[3].as_slice()) {
+            Some(l) if l > 0 => min(l, self.max_limit),
+            _ => {
+                send_fail(user, "INVALID_PARAMS", vec!("TARGETS"), "Invalid limit", srv);
+                return;
+            }
+        };
+        let own = label_to_lower(user.nickname.as_slice());
+        let nick_since = nick_since(user);
+        // only the chans the user is in
+        let names: Vec<String> = user.channels.read().keys().map(|c| c.clone()).collect();
+        let chans: HashMap<String, i64> = names.into_iter().filter_map(
+            |c| chan_since(c.as_slice(), srv).map(|since| (c, since))
+        ).collect();
+        let mut targets = Vec::new();
+        for (key, entries) in self.store.read().all().into_iter() {
+            let chan = is_chan(key.as_slice());
+            let since = if chan {
+                match chans.get(key) {
+                    Some(&since) => since,
+                    None => continue
+                }
+            } else {
+                nick_since
+            };
+            let last = match entries.iter().rev().find(|e| e.time >= from && e.time <= to && e.time >= since) {
+                Some(e) => e,
+                None => continue
+            };
+            let name = if chan {
+                last.target.clone()
+            } else {
+                if !key.as_slice().split(' ').any(|n| n == own.as_slice()) { continue; }
+                if label_to_lower(last.source_nick()) == own {
+                    last.target.clone()
+                } else {
+                    last.source_nick().to_string()
+                }
+            };
+            targets.push((last.time, name));
+        }
+        targets.sort();
+        targets.truncate(limit);
+        let srv_name = srv.settings.read().name.clone();
+        let batch = user.start_batch(srv_name.as_slice(), "draft/chathistory-targets", Vec::new(), None);
+        for &(time, ref name) in targets.iter() {
+            user.push_batched(&batch,
+                IRCMessage {
+                    tags: Vec::new(),
+                    prefix: Some(srv_name.clone()),
+                    command: "CHATHISTORY".to_string(),
+                    args: vec!("TARGETS".to_string(), name.clone(), format_timestamp(time)),
+                    suffix: None
+                }
+            );
+        }
+        user.end_batch(batch);
+    }
+}
+
+impl MessageSendingHandler for ModHistory {
+    fn handle_message_sending(&self, mut msg: TextMessage, srv: &ServerData) -> Option<TextMessage> {
+        // only the messages of users to chans or users are kept
+        let source = match msg.source {
+            User(ref id, _) => srv.users.read().get_user_by_uuid(id).map(|u| u.get_fullname()),
+            _ => None
+        };
+        let target = match msg.target {
+            Channel(ref chan) => Some(chan.clone()),
+            User(_, ref nick) => Some(nick.clone()),
+            _ => None
+        };
+        let (source, target) = match (source, target) {
+            (Some(s), Some(t)) => (s, t),
+            _ => return Some(msg)
+        };
+        let now = now_ms();
+        let entry = HistoryEntry {
+            msgid: Uuid::new_v4().to_simple_str(),
+            time: now,
+            command: msg.kind.command().to_string(),
+            source: source,
+            target: target,
+            tags: msg.tags.clone(),
+            text: msg.text.clone()
+        };
+        // the recipients see the same id as the replays
+        msg.tags.push(("msgid".to_string(), entry.msgid.clone()));
+        if let Err(e) = self.store.write().record(entry, now) {
+            srv.logger.log(Error, format!("(mod_history) Unable to write history file : {}", e));
+        }
+        if self.store.read().has_file() && self.compact_interval > 0
+        && !self.compaction_scheduled.swap(true, SeqCst) {
+            srv.timers.schedule_repeating("history", Duration::seconds(self.compact_interval as i64),
+                                          CompactHistory { store: self.store.clone() });
+        }
+        Some(msg)
+    }
+}
+
+impl EventHandler for ModHistory {
+    fn on_register(&self, user: &UserData, _: &Uuid, _: &ServerData) {
+        user.extensions.insert(NickSince { time: now_ms() });
+    }
+
+    fn on_nick_change(&self, user: &UserData, _: &str, _: &ServerData) {
+        user.extensions.insert(NickSince { time: now_ms() });
+    }
+
+    fn on_chan_destroy(&self, chan: &str, _: &ServerData) {
+        // a new chan of the same name must not see the messages of this one
+        self.store.write().forget(label_to_lower(chan).as_slice());
+    }
+}
+
+impl CommandHandler for ModHistory {
+    fn handle_command(&self, user: &UserData, _: &Uuid, cmd: &IRCMessage, srv: &ServerData)
+        -> (bool, RecyclingAction) {
+        let args = match cmd.as_nparams(1, 4) {
+            Some(args) => args,
+            None => {
+                send_fail(user, "NEED_MORE_PARAMS", vec!(), "Missing parameters", srv);
+                return (true, Nothing);
+            }
+        };
+        let args = args.as_slice();
+        let sub = args[0].as_slice().to_ascii_upper();
+        match sub.as_slice() {
+            "LATEST" | "BEFORE" | "AFTER" | "AROUND" | "BETWEEN" => {
+                self.send_history(user, sub.as_slice(), args, srv)
+            },
+            "TARGETS" => self.send_targets(user, args, srv),
+            _ => send_fail(user, "UNKNOWN_COMMAND", vec!(args[0].as_slice()), "Unknown subcommand", srv)
+        }
+        (true, Nothing)
+    }
+}
+
+impl UnloadHandler for ModHistory {
+    fn pre_unload(&self, reloading: bool, _: &ServerData) -> Option<String> {
+        // with a file, the new instance reads the history from it
+        if reloading && !self.store.read().has_file() {
+            Some(self.store.read().serialize())
+        } else {
+            None
+        }
+    }
+
+    fn restore_state(&self, state: String, _: &ServerData) {
+        self.store.write().restore(state.as_slice());
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use store::HistoryEntry;
+
+    use super::{Selector, Anything, Time, MsgId, parse_selector, index_before, index_after, select_range};
+
+    /// Entries m1 to m10, sent at times 10 to 100.
+    fn entries() -> Vec<HistoryEntry> {
+        range(1i64, 11).map(|i| HistoryEntry {
+            msgid: format!("m{}", i),
+            time: i * 10,
+            command: "PRIVMSG".to_string(),
+            source: "foo!user@host".to_string(),
+            target: "#chan".to_string(),
+            tags: Vec::new(),
+            text: "hi".to_string()
+        }).collect()
+    }
+
+    fn select(sub: &str, refs: &[&str], limit: uint) -> Vec<String> {
+        let entries = entries();
+        let entries: Vec<&HistoryEntry> = entries.iter().collect();
+        let selectors: Vec<Selector> = refs.iter().map(|r| parse_selector(*r).unwrap()).collect();
+        let (start, end) = select_range(sub, entries.as_slice(), selectors.as_slice(), limit);
+        entries.slice(start, end).iter().map(|e| e.msgid.clone()).collect()
+    }
+
+    fn ids(list: &[uint]) -> Vec<String> {
+        list.iter().map(|i| format!("m{}", i)).collect()
+    }
+
+    #[test]
+    fn test_parse_selector() {
+        assert!(match parse_selector("*") { Some(Anything) => true, _ => false });
+        assert!(match parse_selector("timestamp=1970-01-01T00:00:01.500Z") { Some(Time(1500)) => true, _ => false });
+        assert!(match parse_selector("msgid=abc") { Some(MsgId(ref id)) => id.as_slice() == "abc", _ => false });
+        assert!(parse_selector("msgid=").is_none());
+        assert!(parse_selector("timestamp=yesterday").is_none());
+        assert!(parse_selector("m1").is_none());
+    }
+
+    #[test]
+    fn test_indexes() {
+        let entries = entries();
+        let entries: Vec<&HistoryEntry> = entries.iter().collect();
+        let e = entries.as_slice();
+        assert_eq!(index_before(e, &Anything), Some(10));
+        assert_eq!(index_after(e, &Anything), Some(0));
+        assert_eq!(index_before(e, &Time(30)), Some(2));
+        assert_eq!(index_after(e, &Time(30)), Some(3));
+        assert_eq!(index_before(e, &Time(35)), Some(3));
+        assert_eq!(index_after(e, &Time(35)), Some(3));
+        assert_eq!(index_before(e, &Time(1000)), Some(10));
+        assert_eq!(index_before(e, &MsgId("m3".to_string())), Some(2));
+        assert_eq!(index_after(e, &MsgId("m3".to_string())), Some(3));
+        assert_eq!(index_before(e, &MsgId("m42".to_string())), None);
+        assert_eq!(index_after(e, &MsgId("m42".to_string())), None);
+    }
+
+    #[test]
+    fn test_select_range() {
+        assert_eq!(select("LATEST", &["*"], 3), ids(&[8, 9, 10]));
+        assert_eq!(select("LATEST", &["msgid=m9"], 3), ids(&[10]));
+        assert_eq!(select("BEFORE", &["msgid=m5"], 3), ids(&[2, 3, 4]));
+        assert_eq!(select("BEFORE", &["msgid=m2"], 3), ids(&[1]));
+        assert_eq!(select("AFTER", &["msgid=m5"], 3), ids(&[6, 7, 8]));
+        assert_eq!(select("AFTER", &["msgid=m9"], 3), ids(&[10]));
+        assert_eq!(select("AROUND", &["msgid=m5"], 4), ids(&[3, 4, 5, 6]));
+        assert_eq!(select("AROUND", &["msgid=m1"], 4), ids(&[1, 2, 3, 4]));
+        assert_eq!(select("AROUND", &["msgid=m10"], 4), ids(&[8, 9, 10]));
+        // BETWEEN takes the messages next to the first reference, in both orders
+        assert_eq!(select("BETWEEN", &["msgid=m2", "msgid=m8"], 3), ids(&[3, 4, 5]));
+        assert_eq!(select("BETWEEN", &["msgid=m8", "msgid=m2"], 3), ids(&[5, 6, 7]));
+        assert_eq!(select("BETWEEN", &["timestamp=1970-01-01T00:00:00.020Z",
+                                       "timestamp=1970-01-01T00:00:00.050Z"], 10), ids(&[3, 4]));
+        assert!(select("BETWEEN", &["msgid=m2", "msgid=m3"], 3).is_empty());
+        // an unknown msgid gives nothing
+        assert!(select("BEFORE", &["msgid=m42"], 3).is_empty());
+        assert!(select("BETWEEN", &["msgid=m2", "msgid=m42"], 3).is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+//! History module : keeps the messages sent to chans and users, and replays them
+//! with CHATHISTORY.
+//!
+//! Only the messages sent since a chan was created are replayed, and its history is
+//! dropped when it is destroyed, so a chan created again, after a restart as well,
+//! does not see the messages of the former one. The same goes for the queries, only
+//! replayed to the users holding their nicknames since. The history file thus keeps
+//! the messages over a reload of the module.
+
+#![feature(if_let, phase)]
+
+#[phase(plugin)] extern crate metallirc;
+extern crate metallirc;
+extern crate time;
+extern crate toml;
+extern crate uuid;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use metallirc::util::label_to_lower;
+
+use store::{HistoryStore, Retention, DEFAULT_MAX_MESSAGES};
+use chathistory::{ModHistory, NickSince, DEFAULT_COMPACT_INTERVAL, DEFAULT_MAX_LIMIT};
+
+// Public init()
+use metallirc::modules::{ModuleRegistrar, PRIORITY_NORMAL};
+use metallirc::logging::{Logger, Info, Warning};
+
+mod chathistory;
+mod store;
+
+/// Reads a non-negative integer setting of the module config.
+fn read_uint(conf: &toml::TomlTable, key: &str, default: uint, logger: &Logger) -> uint {
+    match conf.get(&key.to_string()) {
+        Some(&toml::Integer(i)) if i >= 0 => i as uint,
+        Some(_) => {
+            logger.log(Warning, format!("(mod_history) Invalid value for {}, using {}.", key, default));
+            default
+        },
+        None => default
+    }
+}
+
+/// Reads the retention limits given in the module config.
+fn parse_retention(conf: &toml::TomlTable, logger: &Logger) -> Retention {
+    let mut chan_limits = HashMap::new();
+    match conf.get(&"limits".to_string()) {
+        Some(&toml::Table(ref limits)) => for (chan, v) in limits.iter() {
+            match v {
+                &toml::Integer(i) if i >= 0 => { chan_limits.insert(label_to_lower(chan.as_slice()), i as uint); },
+                _ => logger.log(Warning, format!("(mod_history) Invalid history limit for {}.", chan))
+            }
+        },
+        Some(_) => logger.log(Warning,
+            "(mod_history) The limits should be defined in a [module.history.limits] section.".to_string()),
+        None => {}
+    }
+    Retention {
+        max_messages: read_uint(conf, "max_messages", DEFAULT_MAX_MESSAGES, logger),
+        chan_limits: chan_limits,
+        max_age: read_uint(conf, "max_age", 0, logger) as i64
+    }
+}
+
+declare_module!("history", "0.0.1", ["core"], init)
+
+fn init(reg: &mut ModuleRegistrar, conf: &toml::TomlTable, logger: &Logger) {
+    let file = match conf.get(&"file".to_string()) {
+        Some(&toml::String(ref path)) => match from_str::<Path>(path.as_slice()) {
+            Some(p) => Some(p),
+            None => {
+                logger.log(Warning, format!("(mod_history) Invalid path for history file: {}.", path));
+                None
+            }
+        },
+        Some(_) => {
+            logger.log(Warning, "(mod_history) Invalid history file path.".to_string());
+            None
+        },
+        None => None
+    };
+    let mut store = HistoryStore::new(parse_retention(conf, logger), file);
+    let now = time::get_time();
+    match store.load(now.sec * 1000 + (now.nsec / 1000000) as i64) {
+        Ok((count, invalid)) => {
+            for line in invalid.iter() {
+                logger.log(Warning, format!("(mod_history) Skipped invalid line {} of the history file.", line));
+            }
+            logger.log(Info, format!("(mod_history) {} messages were loaded.", count))
+        },
+        Err(e) => logger.log(Warning, format!("(mod_history) {}", e))
+    }
+    let max_limit = read_uint(conf, "max_limit", DEFAULT_MAX_LIMIT, logger);
+    let compact_interval = read_uint(conf, "compact_interval", DEFAULT_COMPACT_INTERVAL, logger);
+    let history = Arc::new(ModHistory::new(store, max_limit, compact_interval));
+    reg.add_serializable_extension::<NickSince>("NickSince");
+    reg.add_capability("draft/chathistory");
+    reg.add_isupport_token("CHATHISTORY", Some(max_limit.to_string().as_slice()));
+    reg.add_message_handler("ModHistory", PRIORITY_NORMAL, history.clone());
+    reg.add_command_handler("ModHistory", &["CHATHISTORY"], PRIORITY_NORMAL, history.clone());
+    reg.add_event_handler("ModHistory", PRIORITY_NORMAL, history.clone());
+    reg.add_unload_handler("ModHistory", history);
+}
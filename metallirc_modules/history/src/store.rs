@@ -0,0 +1,380 @@
+//! Storage of the messages, in memory and optionally in a file.
+
+#![experimental]
+
+use metallirc::messages::{format_tags, parse_tags};
+use metallirc::util::label_to_lower;
+
+use std::collections::{Deque, HashMap, RingBuf};
+use std::io::{File, BufferedReader, IoResult, Append, Truncate, Write, InvalidInput};
+
+/// Default maximum number of messages kept for each target.
+pub const DEFAULT_MAX_MESSAGES: uint = 500;
+
+/// A recorded message.
+#[deriving(Clone)]
+pub struct HistoryEntry {
+    pub msgid: String,
+    /// Time of the message, in milliseconds since the epoch.
+    pub time: i64,
+    /// PRIVMSG, NOTICE or TAGMSG.
+    pub command: String,
+    /// Full name of the sender.
+    pub source: String,
+    /// The chan or nickname the message was sent to, as given.
+    pub target: String,
+    /// The client-only tags of the message.
+    pub tags: Vec<(String, String)>,
+    pub text: String
+}
+
+impl HistoryEntry {
+    /// Reads an entry from a line of the history file.
+    fn from_line(line: &str) -> Option<HistoryEntry> {
+        // the text is last as it can contain tabulations
+        let fields: Vec<&str> = line.splitn(6, '\t').collect();
+        if fields.len() != 7 { return None; }
+        Some(HistoryEntry {
+            msgid: fields[0].to_string(),
+            time: match from_str(fields[1]) { Some(t) => t, None => return None },
+            command: fields[2].to_string(),
+            source: fields[3].to_string(),
+            target: fields[4].to_string(),
+            tags: parse_tags(fields[5]),
+            text: fields[6].to_string()
+        })
+    }
+
+    /// Writes the entry as a line of the history file.
+    fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\t{}\t{}\t{}\t{}", self.msgid, self.time, self.command, self.source, self.target,
+                format_tags(self.tags.as_slice()), self.text)
+    }
+
+    /// Nickname of the sender.
+    pub fn source_nick(&self) -> &str {
+        self.source.as_slice().split('!').next().unwrap_or("")
+    }
+
+    /// The key of the history this entry belongs to.
+    pub fn key(&self) -> String {
+        if is_chan(self.target.as_slice()) {
+            label_to_lower(self.target.as_slice())
+        } else {
+            query_key(self.source_nick(), self.target.as_slice())
+        }
+    }
+}
+
+/// Whether given target is a chan.
+pub fn is_chan(target: &str) -> bool {
+    target.starts_with("#") || target.starts_with("&")
+}
+
+/// The key of the history of the queries between two nicknames.
+pub fn query_key(nick1: &str, nick2: &str) -> String {
+    let (a, b) = (label_to_lower(nick1), label_to_lower(nick2));
+    if a < b { format!("{} {}", a, b) } else { format!("{} {}", b, a) }
+}
+
+/// The retention limits of the history.
+pub struct Retention {
+    /// Maximum number of messages kept for each target.
+    pub max_messages: uint,
+    /// Maximum number of messages of some chans, by lowercase name.
+    pub chan_limits: HashMap<String, uint>,
+    /// Maximum age of the messages in seconds, 0 for no limit.
+    pub max_age: i64
+}
+
+impl Retention {
+    fn limit_of(&self, key: &str) -> uint {
+        match self.chan_limits.get(&key.to_string()) {
+            Some(&l) => l,
+            None => self.max_messages
+        }
+    }
+}
+
+/// The recorded messages, by chan or pair of nicknames.
+pub struct HistoryStore {
+    /// Entries by key, the oldest first.
+    buffers: HashMap<String, RingBuf<HistoryEntry>>,
+    retention: Retention,
+    file: Option<Path>,
+    /// The history file opened to append the messages, reopened after a rewrite.
+    append: Option<File>
+}
+
+impl HistoryStore {
+    pub fn new(retention: Retention, file: Option<Path>) -> HistoryStore {
+        HistoryStore {
+            buffers: HashMap::new(),
+            retention: retention,
+            file: file,
+            append: None
+        }
+    }
+
+    /// Loads the entries of the history file, then rewrites it without the expired ones.
+    /// A missing file is not an error. Returns the number of entries kept, and the numbers
+    /// of the invalid lines, which are skipped (the last one may have been cut by a crash).
+    pub fn load(&mut self, now: i64) -> Result<(uint, Vec<uint>), String> {
+        let path = match self.file {
+            Some(ref p) if p.exists() => p.clone(),
+            _ => return Ok((0, Vec::new()))
+        };
+        let mut reader = match File::open(&path) {
+            Ok(f) => BufferedReader::new(f),
+            Err(e) => return Err(format!("Unable to open history file {} : {}", path.display(), e))
+        };
+        let mut invalid = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            match line {
+                Ok(l) => match HistoryEntry::from_line(l.as_slice().trim_right_chars('\n')) {
+                    Some(entry) => self.insert(entry),
+                    None => invalid.push(i + 1)
+                },
+                // not UTF-8
+                Err(ref e) if e.kind == InvalidInput => invalid.push(i + 1),
+                Err(e) => return Err(format!("Unable to read history file {} : {}", path.display(), e))
+            }
+        }
+        match self.compact(now) {
+            Ok(()) => Ok((self.len(), invalid)),
+            Err(e) => Err(format!("Unable to write history file {} : {}", path.display(), e))
+        }
+    }
+
+    /// Drops the expired entries, and rewrites the history file with the ones left, as
+    /// it only grows otherwise.
+    pub fn compact(&mut self, now: i64) -> IoResult<()> {
+        self.expire(now);
+        self.rewrite()
+    }
+
+    /// Drops the history of given key. It is left in the history file until the next
+    /// compaction.
+    pub fn forget(&mut self, key: &str) {
+        self.buffers.remove(&key.to_string());
+    }
+
+    /// Rewrites the history file with the entries in memory.
+    fn rewrite(&mut self) -> IoResult<()> {
+        // the append handle would write after the end of the new file
+        self.append = None;
+        if let Some(ref path) = self.file {
+            let mut file = try!(File::open_mode(path, Truncate, Write));
+            for buf in self.buffers.values() {
+                for entry in buf.iter() {
+                    try!(file.write_line(entry.to_line().as_slice()));
+                }
+            }
+            try!(file.datasync());
+        }
+        Ok(())
+    }
+
+    /// Adds an entry in memory, applying the count limit of its target.
+    fn insert(&mut self, entry: HistoryEntry) {
+        let key = entry.key();
+        let limit = self.retention.limit_of(key.as_slice());
+        if limit == 0 { return; }
+        if let Some(buf) = self.buffers.get_mut(&key) {
+            buf.push_back(entry);
+            while buf.len() > limit {
+                buf.pop_front();
+            }
+            return;
+        }
+        let mut buf = RingBuf::new();
+        buf.push_back(entry);
+        self.buffers.insert(key, buf);
+    }
+
+    /// Records a message, appending it to the history file if any.
+    pub fn record(&mut self, entry: HistoryEntry, now: i64) -> IoResult<()> {
+        let line = entry.to_line();
+        self.insert(entry);
+        self.expire(now);
+        if self.append.is_none() {
+            if let Some(ref path) = self.file {
+                self.append = Some(try!(File::open_mode(path, Append, Write)));
+            }
+        }
+        if let Some(ref mut file) = self.append {
+            try!(file.write_line(line.as_slice()));
+        }
+        Ok(())
+    }
+
+    /// Drops the entries older than the maximum age.
+    fn expire(&mut self, now: i64) {
+        if self.retention.max_age <= 0 { return; }
+        let oldest = now - self.retention.max_age * 1000;
+        for (_, buf) in self.buffers.iter_mut() {
+            while buf.front().map_or(false, |e| e.time < oldest) {
+                buf.pop_front();
+            }
+        }
+        let empty: Vec<String> = self.buffers.iter().filter(|&(_, b)| b.is_empty())
+                                                    .map(|(k, _)| k.clone()).collect();
+        for k in empty.iter() {
+            self.buffers.remove(k);
+        }
+    }
+
+    /// Number of entries in memory.
+    pub fn len(&self) -> uint {
+        self.buffers.values().fold(0, |n, b| n + b.len())
+    }
+
+    /// Whether the history is kept in a file.
+    pub fn has_file(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// The entries of given key sent since given time, the oldest first.
+    pub fn entries_since(&self, key: &str, since: i64) -> Vec<&HistoryEntry> {
+        match self.buffers.get(&key.to_string()) {
+            Some(buf) => buf.iter().skip_while(|e| e.time < since).collect(),
+            None => Vec::new()
+        }
+    }
+
+    /// The keys of the histories, with their entries.
+    pub fn all(&self) -> Vec<(&String, &RingBuf<HistoryEntry>)> {
+        self.buffers.iter().collect()
+    }
+
+    /// All the entries as lines, to hand them off to a new instance of the module.
+    pub fn serialize(&self) -> String {
+        let mut lines = Vec::new();
+        for buf in self.buffers.values() {
+            for entry in buf.iter() {
+                lines.push(entry.to_line());
+            }
+        }
+        lines.connect("\n")
+    }
+
+    /// Restores the entries serialized by `serialize`.
+    pub fn restore(&mut self, data: &str) {
+        for line in data.lines() {
+            if let Some(entry) = HistoryEntry::from_line(line) {
+                self.insert(entry);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::HashMap;
+    use std::io::{File, TempDir};
+
+    use super::{HistoryEntry, HistoryStore, Retention};
+
+    fn entry(id: &str, time: i64, source: &str, target: &str) -> HistoryEntry {
+        HistoryEntry {
+            msgid: id.to_string(),
+            time: time,
+            command: "PRIVMSG".to_string(),
+            source: format!("{}!user@host", source),
+            target: target.to_string(),
+            tags: Vec::new(),
+            text: format!("message {}", id)
+        }
+    }
+
+    fn retention(max_messages: uint, max_age: i64) -> Retention {
+        Retention { max_messages: max_messages, chan_limits: HashMap::new(), max_age: max_age }
+    }
+
+    fn ids(entries: Vec<&HistoryEntry>) -> Vec<String> {
+        entries.iter().map(|e| e.msgid.clone()).collect()
+    }
+
+    #[test]
+    fn test_line_roundtrip() {
+        let mut e = entry("a1", 1414000000123, "foo", "#chan");
+        e.tags = vec!(("+draft/react".to_string(), "lol".to_string()));
+        e.text = "with\ttabs\tin it".to_string();
+        let parsed = HistoryEntry::from_line(e.to_line().as_slice()).unwrap();
+        assert_eq!(parsed.msgid.as_slice(), "a1");
+        assert_eq!(parsed.time, 1414000000123);
+        assert_eq!(parsed.command.as_slice(), "PRIVMSG");
+        assert_eq!(parsed.source.as_slice(), "foo!user@host");
+        assert_eq!(parsed.target.as_slice(), "#chan");
+        assert_eq!(parsed.tags, e.tags);
+        assert_eq!(parsed.text.as_slice(), "with\ttabs\tin it");
+        assert!(HistoryEntry::from_line("a1\tnot a time\tPRIVMSG\tfoo\t#chan\t\ttext").is_none());
+        assert!(HistoryEntry::from_line("a1\t1414000000123\tPRIVMSG").is_none());
+    }
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(entry("a", 0, "foo", "#Chan").key().as_slice(), "#chan");
+        assert_eq!(entry("a", 0, "Foo", "bar").key(), entry("b", 0, "bar", "foo").key());
+    }
+
+    #[test]
+    fn test_count_limits() {
+        let mut r = retention(3, 0);
+        r.chan_limits.insert("#small".to_string(), 1);
+        r.chan_limits.insert("#none".to_string(), 0);
+        let mut store = HistoryStore::new(r, None);
+        for i in range(0i64, 5) {
+            store.record(entry(format!("c{}", i).as_slice(), i, "foo", "#chan"), i).unwrap();
+            store.record(entry(format!("s{}", i).as_slice(), i, "foo", "#Small"), i).unwrap();
+            store.record(entry(format!("n{}", i).as_slice(), i, "foo", "#none"), i).unwrap();
+        }
+        assert_eq!(ids(store.entries_since("#chan", 0)), vec!("c2".to_string(), "c3".to_string(), "c4".to_string()));
+        assert_eq!(ids(store.entries_since("#small", 0)), vec!("s4".to_string()));
+        assert!(store.entries_since("#none", 0).is_empty());
+        assert_eq!(ids(store.entries_since("#chan", 4)), vec!("c4".to_string()));
+        assert_eq!(store.len(), 4);
+    }
+
+    #[test]
+    fn test_max_age() {
+        let mut store = HistoryStore::new(retention(10, 10), None);
+        store.record(entry("old", 1000, "foo", "#chan"), 1000).unwrap();
+        store.record(entry("other", 2000, "foo", "bar"), 2000).unwrap();
+        store.record(entry("new", 9000, "foo", "#chan"), 9000).unwrap();
+        store.compact(12000).unwrap();
+        assert_eq!(ids(store.entries_since("#chan", 0)), vec!("new".to_string()));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_file() {
+        let dir = TempDir::new("mod_history").unwrap();
+        let path = dir.path().join("history");
+        {
+            let mut file = File::create(&path).unwrap();
+            file.write_line(entry("a", 1000, "foo", "#chan").to_line().as_slice()).unwrap();
+            file.write_line("garbage").unwrap();
+            file.write_line(entry("b", 2000, "foo", "#other").to_line().as_slice()).unwrap();
+            // cut by a crash
+            file.write_str("c\t3000\tPRIV").unwrap();
+        }
+        let mut store = HistoryStore::new(retention(10, 0), Some(path.clone()));
+        assert_eq!(store.load(4000), Ok((2, vec!(2, 4))));
+        store.record(entry("d", 5000, "foo", "#chan"), 5000).unwrap();
+        store.forget("#other");
+        // left in the file until the next compaction
+        let mut reloaded = HistoryStore::new(retention(10, 0), Some(path.clone()));
+        assert_eq!(reloaded.load(6000), Ok((3, vec!())));
+        store.compact(6000).unwrap();
+        store.record(entry("e", 7000, "foo", "#chan"), 7000).unwrap();
+        let mut reloaded = HistoryStore::new(retention(10, 0), Some(path.clone()));
+        assert_eq!(reloaded.load(8000), Ok((3, vec!())));
+        assert_eq!(ids(reloaded.entries_since("#chan", 0)), vec!("a".to_string(), "d".to_string(), "e".to_string()));
+        // the file only keeps the entries in memory
+        let mut expiring = HistoryStore::new(retention(10, 3), Some(path.clone()));
+        assert_eq!(expiring.load(8000), Ok((2, vec!())));
+        assert_eq!(File::open(&path).read_to_string().unwrap().as_slice().lines().count(), 2);
+    }
+}
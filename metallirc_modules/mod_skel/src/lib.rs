@@ -16,25 +16,20 @@ use metallirc::modules::{RecyclingAction, Nothing};
 use metallirc::modules::CommandHandler;
 
 // Public init()
-use metallirc::modules::Module;
+use metallirc::modules::{ModuleRegistrar, PRIORITY_NORMAL};
 use metallirc::logging::Logger;
 
 pub struct ModSkel;
 
-module!(ModSkel is CommandHandler)
-
 impl CommandHandler for ModSkel {
-    fn handle_command(&self, _: &UserData, _: &Uuid, cmd: &IRCMessage, _: &ServerData)
+    fn handle_command(&self, _: &UserData, _: &Uuid, _: &IRCMessage, _: &ServerData)
         -> (bool, RecyclingAction) {
-        if cmd.command.as_slice() != "SKEL" { return (false, Nothing); }
-
         (true, Nothing)
     }
 }
 
-#[no_mangle]
-pub fn init(_: &toml::TomlTable, _: &Logger) -> Vec<Box<Module + 'static + Send + Sync>> {
-    init_modules!(
-        ModSkel
-    )
-}
\ No newline at end of file
+declare_module!("skel", "0.0.1", ["core"], init)
+
+fn init(reg: &mut ModuleRegistrar, _: &toml::TomlTable, _: &Logger) {
+    reg.add_command_handler("ModSkel", &["SKEL"], PRIORITY_NORMAL, ModSkel);
+}
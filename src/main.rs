@@ -80,16 +80,7 @@ fn main() {
 
     let srv_data = ServerData::new(serverconfig);
 
-    if let Some(config) = srv_data.settings.read().modules.get(&"core".to_string()) {
-        // core module must always be loaded first
-        srv_data.modules_handler.write().open_module("core", config, &srv_data.logger);
-    }
-
-    for (name, config) in srv_data.settings.read().modules.iter() {
-        if name.as_slice() != "core" {
-            srv_data.modules_handler.write().open_module(name.as_slice(), config, &srv_data.logger);
-        }
-    }
+    srv_data.modules_handler.write().open_modules(&srv_data.settings.read().modules, &srv_data.logger);
 
     scheduling::run_server(srv_data, acceptor);
 
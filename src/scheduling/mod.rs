@@ -10,8 +10,10 @@
 //!   commands and sending them all message they should receive.
 //! - A recycler thread putting clients back in the loop after their have been handled.
 //!   Also does "eavy" operations on users requiring `&mut` acces to the usermanager
-//!   (currently nickname changing and user deletion).
+//!   (currently nickname changing, user deletion and module loading).
 //! - A logger thread handling server logging system.
+//! - A thread reloading the configuration on SIGHUP.
+//! - A scheduler thread running the timers of the server and its modules.
 
 #![experimental]
 
@@ -20,6 +22,7 @@ use metallirc::ServerData;
 
 use std::io::net::tcp::TcpAcceptor;
 use std::sync::{Arc, deque};
+use std::time::duration::Duration;
 
 use uuid::Uuid;
 
@@ -64,6 +67,17 @@ pub fn run_server(srv: ServerData, acceptor: TcpAcceptor) {
         procs::spawn_logger(arc_srv.clone())
     );
 
+    // SIGHUP catcher
+    thread_handles.push(
+        procs::spawn_signal_handler(arc_srv.clone())
+    );
+
+    // timers
+    arc_srv.timers.schedule_repeating("server", Duration::minutes(1), procs::ExpireBans);
+    thread_handles.push(
+        procs::spawn_scheduler(arc_srv.clone())
+    );
+
     //
     // CLEANUP & JOINING
     //
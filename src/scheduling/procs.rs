@@ -5,22 +5,28 @@ use std::collections::DList;
 use std::io::{Acceptor, BufferedStream};
 use std::io::{File, Append, Write};
 use std::io::net::tcp::TcpAcceptor;
+use std::io::signal::{HangUp, Listener as SignalListener};
 use std::io::timer::sleep;
 use std::rt::thread::Thread;
 use std::sync::{Arc, Future};
+use std::sync::atomic::SeqCst;
 use std::sync::deque;
 use std::sync::deque::{Stealer, Worker};
 use std::task::TaskBuilder;
 use std::time::duration::Duration;
 
-use metallirc::messages::numericreply;
+use metallirc::bans::{DLine, KLine};
 use metallirc::modules;
 use metallirc::modules::RecyclingAction;
+use metallirc::timers::TimedTask;
+
+use time::get_time;
 
 use uuid::Uuid;
 
-use metallirc::logging::Debug;
+use metallirc::logging::{Debug, Error, Info, Warning};
 use metallirc::users;
+use metallirc::welcome;
 use metallirc::ServerData;
 
 use super::users_handling::{handle_user, destroy_user, recycle_user, disconnect_user};
@@ -45,7 +51,17 @@ pub fn spawn_newclients_handler(srv: Arc<ServerData>,
                     Ok(mut socket) => {
                         // prepare the new connection
                         socket.set_timeout(Some(0));
-                        inc_list.push_back(users::NewUser::new(BufferedStream::new(socket)));
+                        let host = socket.peer_name().map(|a| a.ip.to_string())
+                                                     .unwrap_or("unknown".to_string());
+                        let port = socket.socket_name().map(|a| a.port).unwrap_or(0);
+                        let mut nu = users::NewUser::new(BufferedStream::new(socket), host, port);
+                        let dline = srv.bans.read().find(DLine, nu.hostname.as_slice(), get_time().sec)
+                                                   .map(|b| b.reason.clone());
+                        if let Some(reason) = dline {
+                            nu.reject(format!("D-lined: {}", reason).as_slice());
+                        } else {
+                            inc_list.push_back(nu);
+                        }
                     },
                     // TODO : handle errors other than timeout
                     Err(_) => {}
@@ -53,24 +69,47 @@ pub fn spawn_newclients_handler(srv: Arc<ServerData>,
                 // loop once over the new connections
                 // TODO : timeout for initial negociation
                 let mut not_finished = DList::new();
+                let caps = srv.modules_handler.read().supported_capabilities();
                 for mut u in inc_list.into_iter() {
-                    u.step_negociate(&*srv.settings.read());
+                    u.step_negociate(&*srv.settings.read(), &*srv.bans.read(), caps.as_slice());
+                    if u.zombie {
+                        // connection is lost, drop it
+                        continue;
+                    }
                     if u.is_ready() {
-                        let mut manager_handle = srv.users.write();
-                        match manager_handle.insert(u) {
+                        let kline = srv.bans.read().find(KLine, u.get_userhost().as_slice(), get_time().sec)
+                                                   .map(|b| b.reason.clone());
+                        if let Some(reason) = kline {
+                            u.reject(format!("K-lined: {}", reason).as_slice());
+                            continue;
+                        }
+                        let class = srv.settings.read().find_class(
+                            u.port, u.get_userhost().as_slice(), u.account.as_ref(), u.password.as_ref()
+                        ).map(|c| (c.name.clone(), c.max_clients));
+                        let class = match class {
+                            Some((name, max)) => {
+                                if srv.users.read().count_in_class(name.as_slice()) >= max {
+                                    u.reject("Too many connections in your class");
+                                    continue;
+                                }
+                                name
+                            },
+                            None => {
+                                u.reject("No connection class for you");
+                                continue;
+                            }
+                        };
+                        let inserted = srv.users.write().insert(u, class);
+                        match inserted {
                             Ok(id) => {
                                 // user was successfully inserted
+                                let manager_handle = srv.users.read();
                                 let my_user = manager_handle.get_user_by_uuid(&id).unwrap();
                                 // welcome the new user
-                                my_user.push_numreply(
-                                    numericreply::RPL_WELCOME(
-                                        "metallirc IRC Network",
-                                        my_user.get_fullname().as_slice()
-                                    ),
-                                    srv.settings.read().name.as_slice()
-                                );
+                                welcome::send_welcome(&*my_user, &*srv);
                                 srv.logger.log(Debug,
                                     format!("New user {} with UUID {}.", my_user.get_fullname(), id));
+                                srv.modules_handler.read().notify_register(&*my_user, &id, &*srv);
                                 to_recycle_sender.send((id, modules::Nothing));
                             },
                             Err(mut nu) => {
@@ -84,6 +123,7 @@ pub fn spawn_newclients_handler(srv: Arc<ServerData>,
                     }
                 }
                 inc_list = not_finished;
+                srv.unregistered.store(inc_list.len(), SeqCst);
             }
         }
     })
@@ -143,7 +183,9 @@ pub fn spawn_clients_recycler(srv: Arc<ServerData>, recycled_worker: deque::Work
                                 for chan in chans.into_iter() {
                                     let empty = srv.channels.read().chan_handle(chan.as_slice())
                                                             .unwrap().write().cleanup();
-                                    if empty { srv.channels.write().destroy_if_empty(chan.as_slice()); }
+                                    if empty && srv.channels.write().destroy_if_empty(chan.as_slice()) {
+                                        srv.modules_handler.read().notify_chan_destroy(chan.as_slice(), &*srv);
+                                    }
                                 }
                             } else if action == modules::Nothing {
                                 recycled_worker.push(id);
@@ -193,4 +235,70 @@ TaskBuilder::new().named("Logger").try_future({
             }
         }
     })
-}
\ No newline at end of file
+}
+
+/// Spawns the thread reloading the configuration when the server receives SIGHUP.
+#[experimental]
+pub fn spawn_signal_handler(srv: Arc<ServerData>) -> Future<Result<(), Box<Any + Send>>> {
+    TaskBuilder::new().named("Signal Handler").try_future({
+        proc() {
+            let mut listener = SignalListener::new();
+            if let Err(e) = listener.register(HangUp) {
+                srv.logger.log(Warning, format!("Unable to catch SIGHUP, rehash on signal disabled : {}", e));
+                return
+            }
+            loop {
+                match listener.rx.try_recv() {
+                    Ok(HangUp) => {
+                        srv.logger.log(Info, "SIGHUP received, reloading configuration.".to_string());
+                        match srv.rehash() {
+                            Ok(warnings) => for w in warnings.into_iter() {
+                                srv.logger.log(Warning, format!("Rehash: {}", w));
+                            },
+                            Err(e) => srv.logger.log(Error, format!("Rehash failed: {}", e))
+                        }
+                    },
+                    _ => {
+                        if *srv.signal_shutdown.read() { return; }
+                        sleep(Duration::milliseconds(200));
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Spawns the thread running the timers.
+#[experimental]
+pub fn spawn_scheduler(srv: Arc<ServerData>) -> Future<Result<(), Box<Any + Send>>> {
+    TaskBuilder::new().named("Scheduler").try_future({
+        proc() {
+            loop {
+                if *srv.signal_shutdown.read() {
+                    srv.timers.clear();
+                    return;
+                }
+                srv.timers.run_due(&*srv);
+                sleep(Duration::milliseconds(100));
+                Thread::yield_now();
+            }
+        }
+    })
+}
+
+/// Removes the expired bans from the ban list and the ban file.
+pub struct ExpireBans;
+
+impl TimedTask for ExpireBans {
+    fn run(&self, srv: &ServerData) {
+        let mut bans = srv.bans.write();
+        let expired = bans.expire(get_time().sec);
+        if expired.is_empty() { return; }
+        for old in expired.iter() {
+            srv.logger.log(Info, format!("{} for {} expired.", old.kind.name(), old.mask));
+        }
+        if let Err(e) = bans.save() {
+            srv.logger.log(Error, format!("Unable to save bans : {}", e));
+        }
+    }
+}
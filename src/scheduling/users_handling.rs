@@ -1,22 +1,34 @@
 use metallirc::ServerData;
+use metallirc::users::{UserData, PrivateUserDataHandler, Alive, PingNeeded, PingTimeout, WhowasEntry};
 
-use metallirc::modules::{RecyclingAction, Nothing, Zombify, ChangeNick};
+use metallirc::modules::{RecyclingAction, Nothing, Zombify, ChangeNick, ChangeHost, Kill};
+use metallirc::modules::{LoadModule, UnloadModule, ReloadModule};
 
-use metallirc::logging::Debug;
+use metallirc::logging::{Debug, Info};
 
 use std::io;
 
 use metallirc::messages::{IRCMessage, numericreply};
 
+use time::{get_time, precise_time_ns};
+
 use uuid::Uuid;
 
 /// Handles a user, sending and receiving awaiting messages
 #[experimental]
 pub fn handle_user(id: &Uuid, srv: &ServerData) -> RecyclingAction {
-    // first, send its messages to the user
     let manager = srv.users.read();
     let u = &*manager.get_user_by_uuid(id).unwrap();
+    // it may have been killed in the meantime
+    if u.is_zombie() { return Nothing; }
+    let class = srv.settings.read().get_class(u.class.as_slice()).clone();
     let mut pu = u.private_handler();
+
+    // first, send its messages to the user
+    if u.queue_size() > class.sendq {
+        close_connection(u, &mut pu, "SendQ exceeded");
+        return Nothing;
+    }
     while match pu.next_queued_message() {
         Some(msg) => match pu.socket_write_message(msg) {
             Ok(()) => true,
@@ -25,11 +37,52 @@ pub fn handle_user(id: &Uuid, srv: &ServerData) -> RecyclingAction {
         None => false
     } {}
 
-    while match pu.socket_read_message() {
-        Ok(msg) => match srv.modules_handler.read().handle_command(u, id, msg, srv) {
+    // is the user still there ?
+    match pu.input().check_ping(get_time().sec, class.ping_frequency) {
+        Alive => {},
+        PingNeeded => u.push_message(
+            IRCMessage {
+                tags: Vec::new(),
+                prefix: None,
+                command: "PING".to_string(),
+                args: vec!(),
+                suffix: Some(srv.settings.read().name.clone())
+            }
+        ),
+        PingTimeout => {
+            close_connection(u, &mut pu, "Ping timeout");
+            return Nothing;
+        }
+    }
+
+    // handle the commands held back by flood control
+    pu.input().refill(class.flood_burst, class.flood_rate, precise_time_ns() / 1000000);
+    while let Some(msg) = pu.input().pop_pending() {
+        match run_command(u, id, msg, srv) {
             Zombify => { pu.zombify(); return Nothing; },
-            Nothing => true,
+            Nothing => {},
             act => { return act; }
+        }
+    }
+
+    while match pu.socket_read_message() {
+        Ok(msg) => {
+            pu.input().mark_activity(get_time().sec);
+            if pu.input().has_pending() || !pu.input().take_token() {
+                // flooding, keep it for later
+                pu.input().push_pending(msg);
+                if pu.input().pending_size() > class.recvq {
+                    close_connection(u, &mut pu, "Excess Flood");
+                    return Nothing;
+                }
+                true
+            } else {
+                match run_command(u, id, msg, srv) {
+                    Zombify => { pu.zombify(); return Nothing; },
+                    Nothing => true,
+                    act => { return act; }
+                }
+            }
         },
         // TODO proper error handling
         Err(e) => {
@@ -39,6 +92,7 @@ pub fn handle_user(id: &Uuid, srv: &ServerData) -> RecyclingAction {
                 // connection error, zombify
                 u.send_to_known(
                     IRCMessage {
+                        tags: Vec::new(),
                         prefix: Some(u.get_fullname()),
                         command: "QUIT".to_string(),
                         args: vec!(),
@@ -55,6 +109,49 @@ pub fn handle_user(id: &Uuid, srv: &ServerData) -> RecyclingAction {
     return Nothing;
 }
 
+/// Handles a command of a user. If the client gave it a label and enabled `labeled-response`,
+/// the replies sent while handling it are gathered in a labeled response, which is ended
+/// by `recycle_user` if the command returns an action.
+#[experimental]
+fn run_command(u: &UserData, id: &Uuid, msg: IRCMessage, srv: &ServerData) -> RecyclingAction {
+    let label = msg.tag("label").map(|l| l.to_string());
+    match label {
+        Some(ref l) if l.len() > 0 && u.has_capability("labeled-response") => u.start_labeled_response(l.as_slice()),
+        _ => return srv.modules_handler.read().handle_command(u, id, msg, srv)
+    }
+    let action = srv.modules_handler.read().handle_command(u, id, msg, srv);
+    match action {
+        Nothing | Zombify => u.end_labeled_response(srv.settings.read().name.as_slice()),
+        _ => u.suspend_labeled_response()
+    }
+    action
+}
+
+/// Closes the connection of a user for given reason, notifying it and the users knowing it.
+#[experimental]
+fn close_connection(u: &UserData, pu: &mut PrivateUserDataHandler, reason: &str) {
+    u.send_to_known(
+        IRCMessage {
+            tags: Vec::new(),
+            prefix: Some(u.get_fullname()),
+            command: "QUIT".to_string(),
+            args: vec!(),
+            suffix: Some(reason.to_string())
+        }
+    );
+    // we don't care about the result, it will be disconnected anyway.
+    let _ = pu.socket_write_message(
+        IRCMessage {
+            tags: Vec::new(),
+            prefix: None,
+            command: "ERROR".to_string(),
+            args: vec!(),
+            suffix: Some(format!("Closing Link: {} ({})", u.hostname, reason))
+        }
+    );
+    pu.zombify();
+}
+
 /// Forcibly disconnect the user for a server shutdown.
 #[experimental]
 pub fn disconnect_user(id: &Uuid, srv: &ServerData, reason: &str) {
@@ -65,6 +162,7 @@ pub fn disconnect_user(id: &Uuid, srv: &ServerData, reason: &str) {
     // we don't care about the result, it will be disconnected anyway.
     let _ = pu.socket_write_message(
         IRCMessage {
+            tags: Vec::new(),
             prefix: Some(srv.settings.read().name.clone()),
             command: "NOTICE".to_string(),
             args: vec!(zombie_nickname),
@@ -74,23 +172,42 @@ pub fn disconnect_user(id: &Uuid, srv: &ServerData, reason: &str) {
     pu.zombify();
 }
 
-/// Perform a recycling action on given user.
+/// Perform a recycling action on given user. If it was returned by a labeled command,
+/// the replies sent by the action are part of the response, which is ended here.
 #[experimental]
 pub fn recycle_user(id: &Uuid, action: RecyclingAction, srv: &ServerData) {
+    if let Some(u) = srv.users.read().get_user_by_uuid(id) {
+        u.resume_labeled_response();
+    }
+    run_action(id, action, srv);
+    if let Some(u) = srv.users.read().get_user_by_uuid(id) {
+        u.end_labeled_response(srv.settings.read().name.as_slice());
+    }
+}
+
+fn run_action(id: &Uuid, action: RecyclingAction, srv: &ServerData) {
     match action {
         ChangeNick(new_nick) => {
-            let (success, old_name) = {
+            let (success, old_name, old_nick, record) = {
                 let mut manager = srv.users.write();
-                let old_name = manager.get_user_by_uuid(id).unwrap().get_fullname();
-                (manager.change_nick(id, &new_nick), old_name)
+                let (old_name, old_nick, record) = {
+                    let user = manager.get_user_by_uuid(id).unwrap();
+                    (user.get_fullname(), user.nickname.clone(), whowas_entry(&*user, srv))
+                };
+                (manager.change_nick(id, &new_nick), old_name, old_nick, record)
             };
             if success {
-                srv.users.read().get_user_by_uuid(id).unwrap().send_to_known(IRCMessage {
+                save_whowas(record, srv);
+                let manager = srv.users.read();
+                let user = manager.get_user_by_uuid(id).unwrap();
+                user.send_to_known(IRCMessage {
+                    tags: Vec::new(),
                     prefix: Some(old_name),
                     command: "NICK".to_string(),
                     args: vec!(new_nick),
                     suffix: None,
                 });
+                srv.modules_handler.read().notify_nick_change(&*user, old_nick.as_slice(), srv);
             } else {
                 srv.users.read().get_user_by_uuid(id).unwrap().push_numreply(
                     numericreply::ERR_NICKNAMEINUSE(new_nick.as_slice()),
@@ -98,14 +215,134 @@ pub fn recycle_user(id: &Uuid, action: RecyclingAction, srv: &ServerData) {
                 );
             }
         },
+        ChangeHost(target, username, hostname) => {
+            let old_name = match srv.users.read().get_user_by_uuid(&target) {
+                Some(user) => user.get_fullname(),
+                None => return
+            };
+            srv.users.write().change_host(&target, username.clone(), hostname.clone());
+            let manager = srv.users.read();
+            let user = manager.get_user_by_uuid(&target).unwrap();
+            let msg = IRCMessage {
+                tags: Vec::new(),
+                prefix: Some(old_name),
+                command: "CHGHOST".to_string(),
+                args: vec!(username, hostname),
+                suffix: None
+            };
+            // send_to_known only reaches the user itself through its channels
+            if user.channels.read().is_empty() && user.has_capability("chghost") {
+                user.push_message(msg.clone());
+            }
+            user.send_to_known_with_cap(msg, "chghost");
+        },
+        Kill(victims, reason) => {
+            let killer = srv.users.read().get_user_by_uuid(id).unwrap().get_fullname();
+            for victim in victims.iter() {
+                kill_user(victim, srv, reason.as_slice());
+            }
+            srv.logger.log(Info,
+                format!("{} users were disconnected by {} ({}).", victims.len(), killer, reason));
+        },
+        LoadModule(name) => {
+            let cfg = srv.settings.read().modules.get(&name).map(|c| c.clone());
+            let result = match cfg {
+                Some(cfg) => srv.modules_handler.write().open_module(name.as_slice(), &cfg, &srv.logger),
+                None => Err(format!("No [module.{}] section in the configuration.", name))
+            };
+            report_module_operation(id, srv, "loaded", name.as_slice(), result);
+        },
+        UnloadModule(name) => {
+            // its tasks must not run while it is closed
+            srv.timers.suspend_owner(name.as_slice());
+            let result = srv.modules_handler.write().unload_module(name.as_slice(), srv);
+            srv.timers.resume_owner(name.as_slice());
+            report_module_operation(id, srv, "unloaded", name.as_slice(), result);
+        },
+        ReloadModule(name) => {
+            let cfg = srv.settings.read().modules.get(&name).map(|c| c.clone());
+            let result = match cfg {
+                Some(cfg) => {
+                    srv.timers.suspend_owner(name.as_slice());
+                    let result = srv.modules_handler.write().reload_module(name.as_slice(), &cfg, srv);
+                    srv.timers.resume_owner(name.as_slice());
+                    result
+                },
+                None => Err(format!("No [module.{}] section in the configuration.", name))
+            };
+            report_module_operation(id, srv, "reloaded", name.as_slice(), result);
+        },
         _ => {}
     }
 }
 
+/// Tells the operator who requested an operation on a module how it went.
+fn report_module_operation(id: &Uuid, srv: &ServerData, op: &str, name: &str, result: Result<(), String>) {
+    let text = match result {
+        Ok(()) => {
+            srv.logger.log(Info, format!("Module {} was {} by {}.", name, op,
+                srv.users.read().get_user_by_uuid(id).unwrap().get_fullname()));
+            format!("*** Module {} {}.", name, op)
+        },
+        Err(e) => format!("*** Module {} could not be {}: {}", name, op, e)
+    };
+    let manager = srv.users.read();
+    let user = manager.get_user_by_uuid(id).unwrap();
+    user.push_message(
+        IRCMessage {
+            tags: Vec::new(),
+            prefix: Some(srv.settings.read().name.clone()),
+            command: "NOTICE".to_string(),
+            args: vec!(user.nickname.clone()),
+            suffix: Some(text)
+        }
+    );
+}
+
+/// Disconnects a user for given reason, notifying it and the users knowing it.
+/// Does nothing if the user does not exist anymore.
+#[experimental]
+pub fn kill_user(id: &Uuid, srv: &ServerData, reason: &str) {
+    let manager = srv.users.read();
+    if let Some(victim) = manager.get_user_by_uuid(id) {
+        if victim.is_zombie() { return; }
+        let mut pv = victim.private_handler();
+        close_connection(&*victim, &mut pv, reason);
+    }
+}
+
 /// Recycles a disconnected user.
 #[experimental]
 pub fn destroy_user(id: &Uuid, srv: &ServerData) {
     srv.logger.log(Debug, format!("Recycling user {}", id));
+    let record = match srv.users.read().get_user_by_uuid(id) {
+        Some(user) => {
+            srv.modules_handler.read().notify_destroy(&*user, id, srv);
+            Some(whowas_entry(&*user, srv))
+        },
+        None => None
+    };
     srv.users.write().del_user(id);
-    // TODO save historyfor WHOWAS
+    if let Some(r) = record { save_whowas(r, srv); }
+}
+
+/// Creates the WHOWAS record of a user leaving its current nickname.
+fn whowas_entry(user: &UserData, srv: &ServerData) -> WhowasEntry {
+    WhowasEntry {
+        nickname: user.nickname.clone(),
+        username: user.username.clone(),
+        hostname: user.hostname.clone(),
+        realname: user.realname.clone(),
+        server: srv.settings.read().name.clone(),
+        signoff: get_time().sec
+    }
+}
+
+/// Adds a record to the WHOWAS history, within the configured bounds.
+fn save_whowas(record: WhowasEntry, srv: &ServerData) {
+    let (per_nick, nicks) = {
+        let settings = srv.settings.read();
+        (settings.whowas_per_nick, settings.whowas_nicks)
+    };
+    srv.whowas.write().record(record, per_nick, nicks);
 }